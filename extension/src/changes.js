@@ -0,0 +1,74 @@
+const fetch = require("./fetch");
+
+// Reads the server-sent events of the backend and passes each change to `onChange`.
+// The connection is opened again after it dropped, followed by a resync since changes may
+// have been missed in the meantime.
+function subscribeToChanges(baseEndpoint, onChange) {
+    const changesEndpoint = baseEndpoint + 'changes';
+    let reconnecting = false;
+
+    const reconnect = () => {
+        if (reconnecting) {
+            return;
+        }
+        reconnecting = true;
+        setTimeout(() => {
+            reconnecting = false;
+            connect(true);
+        }, 5000);
+    };
+
+    const connect = async (resync) => {
+        let response;
+        try {
+            response = await fetch(changesEndpoint, {
+                headers: { Accept: "text/event-stream" },
+            });
+        } catch (error) {
+            reconnect();
+            return;
+        }
+        if (!response.ok) {
+            reconnect();
+            return;
+        }
+        if (resync) {
+            onChange({ kind: "resync" });
+        }
+
+        let buffer = "";
+        response.body.on("data", (chunk) => {
+            buffer += chunk.toString();
+            // Events are separated by an empty line, keep-alive comments start with a colon
+            let end;
+            while ((end = buffer.indexOf("\n\n")) >= 0) {
+                const event = buffer.slice(0, end);
+                buffer = buffer.slice(end + 2);
+                const data = event
+                    .split("\n")
+                    .filter((line) => line.startsWith("data:"))
+                    .map((line) => line.slice(5).trim())
+                    .join("\n");
+                if (data) {
+                    try {
+                        onChange(JSON.parse(data));
+                    } catch (error) {
+                        console.error("error reading change:", error);
+                    }
+                }
+            }
+        });
+        response.body.on("end", reconnect);
+        response.body.on("error", reconnect);
+    };
+
+    connect(false);
+}
+
+// File names of changes are relative to the repository, editors know the absolute path
+function isChangeOf(change, fileName) {
+    return change.file_name !== undefined &&
+        (fileName === change.file_name || fileName.endsWith("/" + change.file_name));
+}
+
+module.exports = { subscribeToChanges, isChangeOf };
@@ -1,10 +1,13 @@
 const vscode = require("vscode");
-const fetch = require("node-fetch");
+const fetch = require("./fetch");
+const { isChangeOf } = require("./changes");
 
 const newNoteComment = (id, body, mode, author, parent, contextValue) => {
     return {
         id,
         body,
+        // Restored when an edit is cancelled
+        savedBody: body,
         mode,
         author,
         parent,
@@ -36,6 +39,11 @@ async function commentHandler(context, endpoint) {
         const response = await fetch(
             endpoint + "?" + new URLSearchParams({ file_name: fileName })
         );
+        if (!response.ok) {
+            const error = await response.json();
+            vscode.window.showErrorMessage("Auditor: " + error.message);
+            return {};
+        }
         const review_state = await response.json();
         return review_state;
     }
@@ -55,6 +63,11 @@ async function commentHandler(context, endpoint) {
                     author
                 }),
             });
+            if (!response.ok) {
+                const error = await response.json();
+                vscode.window.showErrorMessage("Auditor: " + error.message);
+                return;
+            }
             const comment_id = await response.json();
             return comment_id;
         } catch (error) {
@@ -62,9 +75,89 @@ async function commentHandler(context, endpoint) {
         }
     }
 
+    async function replyToCommentInBackend(fileName, lineNumber, threadId, body, author) {
+        try {
+            const response = await fetch(endpoint + "/reply", {
+                headers: {
+                    Accept: "application/json",
+                    "Content-Type": "application/json",
+                },
+                method: "POST",
+                body: JSON.stringify({
+                    file_name: fileName,
+                    line_number: lineNumber,
+                    thread_id: threadId,
+                    body,
+                    author
+                }),
+            });
+            if (!response.ok) {
+                const error = await response.json();
+                vscode.window.showErrorMessage("Auditor: " + error.message);
+                return;
+            }
+            const reply_id = await response.json();
+            return reply_id;
+        } catch (error) {
+            console.error("error replying to comment:", error);
+        }
+    }
+
+    // `action` is either "resolve" or "reopen", returns whether the thread was updated
+    async function updateThreadInBackend(action, fileName, lineNumber, threadId, state, author) {
+        try {
+            const response = await fetch(endpoint + "/" + action, {
+                headers: {
+                    Accept: "application/json",
+                    "Content-Type": "application/json",
+                },
+                method: "POST",
+                body: JSON.stringify({
+                    file_name: fileName,
+                    line_number: lineNumber,
+                    thread_id: threadId,
+                    state,
+                    author
+                }),
+            });
+            if (!response.ok) {
+                const error = await response.json();
+                vscode.window.showErrorMessage("Auditor: " + error.message);
+            }
+            return response.ok;
+        } catch (error) {
+            console.error("error updating comment thread:", error);
+            return false;
+        }
+    }
+
+    // Returns the status code, 403 means the comment was written by someone else
+    async function updateCommentInBackend(fileName, lineNumber, commentId, body, author, force) {
+        try {
+            const response = await fetch(endpoint + "/" + commentId, {
+                headers: {
+                    Accept: "application/json",
+                    "Content-Type": "application/json",
+                },
+                method: "PUT",
+                body: JSON.stringify({
+                    file_name: fileName,
+                    line_number: lineNumber,
+                    body,
+                    author,
+                    force
+                }),
+            });
+            return response.status;
+        } catch (error) {
+            console.error("error updating comment:", error);
+        }
+    }
+
+    // Returns whether the comment was deleted, only leads may delete the comments of others
     async function deleteCommentInBackend(fileName, lineNumber, commentId) {
         try {
-            await fetch(endpoint, {
+            const response = await fetch(endpoint, {
                 headers: {
                     Accept: "application/json",
                     "Content-Type": "application/json",
@@ -76,32 +169,52 @@ async function commentHandler(context, endpoint) {
                     comment_id: commentId,
                 }),
             });
+            if (!response.ok) {
+                const error = await response.json();
+                vscode.window.showErrorMessage("Auditor: " + error.message);
+            }
+            return response.ok;
         } catch (error) {
             console.error("error updating review state:", error);
+            return false;
         }
     }
 
     // ------------------ Comment handlers
 
+    function setThreadState(thread, state) {
+        thread.contextValue = state === "Open" ? "open" : "resolved";
+        thread.label = state === "WontFix" ? "Won't fix" : state;
+        thread.state = state === "Open" ? vscode.CommentThreadState.Unresolved : vscode.CommentThreadState.Resolved;
+    }
+
+    // Every comment on a line starts its own thread, replies are shown under it
+    // Threads shown in each file, replaced when the comments of the file are loaded again
+    const threadsOfFile = {};
+
     function showComments(all_comments, uri) {
+        for (const thread of threadsOfFile[uri.path] || []) {
+            thread.dispose();
+        }
+        threadsOfFile[uri.path] = [];
         for (const [line, comments] of Object.entries(all_comments)) {
             const lineNum = parseInt(line);
-            let shownComments = [];
             const range = new vscode.Range(new vscode.Position(lineNum, 0), new vscode.Position(lineNum, 0));
-            let thread = commentController.createCommentThread(uri, range, []);
-            for (let i = 0; i < comments.length; i++) {
-                const comment = comments[i];
-                shownComments.push(newNoteComment(
-                    comment.id,
-                    comment.body,
+            for (const comment of comments) {
+                let thread = commentController.createCommentThread(uri, range, []);
+                threadsOfFile[uri.path].push(thread);
+                const shownComments = [comment, ...(comment.replies || [])].map((cmt) => newNoteComment(
+                    cmt.id,
+                    cmt.body,
                     vscode.CommentMode.Preview,
-                    { name: comment.author },
+                    { name: cmt.author },
                     thread,
                     "canDelete"
                 ));
+                thread.comments = shownComments;
+                setThreadState(thread, comment.state || "Open");
+                thread.collapsibleState = vscode.CommentThreadCollapsibleState.Collapsed;
             }
-            thread.comments = shownComments;
-            thread.collapsibleState = vscode.CommentThreadCollapsibleState.Collapsed;
         }
     }
 
@@ -110,7 +223,17 @@ async function commentHandler(context, endpoint) {
         const author = vscode.workspace.getConfiguration().get('auditor.commenterName');
         let fileName = thread.uri.path;
         let lineNumber = reply.thread.range.start.line;
-        let comment_id = await createCommentInBackend(fileName, lineNumber, reply.text, author);
+        let comment_id;
+        if (thread.comments.length === 0) {
+            comment_id = await createCommentInBackend(fileName, lineNumber, reply.text, author);
+            setThreadState(thread, "Open");
+            (threadsOfFile[fileName] = threadsOfFile[fileName] || []).push(thread);
+        } else {
+            comment_id = await replyToCommentInBackend(fileName, lineNumber, thread.comments[0].id, reply.text, author);
+        }
+        if (comment_id === undefined) {
+            return;
+        }
         const newComment = newNoteComment(
             comment_id,
             reply.text,
@@ -122,28 +245,52 @@ async function commentHandler(context, endpoint) {
         thread.comments = [...thread.comments, newComment];
     }
 
-    // function editComment(comment) {
-    //     if (!comment.parent) {
-    //         return;
-    //     }
+    function editComment(comment) {
+        if (!comment.parent) {
+            return;
+        }
 
-    //     comment.parent.comments = comment.parent.comments.map((cmt) => {
-    //         if (cmt.id === comment.id) {
-    //             cmt.mode = vscode.CommentMode.Editing;
-    //         }
+        comment.parent.comments = comment.parent.comments.map((cmt) => {
+            if (cmt.id === comment.id) {
+                cmt.mode = vscode.CommentMode.Editing;
+            }
 
-    //         return cmt;
-    //     });
-    // }
+            return cmt;
+        });
+    }
 
-    function saveComment(comment) {
-        if (!comment.parent) {
+    async function saveComment(comment) {
+        const thread = comment.parent;
+        if (!thread) {
+            return;
+        }
+        const author = vscode.workspace.getConfiguration().get('auditor.commenterName');
+        let fileName = thread.uri.path;
+        let lineNumber = thread.range.start.line;
+        const body = typeof comment.body === "string" ? comment.body : comment.body.value;
+        let status = await updateCommentInBackend(fileName, lineNumber, comment.id, body, author, false);
+        if (status === 403) {
+            const answer = await vscode.window.showWarningMessage(
+                `This comment was written by ${comment.author.name}, edit it anyway?`,
+                "Edit anyway"
+            );
+            if (answer !== "Edit anyway") {
+                return;
+            }
+            status = await updateCommentInBackend(fileName, lineNumber, comment.id, body, author, true);
+        }
+        if (status === 403) {
+            vscode.window.showErrorMessage("Only leads can edit the comments of others");
+            return;
+        }
+        if (status !== 201) {
+            vscode.window.showErrorMessage("Failed to save the comment");
             return;
         }
 
-        comment.parent.comments = comment.parent.comments.map((cmt) => {
+        thread.comments = thread.comments.map((cmt) => {
             if (cmt.id === comment.id) {
-                cmt.body = cmt.body;
+                cmt.savedBody = cmt.body;
                 cmt.mode = vscode.CommentMode.Preview;
             }
 
@@ -158,7 +305,9 @@ async function commentHandler(context, endpoint) {
         }
         let fileName = thread.uri.path;
         let lineNumber = comment.parent.range.start.line;
-        await deleteCommentInBackend(fileName, lineNumber, comment.id);
+        if (!await deleteCommentInBackend(fileName, lineNumber, comment.id)) {
+            return;
+        }
         thread.comments = thread.comments.filter((cmt) => cmt.id !== comment.id);
 
         if (thread.comments.length === 0) {
@@ -166,6 +315,18 @@ async function commentHandler(context, endpoint) {
         }
     }
 
+    async function updateThread(thread, action, state) {
+        if (thread.comments.length === 0) {
+            return;
+        }
+        const author = vscode.workspace.getConfiguration().get('auditor.commenterName');
+        let fileName = thread.uri.path;
+        let lineNumber = thread.range.start.line;
+        if (await updateThreadInBackend(action, fileName, lineNumber, thread.comments[0].id, state, author)) {
+            setThreadState(thread, state);
+        }
+    }
+
     function cancelSaveComment(comment) {
         if (!comment.parent) {
             return;
@@ -173,7 +334,7 @@ async function commentHandler(context, endpoint) {
 
         comment.parent.comments = comment.parent.comments.map((cmt) => {
             if (cmt.id === comment.id) {
-                cmt.body = cmt.body;
+                cmt.body = cmt.savedBody;
                 cmt.mode = vscode.CommentMode.Preview;
             }
 
@@ -199,6 +360,24 @@ async function commentHandler(context, endpoint) {
         vscode.commands.registerCommand("auditor.deleteNoteComment", deleteComment)
     );
 
+    context.subscriptions.push(
+        vscode.commands.registerCommand("auditor.resolveNote", (thread) => {
+            updateThread(thread, "resolve", "Resolved");
+        })
+    );
+
+    context.subscriptions.push(
+        vscode.commands.registerCommand("auditor.wontFixNote", (thread) => {
+            updateThread(thread, "resolve", "WontFix");
+        })
+    );
+
+    context.subscriptions.push(
+        vscode.commands.registerCommand("auditor.reopenNote", (thread) => {
+            updateThread(thread, "reopen", "Open");
+        })
+    );
+
     context.subscriptions.push(
         vscode.commands.registerCommand("auditor.deleteNote", (thread) => {
             thread.dispose();
@@ -213,10 +392,9 @@ async function commentHandler(context, endpoint) {
         vscode.commands.registerCommand("auditor.saveNote", saveComment)
     );
 
-    // TODO: implement later
-    // context.subscriptions.push(
-    //     vscode.commands.registerCommand("auditor.editNote", editComment)
-    // );
+    context.subscriptions.push(
+        vscode.commands.registerCommand("auditor.editNote", editComment)
+    );
 
     context.subscriptions.push(
         vscode.commands.registerCommand("auditor.dispose", () => {
@@ -251,6 +429,29 @@ async function commentHandler(context, endpoint) {
             }
         }
     });
+
+    // Comments changed by someone else are shown in the files that are open, unless a comment
+    // of the file is being edited
+    const onChange = async (change) => {
+        for (const document of vscode.workspace.textDocuments) {
+            const fileName = document.fileName;
+            if (!(fileName in initialized)) {
+                continue;
+            }
+            if (change.kind !== "resync" && !(change.kind === "comments" && isChangeOf(change, fileName))) {
+                continue;
+            }
+            const editing = (threadsOfFile[document.uri.path] || []).some((thread) =>
+                thread.comments.some((comment) => comment.mode === vscode.CommentMode.Editing)
+            );
+            if (!editing) {
+                const comments = await getCommentsFromBackend(fileName);
+                showComments(comments, document.uri);
+            }
+        }
+    };
+
+    return { onChange };
 }
 
 module.exports = commentHandler;
\ No newline at end of file
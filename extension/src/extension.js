@@ -1,5 +1,6 @@
 const commentHandler = require("./comments");
 const linereviewHandler = require("./linereviews");
+const { subscribeToChanges } = require("./changes");
 
 /**
  * @param {vscode.ExtensionContext} context
@@ -8,8 +9,14 @@ function activate(context) {
   // Backend endpoint
   const endpoint = 'http://localhost:3000/';
 
-  commentHandler(context, endpoint);
-  linereviewHandler(endpoint);
+  const lineReviews = linereviewHandler(endpoint);
+  commentHandler(context, endpoint).then((comments) => {
+    // Changes made by other auditors are pushed by the backend
+    subscribeToChanges(endpoint, (change) => {
+      lineReviews.onChange(change);
+      comments.onChange(change);
+    });
+  });
 }
 
 // This method is called when your extension is deactivated
@@ -0,0 +1,17 @@
+const vscode = require("vscode");
+const nodeFetch = require("node-fetch");
+
+// Sends the API token of the user along with every request, the backend needs it when
+// authentication is enabled and attributes the changes to its user
+function fetch(url, options = {}) {
+    const token = vscode.workspace.getConfiguration().get('auditor.apiToken');
+    if (!token) {
+        return nodeFetch(url, options);
+    }
+    return nodeFetch(url, {
+        ...options,
+        headers: { ...(options.headers || {}), Authorization: "Bearer " + token },
+    });
+}
+
+module.exports = fetch;
@@ -1,6 +1,7 @@
 
 const vscode = require("vscode");
-const fetch = require("node-fetch");
+const fetch = require("./fetch");
+const { isChangeOf } = require("./changes");
 
 function linereviewHandler(baseEndpoint) {
     const auditingFiletypes = vscode.workspace.getConfiguration().get('auditor.auditingFiletypes');
@@ -37,10 +38,36 @@ function linereviewHandler(baseEndpoint) {
             },
         });
 
+    // Failed requests carry a JSON body with a code, a message and details
+    const showBackendError = async (fileName, response) => {
+        let error;
+        try {
+            error = await response.json();
+        } catch (_) {
+            error = { code: "unknown", message: response.statusText };
+        }
+        if (error.code === "old_commit") {
+            const answer = await vscode.window.showWarningMessage(
+                "The reviews of this file were made at commit " + error.details.commit +
+                ", which is older than HEAD. Transform them to HEAD?",
+                "Transform"
+            );
+            if (answer === "Transform") {
+                await transformReviewState(fileName);
+            }
+        } else {
+            vscode.window.showErrorMessage("Auditor: " + error.message);
+        }
+    };
+
     const getReviewState = async (fileName) => {
         const response = await fetch(
             reviewEndpoint + "?" + new URLSearchParams({ file_name: fileName })
         );
+        if (!response.ok) {
+            await showBackendError(fileName, response);
+            return null;
+        }
         const review_state = await response.json();
         return review_state;
     };
@@ -52,8 +79,9 @@ function linereviewHandler(baseEndpoint) {
         reviewState,
         totalLines,
     ) => {
+        const reviewer = vscode.workspace.getConfiguration().get('auditor.commenterName');
         try {
-            await fetch(reviewEndpoint, {
+            const response = await fetch(reviewEndpoint, {
                 headers: {
                     Accept: "application/json",
                     "Content-Type": "application/json",
@@ -65,8 +93,13 @@ function linereviewHandler(baseEndpoint) {
                     end_line: endLine,
                     review_state: reviewState,
                     total_lines: totalLines,
+                    reviewer: reviewer,
                 }),
             });
+            if (!response.ok) {
+                await showBackendError(fileName, response);
+                return;
+            }
             const state = await getReviewState(fileName);
             showReviewState(state);
         } catch (error) {
@@ -76,7 +109,7 @@ function linereviewHandler(baseEndpoint) {
 
     const transformReviewState = async (fileName) => {
         try {
-            await fetch(transformReviewEndpoint, {
+            const response = await fetch(transformReviewEndpoint, {
                 headers: {
                     Accept: "application/json",
                     "Content-Type": "application/json",
@@ -86,6 +119,10 @@ function linereviewHandler(baseEndpoint) {
                     file_name: fileName,
                 }),
             });
+            if (!response.ok) {
+                await showBackendError(fileName, response);
+                return;
+            }
             const state = await getReviewState(fileName);
             showReviewState(state);
         } catch (error) {
@@ -93,7 +130,11 @@ function linereviewHandler(baseEndpoint) {
         }
     };
 
-    const showReviewState = ({ reviewed, modified, ignored }) => {
+    const showReviewState = (state) => {
+        if (!state) {
+            return;
+        }
+        let { reviewed, modified, ignored } = state;
         let activeEditor = vscode.window.activeTextEditor;
         if (!auditingFiletypes.includes(vscode.window.activeTextEditor.document.languageId)) {
             return
@@ -218,6 +259,21 @@ function linereviewHandler(baseEndpoint) {
             });
         }
     }
+
+    // Reviews changed by someone else are shown right away
+    const onChange = async (change) => {
+        const activeEditor = vscode.window.activeTextEditor;
+        if (!activeEditor || !auditingFiletypes.includes(activeEditor.document.languageId)) {
+            return;
+        }
+        const fileName = activeEditor.document.fileName;
+        if (change.kind === "resync" || (change.kind === "reviews" && isChangeOf(change, fileName))) {
+            const state = await getReviewState(fileName);
+            showReviewState(state);
+        }
+    };
+
+    return { onChange };
 }
 
 module.exports = linereviewHandler;
\ No newline at end of file
@@ -0,0 +1,328 @@
+use crate::errors::AuditorError;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// The body of every failed request, so that clients can tell the cause apart from the status.
+///
+/// `code` is stable and meant for programs, `message` is meant for people and `details` holds
+/// the values the error is about, such as the commit of an `old_commit` error.
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn missing_parameter(name: &str) -> Self {
+        Self::bad_request(
+            "missing_parameter",
+            format!("Query parameter {name} is missing"),
+        )
+        .with_details(json!({ "parameter": name }))
+    }
+
+    pub fn with_details(self, details: Value) -> Self {
+        Self {
+            details: Some(details),
+            ..self
+        }
+    }
+}
+
+impl From<&AuditorError> for ApiError {
+    fn from(err: &AuditorError) -> Self {
+        use AuditorError::*;
+        let (status, code, details) = match err {
+            MissingConfig(item) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "missing_config",
+                Some(json!({ "item": item })),
+            ),
+            InvalidConfig(item) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_config",
+                Some(json!({ "item": item })),
+            ),
+            UnknownCommentId(id) => (
+                StatusCode::NOT_FOUND,
+                "unknown_comment",
+                Some(json!({ "comment_id": id })),
+            ),
+            NotCommentAuthor(author) => (
+                StatusCode::FORBIDDEN,
+                "not_comment_author",
+                Some(json!({ "author": author })),
+            ),
+            UnknownFindingId(id) => (
+                StatusCode::NOT_FOUND,
+                "unknown_finding",
+                Some(json!({ "finding_id": id })),
+            ),
+            InvalidFinding(reason) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_finding",
+                Some(json!({ "reason": reason })),
+            ),
+            Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized", None),
+            UnknownUser(name) => (
+                StatusCode::NOT_FOUND,
+                "unknown_user",
+                Some(json!({ "user": name })),
+            ),
+            InvalidUserName(name) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_user_name",
+                Some(json!({ "user": name })),
+            ),
+            UserExists(name) => (
+                StatusCode::CONFLICT,
+                "user_exists",
+                Some(json!({ "user": name })),
+            ),
+            UnknownTokenId(id) => (
+                StatusCode::NOT_FOUND,
+                "unknown_token",
+                Some(json!({ "token_id": id })),
+            ),
+            InvalidRole(role) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_role",
+                Some(json!({ "role": role })),
+            ),
+            Forbidden(permission) => (
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                Some(json!({ "permission": permission })),
+            ),
+            UnknownSessionId(id) => (
+                StatusCode::NOT_FOUND,
+                "unknown_session",
+                Some(json!({ "session_id": id })),
+            ),
+            UnknownEventId(id) => (
+                StatusCode::NOT_FOUND,
+                "unknown_event",
+                Some(json!({ "event_id": id })),
+            ),
+            StaleEvent(id) => (
+                StatusCode::CONFLICT,
+                "stale_event",
+                Some(json!({ "event_id": id })),
+            ),
+            // The reviews have to be transformed to HEAD before they can be read or changed
+            OldCommitError(commit) => (
+                StatusCode::CONFLICT,
+                "old_commit",
+                Some(json!({ "commit": commit })),
+            ),
+            ShouldUpdateToLatest(commit) => (
+                StatusCode::CONFLICT,
+                "should_update_to_latest",
+                Some(json!({ "commit": commit })),
+            ),
+            // Only committed lines can be reviewed, the file has to be committed first
+            UncommittedLines(file_name) => (
+                StatusCode::CONFLICT,
+                "uncommitted_lines",
+                Some(json!({ "file_name": file_name })),
+            ),
+            UnsavedChanges(file_name) => (
+                StatusCode::CONFLICT,
+                "unsaved_changes",
+                Some(json!({ "file_name": file_name })),
+            ),
+            UnknownCommit(commit) => (
+                StatusCode::NOT_FOUND,
+                "unknown_commit",
+                Some(json!({ "commit": commit })),
+            ),
+            UnknownFileName(file_name) => (
+                StatusCode::NOT_FOUND,
+                "unknown_file",
+                Some(json!({ "file_name": file_name })),
+            ),
+            InvalidAbsolutePath(path) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_path",
+                Some(json!({ "path": path })),
+            ),
+            UnknownLinenumberInFile(line_number, file_name) => (
+                StatusCode::NOT_FOUND,
+                "unknown_line",
+                Some(json!({ "line_number": line_number, "file_name": file_name })),
+            ),
+            UnknownReportFormat(format) => (
+                StatusCode::BAD_REQUEST,
+                "unknown_report_format",
+                Some(json!({ "format": format })),
+            ),
+            InvalidPriority(priority) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_priority",
+                Some(json!({ "priority": priority })),
+            ),
+            InvalidReviewState(state) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_review_state",
+                Some(json!({ "review_state": state })),
+            ),
+            MissingAuthor => (StatusCode::BAD_REQUEST, "missing_author", None),
+            // Only returned by the client of another service
+            ServiceError(code, _) => (
+                StatusCode::BAD_GATEWAY,
+                "service_error",
+                Some(json!({ "code": code })),
+            ),
+            ConcurrentModification(file_name) => (
+                StatusCode::CONFLICT,
+                "concurrent_modification",
+                Some(json!({ "file_name": file_name })),
+            ),
+            UnsupportedSchemaVersion(version) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "unsupported_schema_version",
+                Some(json!({ "version": version })),
+            ),
+            UnknownBackend(backend) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "unknown_backend",
+                Some(json!({ "backend": backend })),
+            ),
+            OsStringError => (StatusCode::INTERNAL_SERVER_ERROR, "invalid_path", None),
+            RegexError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "regex_error", None),
+            // Stored data that cannot be read back is corrupt
+            SerdeError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "corrupt_data", None),
+            MissingCommit(commit) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "missing_commit",
+                Some(json!({ "commit": commit })),
+            ),
+            GitError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "git_error", None),
+            IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "io_error", None),
+            ConfigError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "invalid_config", None),
+            SqliteError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database_error", None),
+        };
+        Self {
+            status,
+            code,
+            message: err.to_string(),
+            details,
+        }
+    }
+}
+
+impl From<AuditorError> for ApiError {
+    fn from(err: AuditorError) -> Self {
+        (&err).into()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        // The message keeps the context that was added on the way up
+        let message = format!("{err:#}");
+        // Errors of the storage and of git are often returned without an `AuditorError` around
+        // them, they get the same code as if they were wrapped
+        let auditor_error = err
+            .downcast::<AuditorError>()
+            .or_else(|err| err.downcast::<serde_json::Error>().map(AuditorError::from))
+            .or_else(|err| err.downcast::<rusqlite::Error>().map(AuditorError::from))
+            .or_else(|err| err.downcast::<git2::Error>().map(AuditorError::from))
+            .or_else(|err| err.downcast::<std::io::Error>().map(AuditorError::from));
+        match auditor_error {
+            Ok(auditor_error) => Self {
+                message,
+                ..(&auditor_error).into()
+            },
+            Err(_) => Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", message),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::error!("{}: {}", self.code, self.message);
+        } else {
+            tracing::debug!("{}: {}", self.code, self.message);
+        }
+        (self.status, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::db::DB;
+
+    #[test]
+    fn test_api_error() {
+        let err: ApiError =
+            anyhow::Error::from(AuditorError::OldCommitError("abc123".to_string())).into();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            json!({
+                "code": "old_commit",
+                "message": "Commit is older than the latest commit",
+                "details": { "commit": "abc123" },
+            })
+        );
+
+        let err: ApiError = anyhow::anyhow!("unexpected").into();
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.code, "internal_error");
+
+        let response = ApiError::missing_parameter("file_name").into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_unwrapped_errors() {
+        let path = std::env::temp_dir().join(format!("auditor-errors-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        std::fs::write(
+            format!("{path}/db_a.c-{}.json", "0".repeat(64)),
+            "{\"file_name\": ",
+        )
+        .unwrap();
+        let err: ApiError = DB::new(path.clone()).unwrap_err().into();
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.code, "corrupt_data");
+        std::fs::remove_dir_all(&path).unwrap();
+
+        let err: ApiError = DB::new(path).unwrap_err().into();
+        assert_eq!(err.code, "io_error");
+        // The context is kept
+        assert!(err.message.starts_with("dir: "));
+
+        let err: ApiError = anyhow::Error::from(git2::Error::from_str("no repo")).into();
+        assert_eq!(err.code, "git_error");
+        let err: ApiError = anyhow::Error::from(rusqlite::Error::InvalidQuery).into();
+        assert_eq!(err.code, "database_error");
+    }
+}
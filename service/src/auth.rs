@@ -0,0 +1,269 @@
+use crate::{errors::AuditorError, now, storage::Storage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// What a user may do: leads manage the audit, auditors review and comment, viewers only read.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Lead,
+    #[default]
+    Auditor,
+    Viewer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Lead => "lead",
+            Role::Auditor => "auditor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Role::Lead => true,
+            Role::Auditor => matches!(permission, Permission::Read | Permission::Write),
+            Role::Viewer => permission == Permission::Read,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = AuditorError;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "lead" => Ok(Role::Lead),
+            "auditor" => Ok(Role::Auditor),
+            "viewer" => Ok(Role::Viewer),
+            _ => Err(AuditorError::InvalidRole(role.to_string())),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // Reading the file infos, reviews and comments
+    Read,
+    // Every other request, unless it needs one of the permissions below
+    Write,
+    // Setting the priority or the reviewer of a file
+    AssignFiles,
+    // Deleting or editing comments written by someone else
+    ModerateComments,
+    // Clearing reviewed lines
+    ClearReviews,
+    // Deleting findings reported by someone else
+    ModerateFindings,
+}
+
+impl Permission {
+    /// The permission a request needs to reach the route at all. Handlers check the permissions
+    /// that depend on the request body themselves.
+    pub fn of_route(method: &str, path: &str) -> Self {
+        let readable = matches!(
+            path,
+            "/" | "/me" | "/info" | "/reviews" | "/comments" | "/changes" | "/reviewers"
+        ) || path == "/sessions"
+            || path.starts_with("/sessions/");
+        if readable && matches!(method, "GET" | "HEAD") {
+            Permission::Read
+        } else {
+            Permission::Write
+        }
+    }
+}
+
+/// An account that changes are attributed to when authentication is enabled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+    // Seconds since the unix epoch
+    pub created_at: u64,
+    // Users created before roles existed are auditors
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl User {
+    /// Fails with `Forbidden` unless the role of the user allows the permission.
+    pub fn authorize(&self, permission: Permission) -> Result<()> {
+        if self.role.allows(permission) {
+            Ok(())
+        } else {
+            Err(AuditorError::Forbidden(permission).into())
+        }
+    }
+}
+
+/// An API token of a user. Only the hash of the token is stored, the token itself is shown once
+/// when it is created.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ApiToken {
+    pub id: String,
+    pub user: String,
+    pub token_hash: String,
+    pub created_at: u64,
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn create_user(name: &str, role: Role, db: &mut dyn Storage) -> Result<User> {
+    if name.trim().is_empty() {
+        return Err(AuditorError::InvalidUserName(name.to_string()).into());
+    }
+    if db.get_users()?.iter().any(|user| user.name == name) {
+        return Err(AuditorError::UserExists(name.to_string()).into());
+    }
+    let user = User {
+        name: name.to_string(),
+        created_at: now(),
+        role,
+    };
+    db.store_user(user.clone())?;
+    Ok(user)
+}
+
+pub fn set_role(name: &str, role: Role, db: &mut dyn Storage) -> Result<User> {
+    let mut user = db
+        .get_users()?
+        .into_iter()
+        .find(|user| user.name == name)
+        .ok_or(AuditorError::UnknownUser(name.to_string()))?;
+    user.role = role;
+    db.store_user(user.clone())?;
+    Ok(user)
+}
+
+/// Creates a new token for the user and returns its id and the token.
+pub fn create_token(user_name: &str, db: &mut dyn Storage) -> Result<(String, String)> {
+    if !db.get_users()?.iter().any(|user| user.name == user_name) {
+        return Err(AuditorError::UnknownUser(user_name.to_string()).into());
+    }
+    // Two random uuids give 244 random bits
+    let token = format!("aud_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let id = Uuid::new_v4().to_string();
+    db.store_token(ApiToken {
+        id: id.clone(),
+        user: user_name.to_string(),
+        token_hash: hash_token(&token),
+        created_at: now(),
+    })?;
+    Ok((id, token))
+}
+
+/// Returns the user the token belongs to, or an `Unauthorized` error for unknown tokens.
+pub fn authenticate(token: &str, db: &dyn Storage) -> Result<User> {
+    db.user_of_token(&hash_token(token))?
+        .ok_or(AuditorError::Unauthorized.into())
+}
+
+/// Reads the token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.split_once(' ')?;
+    (scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty()).then(|| token.trim())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{db::DB, sqlite::SqliteDB};
+
+    fn check_tokens(db: &mut dyn Storage) {
+        create_user("alice", Role::Auditor, db).unwrap();
+        assert!(create_user("alice", Role::Lead, db).is_err());
+        assert!(create_token("bob", db).is_err());
+
+        let (id, token) = create_token("alice", db).unwrap();
+        let (_, other_token) = create_token("alice", db).unwrap();
+        assert_ne!(token, other_token);
+        assert_eq!(authenticate(&token, db).unwrap().name, "alice");
+        assert!(authenticate("aud_unknown", db).is_err());
+        // Only the hash is stored
+        assert!(db
+            .get_tokens()
+            .unwrap()
+            .iter()
+            .all(|t| t.token_hash != token));
+
+        db.delete_token(&id).unwrap();
+        assert!(db.delete_token(&id).is_err());
+        assert!(authenticate(&token, db).is_err());
+        assert_eq!(authenticate(&other_token, db).unwrap().name, "alice");
+
+        // The role is read along with the user of the token
+        assert!(set_role("bob", Role::Lead, db).is_err());
+        set_role("alice", Role::Viewer, db).unwrap();
+        assert_eq!(authenticate(&other_token, db).unwrap().role, Role::Viewer);
+        assert_eq!(db.get_users().unwrap()[0].role, Role::Viewer);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let path = std::env::temp_dir().join(format!("auditor-auth-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut db = DB::new(path.clone()).unwrap();
+        check_tokens(&mut db);
+        db.save().unwrap();
+        // Accounts of the JSON backend are kept once saved
+        let db = DB::new_without_files(path.clone()).unwrap();
+        assert_eq!(db.get_users().unwrap()[0].name, "alice");
+
+        check_tokens(&mut SqliteDB::new(&path).unwrap());
+        std::fs::remove_dir_all(path).unwrap();
+
+        assert_eq!(bearer_token("Bearer aud_123"), Some("aud_123"));
+        assert_eq!(bearer_token("bearer  aud_123 "), Some("aud_123"));
+        assert_eq!(bearer_token("Basic dXNlcg=="), None);
+        assert_eq!(bearer_token("Bearer "), None);
+    }
+
+    #[test]
+    fn test_permissions() {
+        let user = |role| User {
+            name: "alice".to_string(),
+            created_at: 0,
+            role,
+        };
+        assert!(user(Role::Lead).authorize(Permission::AssignFiles).is_ok());
+        assert!(user(Role::Auditor).authorize(Permission::Write).is_ok());
+        assert!(user(Role::Auditor)
+            .authorize(Permission::ClearReviews)
+            .is_err());
+        assert!(user(Role::Viewer).authorize(Permission::Read).is_ok());
+        assert!(user(Role::Viewer).authorize(Permission::Write).is_err());
+
+        assert_eq!(Permission::of_route("GET", "/info"), Permission::Read);
+        assert_eq!(Permission::of_route("GET", "/comments"), Permission::Read);
+        assert_eq!(Permission::of_route("POST", "/reviews"), Permission::Write);
+        assert_eq!(Permission::of_route("GET", "/findings"), Permission::Write);
+        assert_eq!(Permission::of_route("GET", "/history"), Permission::Write);
+        assert_eq!(
+            Permission::of_route("GET", "/sessions/s1"),
+            Permission::Read
+        );
+        assert_eq!(Permission::of_route("POST", "/sessions"), Permission::Write);
+        assert_eq!(Permission::of_route("GET", "/changes"), Permission::Read);
+        assert_eq!(Permission::of_route("GET", "/reviewers"), Permission::Read);
+        assert!(user(Role::Auditor)
+            .authorize(Permission::ModerateFindings)
+            .is_err());
+
+        assert_eq!("lead".parse::<Role>().unwrap(), Role::Lead);
+        assert!("admin".parse::<Role>().is_err());
+        // Accounts stored before roles existed
+        let user: User = serde_json::from_str(r#"{"name":"bob","created_at":1}"#).unwrap();
+        assert_eq!(user.role, Role::Auditor);
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use auditor::{
+    cli::{
+        count_lines, format_comments, format_status, parse_lines, resolve_file, Backend,
+        HttpBackend, LocalBackend,
+    },
+    config::ConfigBuilder,
+    Metadata, Priority, UpdateMetadataRequest, UpdateReviewState,
+};
+use std::{env, fs, path::Path, process};
+
+const USAGE: &str = "usage: auditor-cli [--config <file> | --server <url>] [--token <token>] [--author <name>] <command>
+
+commands:
+    status [file]                          coverage of every file in scope, or of one file
+    mark <file> <lines> <state>            marks lines like 5 or 5-10 as reviewed, modified, ignored or cleared
+    transform <file>                       moves the reviews and comments of the file to HEAD
+    comments list <file>
+    comments add <file> <line> <body>
+    comments delete <file> <line> <comment id>
+    metadata set <file> [--priority <priority>] [--reviewer <name>] [--note <note>]
+
+Without --server the db of the config is changed directly. The server and the token can also be
+set with AUDITOR_SERVER and AUDITOR_API_TOKEN, the author with AUDITOR_AUTHOR.";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2)
+}
+
+/// Removes `--name <value>` from the arguments and returns the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    if index + 1 >= args.len() {
+        usage();
+    }
+    args.remove(index);
+    Some(args.remove(index))
+}
+
+/// The file name relative to the repository and its number of lines, read from disk.
+fn file_of(path: &str, repository_path: Option<&str>) -> Result<(String, Option<usize>)> {
+    let (file_name, total_lines) = resolve_file(path)?;
+    if total_lines.is_some() {
+        return Ok((file_name, total_lines));
+    }
+    // The file may be given relative to the repository instead of the working directory
+    let total_lines = repository_path
+        .and_then(|repository_path| fs::read_to_string(Path::new(repository_path).join(path)).ok())
+        .map(|content| count_lines(&content));
+    Ok((file_name, total_lines))
+}
+
+fn run(mut args: Vec<String>) -> Result<()> {
+    let server = take_option(&mut args, "--server").or_else(|| env::var("AUDITOR_SERVER").ok());
+    let token = take_option(&mut args, "--token").or_else(|| env::var("AUDITOR_API_TOKEN").ok());
+    let config_path =
+        take_option(&mut args, "--config").unwrap_or_else(|| "./config.toml".to_string());
+    let author = take_option(&mut args, "--author")
+        .or_else(|| env::var("AUDITOR_AUTHOR").ok())
+        .or_else(|| env::var("USER").ok());
+    let priority = take_option(&mut args, "--priority");
+    let reviewer = take_option(&mut args, "--reviewer");
+    let note = take_option(&mut args, "--note");
+
+    let (backend, repository_path): (Box<dyn Backend>, Option<String>) = match server {
+        Some(server) => (Box::new(HttpBackend::new(&server, token)?), None),
+        None => {
+            let config = ConfigBuilder::default()
+                .toml(&config_path)?
+                .env()?
+                .build()?;
+            let repository_path = config.repository_path.clone();
+            (Box::new(LocalBackend::new(config)), Some(repository_path))
+        }
+    };
+    let file = |index: usize| -> Result<(String, Option<usize>)> {
+        file_of(
+            args.get(index).unwrap_or_else(|| usage()),
+            repository_path.as_deref(),
+        )
+    };
+    let line = |index: usize| -> usize {
+        match args.get(index).and_then(|line| parse_lines(line)) {
+            Some(lines) => *lines.start(),
+            None => usage(),
+        }
+    };
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["status"] => print!("{}", format_status(&backend.infos(None)?)),
+        ["status", _] => {
+            let (file_name, _) = file(1)?;
+            print!("{}", format_status(&backend.infos(Some(&file_name))?));
+        }
+        ["mark", _, lines, state] => {
+            let (file_name, total_lines) = file(1)?;
+            let total_lines = total_lines
+                .or_else(|| {
+                    // Files that are not on disk keep the number of lines they were marked with
+                    let infos = backend.infos(Some(&file_name)).ok()?;
+                    Some(infos.first()?.line_reviews.total_lines)
+                })
+                .unwrap_or_else(|| usage());
+            let lines = parse_lines(lines).unwrap_or_else(|| usage());
+            backend.mark(UpdateReviewState::new(
+                file_name.clone(),
+                lines,
+                state.parse()?,
+                total_lines,
+                author,
+            ))?;
+            println!("Marked lines {} of {file_name} as {state}", args[2]);
+        }
+        ["transform", _] => {
+            let (file_name, _) = file(1)?;
+            backend.transform(&file_name, author)?;
+            println!("Transformed the reviews of {file_name} to HEAD");
+        }
+        ["comments", "list", _] => {
+            let (file_name, _) = file(2)?;
+            print!("{}", format_comments(&backend.comments(&file_name)?));
+        }
+        ["comments", "add", _, _, body @ ..] if !body.is_empty() => {
+            let (file_name, _) = file(2)?;
+            let comment_id = backend.add_comment(&file_name, line(3), body.join(" "), author)?;
+            println!("{comment_id}");
+        }
+        ["comments", "delete", _, _, comment_id] => {
+            let (file_name, _) = file(2)?;
+            backend.delete_comment(&file_name, line(3), comment_id, author)?;
+            println!("Deleted comment {comment_id}");
+        }
+        ["metadata", "set", _] if priority.is_some() || reviewer.is_some() || note.is_some() => {
+            let (file_name, _) = file(2)?;
+            // Only the given fields change
+            let current = backend
+                .infos(Some(&file_name))?
+                .into_iter()
+                .next()
+                .and_then(|info| info.metadata);
+            let metadata = Metadata {
+                priority: match priority {
+                    Some(priority) => priority.parse()?,
+                    None => current
+                        .as_ref()
+                        .map_or(Priority::Unspecified, |current| current.priority.clone()),
+                },
+                reviewer: reviewer
+                    .or_else(|| current.as_ref().map(|current| current.reviewer.clone()))
+                    .unwrap_or_default(),
+                note: note
+                    .or_else(|| current.as_ref().map(|current| current.note.clone()))
+                    .unwrap_or_default(),
+            };
+            backend.set_metadata(UpdateMetadataRequest {
+                file_name: file_name.clone(),
+                metadata,
+                author,
+            })?;
+            println!("Updated the metadata of {file_name}");
+        }
+        _ => usage(),
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run(env::args().skip(1).collect()) {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
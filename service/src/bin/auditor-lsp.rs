@@ -0,0 +1,64 @@
+use anyhow::Result;
+use auditor::{
+    cli::{Backend, HttpBackend, LocalBackend},
+    config::ConfigBuilder,
+    lsp::Server,
+};
+use lsp_server::Connection;
+use std::{env, process};
+
+const USAGE: &str =
+    "usage: auditor-lsp [--config <file> | --server <url>] [--token <token>] [--author <name>]
+
+Speaks the language server protocol on stdin and stdout. Without --server the db of the config is
+changed directly. The server and the token can also be set with AUDITOR_SERVER and
+AUDITOR_API_TOKEN, the author with AUDITOR_AUTHOR.";
+
+/// Reads `--name <value>` from the arguments.
+fn option(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    match args.get(index + 1) {
+        Some(value) => Some(value.clone()),
+        None => {
+            eprintln!("{USAGE}");
+            process::exit(2)
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<()> {
+    let server = option(&args, "--server").or_else(|| env::var("AUDITOR_SERVER").ok());
+    let token = option(&args, "--token").or_else(|| env::var("AUDITOR_API_TOKEN").ok());
+    let config_path = option(&args, "--config").unwrap_or_else(|| "./config.toml".to_string());
+    let author = option(&args, "--author")
+        .or_else(|| env::var("AUDITOR_AUTHOR").ok())
+        .or_else(|| env::var("USER").ok());
+
+    let backend: Box<dyn Backend> = match server {
+        Some(server) => Box::new(HttpBackend::new(&server, token)?),
+        None => Box::new(LocalBackend::new(
+            ConfigBuilder::default()
+                .toml(&config_path)?
+                .env()?
+                .build()?,
+        )),
+    };
+    // Logs have to go to stderr, stdout carries the protocol
+    let (connection, io_threads) = Connection::stdio();
+    Server::new(backend, author).run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    if let Err(err) = run(args) {
+        eprintln!("error: {err:#}");
+        process::exit(1);
+    }
+}
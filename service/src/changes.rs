@@ -0,0 +1,217 @@
+use crate::history::{Event, Prior};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// A change to the db that is pushed to the dashboard and the editors, which then reload the
+/// affected part of the file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    Reviews { file_name: String },
+    Comments { file_name: String },
+    Metadata { file_name: String },
+    Findings,
+    // Sent when a subscriber missed changes or many files changed at once, everything has to be
+    // reloaded
+    Resync,
+}
+
+impl Change {
+    pub fn file_name(&self) -> Option<&str> {
+        match self {
+            Change::Reviews { file_name }
+            | Change::Comments { file_name }
+            | Change::Metadata { file_name } => Some(file_name),
+            Change::Findings | Change::Resync => None,
+        }
+    }
+
+    /// Lists the parts of the file that the event changed, which are the parts it keeps the
+    /// prior state of.
+    pub fn of_event(event: &Event) -> Vec<Change> {
+        let file_name = event.file_name.clone();
+        event
+            .prior
+            .iter()
+            .map(|prior| match prior {
+                Prior::Review { .. } => Change::Reviews {
+                    file_name: file_name.clone(),
+                },
+                Prior::Comments(_) => Change::Comments {
+                    file_name: file_name.clone(),
+                },
+                Prior::Metadata(_) => Change::Metadata {
+                    file_name: file_name.clone(),
+                },
+            })
+            .collect()
+    }
+}
+
+/// Sends every published change to all current subscribers.
+#[derive(Clone, Debug)]
+pub struct Changes {
+    sender: broadcast::Sender<Change>,
+}
+
+/// Receives the changes published after it subscribed.
+pub struct Subscription {
+    receiver: broadcast::Receiver<Change>,
+}
+
+impl Changes {
+    /// Subscribers that fall more than `capacity` changes behind get a resync instead.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity).0,
+        }
+    }
+
+    pub fn publish(&self, change: Change) {
+        // Nobody may be subscribed, which is not an error
+        let _ = self.sender.send(change);
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+impl Subscription {
+    /// Waits for the next change, None once no more changes can be published.
+    pub async fn next(&mut self) -> Option<Change> {
+        match self.receiver.recv().await {
+            Ok(change) => Some(change),
+            Err(RecvError::Lagged(_)) => Some(Change::Resync),
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{history::Action, FileComments, State};
+    use std::collections::HashMap;
+
+    fn reviews(file_name: &str) -> Change {
+        Change::Reviews {
+            file_name: file_name.to_string(),
+        }
+    }
+
+    fn comments(file_name: &str) -> Change {
+        Change::Comments {
+            file_name: file_name.to_string(),
+        }
+    }
+
+    fn metadata(file_name: &str) -> Change {
+        Change::Metadata {
+            file_name: file_name.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let changes = Changes::new(8);
+        // Published before anyone subscribed, so it is not received
+        changes.publish(Change::Findings);
+
+        let mut dashboard = changes.subscribe();
+        let mut editor = changes.subscribe();
+        let published = vec![
+            reviews("src/a.rs"),
+            comments("src/b.rs"),
+            metadata("src/a.rs"),
+        ];
+        for change in &published {
+            changes.publish(change.clone());
+        }
+        for subscription in [&mut dashboard, &mut editor] {
+            for change in &published {
+                assert_eq!(subscription.next().await.as_ref(), Some(change));
+            }
+        }
+        assert_eq!(published[1].file_name(), Some("src/b.rs"));
+        assert_eq!(Change::Findings.file_name(), None);
+        assert_eq!(
+            serde_json::to_value(&published[2]).unwrap(),
+            serde_json::json!({ "kind": "metadata", "file_name": "src/a.rs" })
+        );
+
+        // Subscribers are told when no more changes can be published
+        drop(changes);
+        assert_eq!(dashboard.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_resync() {
+        let changes = Changes::new(2);
+        let mut subscription = changes.subscribe();
+        changes.publish(reviews("src/a.rs"));
+        assert_eq!(subscription.next().await, Some(reviews("src/a.rs")));
+
+        // A subscriber that fell behind is told to reload everything and then gets the changes
+        // that are still buffered
+        for file_name in ["src/a.rs", "src/b.rs", "src/c.rs"] {
+            changes.publish(comments(file_name));
+        }
+        assert_eq!(subscription.next().await, Some(Change::Resync));
+        assert_eq!(subscription.next().await, Some(comments("src/b.rs")));
+        assert_eq!(subscription.next().await, Some(comments("src/c.rs")));
+
+        drop(changes);
+        assert_eq!(subscription.next().await, None);
+    }
+
+    #[test]
+    fn test_of_event() {
+        let file_name = "src/a.rs";
+        let prior_review = || Prior::Review {
+            commit: None,
+            review: None,
+        };
+        let prior_comments = || Prior::Comments(FileComments(HashMap::default()));
+
+        let mark = Event::new(
+            file_name,
+            None,
+            Action::Mark(State::Reviewed),
+            vec![prior_review()],
+        );
+        assert_eq!(Change::of_event(&mark), vec![reviews(file_name)]);
+        let comment = Event::new(file_name, None, Action::AddComment, vec![prior_comments()]);
+        assert_eq!(Change::of_event(&comment), vec![comments(file_name)]);
+        let assign = Event::new(
+            file_name,
+            None,
+            Action::Metadata,
+            vec![Prior::Metadata(None)],
+        );
+        assert_eq!(Change::of_event(&assign), vec![metadata(file_name)]);
+
+        // Moving a file along a commit changes its reviews and its comments
+        let transform = Event::new(
+            file_name,
+            None,
+            Action::Transform,
+            vec![prior_review(), prior_comments()],
+        );
+        assert_eq!(
+            Change::of_event(&transform),
+            vec![reviews(file_name), comments(file_name)]
+        );
+        // A revert changes the same parts as the event it reverts
+        let revert = Event::new(
+            file_name,
+            None,
+            Action::Revert(comment.id.clone()),
+            vec![prior_comments()],
+        );
+        assert_eq!(Change::of_event(&revert), vec![comments(file_name)]);
+    }
+}
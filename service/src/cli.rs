@@ -0,0 +1,491 @@
+use crate::{
+    add_comment,
+    config::Config,
+    delete_comment,
+    errors::AuditorError,
+    file_key,
+    git::Git,
+    infos_in_scope,
+    report::{count, percent},
+    storage::open_storage,
+    transform_review_state, update_metadata, update_review_state, FileComments, LatestFileInfo,
+    UpdateMetadataRequest, UpdateReviewState,
+};
+use anyhow::Result;
+use hyper::{body, client::HttpConnector, header, Body, Client, Method, Request};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, fs, ops::RangeInclusive, path::Path};
+use tokio::runtime::Runtime;
+
+/// What `auditor-cli` does, either on the db directory or through a running service.
+/// File names are relative to the repository.
+pub trait Backend {
+    /// Lists the files in scope, or only `file_name` if given.
+    fn infos(&self, file_name: Option<&str>) -> Result<Vec<LatestFileInfo>>;
+
+    fn mark(&self, request: UpdateReviewState) -> Result<()>;
+
+    /// Moves the reviews and comments of the file to HEAD.
+    fn transform(&self, file_name: &str, author: Option<String>) -> Result<()>;
+
+    fn comments(&self, file_name: &str) -> Result<FileComments>;
+
+    /// Adds a comment and returns its id. The service knows the author from the token, the db
+    /// needs it to be given.
+    fn add_comment(
+        &self,
+        file_name: &str,
+        line_number: usize,
+        body: String,
+        author: Option<String>,
+    ) -> Result<String>;
+
+    fn delete_comment(
+        &self,
+        file_name: &str,
+        line_number: usize,
+        comment_id: &str,
+        author: Option<String>,
+    ) -> Result<()>;
+
+    fn set_metadata(&self, request: UpdateMetadataRequest) -> Result<()>;
+}
+
+/// Reads and writes the db of the config directly, the same way the service does.
+pub struct LocalBackend {
+    config: Config,
+}
+
+impl LocalBackend {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+impl Backend for LocalBackend {
+    fn infos(&self, file_name: Option<&str>) -> Result<Vec<LatestFileInfo>> {
+        let file_name = file_name.map(str::to_string);
+        let db = open_storage(&self.config, file_name.as_ref())?;
+        infos_in_scope(&self.config, db.as_ref(), file_name.as_deref())
+    }
+
+    fn mark(&self, request: UpdateReviewState) -> Result<()> {
+        let git = Git::new(&self.config.repository_path)?;
+        let file_name = request.file_name.clone();
+        let mut db = open_storage(&self.config, Some(&file_name))?;
+        update_review_state(request, db.as_mut(), &git)?;
+        db.save_file(&file_name)
+    }
+
+    fn transform(&self, file_name: &str, author: Option<String>) -> Result<()> {
+        let git = Git::new(&self.config.repository_path)?;
+        let mut db = open_storage(&self.config, None)?;
+        transform_review_state(&file_name.to_string(), author, db.as_mut(), &git)?;
+        db.save()
+    }
+
+    fn comments(&self, file_name: &str) -> Result<FileComments> {
+        let db = open_storage(&self.config, Some(&file_name.to_string()))?;
+        Ok(db
+            .get_file_comments(file_name)?
+            .unwrap_or_else(|| FileComments(HashMap::default())))
+    }
+
+    fn add_comment(
+        &self,
+        file_name: &str,
+        line_number: usize,
+        body: String,
+        author: Option<String>,
+    ) -> Result<String> {
+        let author = author.ok_or(AuditorError::MissingAuthor)?;
+        let mut db = open_storage(&self.config, Some(&file_name.to_string()))?;
+        let comment_id = add_comment(
+            file_name.to_string(),
+            line_number,
+            body,
+            author,
+            db.as_mut(),
+        )?;
+        db.save_file(file_name)?;
+        Ok(comment_id)
+    }
+
+    fn delete_comment(
+        &self,
+        file_name: &str,
+        line_number: usize,
+        comment_id: &str,
+        author: Option<String>,
+    ) -> Result<()> {
+        let mut db = open_storage(&self.config, Some(&file_name.to_string()))?;
+        delete_comment(
+            file_name.to_string(),
+            comment_id.to_string(),
+            line_number,
+            author,
+            db.as_mut(),
+        )?;
+        db.save_file(file_name)
+    }
+
+    fn set_metadata(&self, request: UpdateMetadataRequest) -> Result<()> {
+        let file_name = request.file_name.clone();
+        let mut db = open_storage(&self.config, Some(&file_name))?;
+        update_metadata(request, db.as_mut())?;
+        db.save_file(&file_name)
+    }
+}
+
+/// The error body of the service.
+#[derive(Deserialize)]
+struct ServiceError {
+    code: String,
+    message: String,
+}
+
+/// Sends the requests to a running service, which checks the token and the role of its user.
+pub struct HttpBackend {
+    base_url: String,
+    token: Option<String>,
+    client: Client<HttpConnector>,
+    runtime: Runtime,
+}
+
+impl HttpBackend {
+    pub fn new(base_url: &str, token: Option<String>) -> Result<Self> {
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            client: Client::new(),
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+        })
+    }
+
+    /// Sends the request and returns the body of a successful response.
+    fn send(&self, method: Method, path: &str, payload: Option<Value>) -> Result<Vec<u8>> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("{}{path}", self.base_url));
+        if let Some(token) = &self.token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = match payload {
+            Some(payload) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&payload)?))?,
+            None => request.body(Body::empty())?,
+        };
+        self.runtime.block_on(async {
+            let response = self.client.request(request).await?;
+            let status = response.status();
+            let body = body::to_bytes(response.into_body()).await?.to_vec();
+            if status.is_success() {
+                return Ok(body);
+            }
+            let error = serde_json::from_slice::<ServiceError>(&body).unwrap_or(ServiceError {
+                code: status.as_u16().to_string(),
+                message: String::from_utf8_lossy(&body).to_string(),
+            });
+            Err(AuditorError::ServiceError(error.code, error.message).into())
+        })
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(serde_json::from_slice(&self.send(
+            Method::GET,
+            path,
+            None,
+        )?)?)
+    }
+}
+
+impl Backend for HttpBackend {
+    fn infos(&self, file_name: Option<&str>) -> Result<Vec<LatestFileInfo>> {
+        match file_name {
+            Some(file_name) => self.get(&format!("/info?file_name={}", encode(file_name))),
+            None => self.get("/info"),
+        }
+    }
+
+    fn mark(&self, request: UpdateReviewState) -> Result<()> {
+        self.send(
+            Method::POST,
+            "/reviews",
+            Some(serde_json::to_value(request)?),
+        )?;
+        Ok(())
+    }
+
+    fn transform(&self, file_name: &str, author: Option<String>) -> Result<()> {
+        let payload = json!({ "file_name": file_name, "author": author });
+        self.send(Method::POST, "/transform", Some(payload))?;
+        Ok(())
+    }
+
+    fn comments(&self, file_name: &str) -> Result<FileComments> {
+        self.get(&format!("/comments?file_name={}", encode(file_name)))
+    }
+
+    fn add_comment(
+        &self,
+        file_name: &str,
+        line_number: usize,
+        body: String,
+        author: Option<String>,
+    ) -> Result<String> {
+        let payload = json!({
+            "file_name": file_name,
+            "line_number": line_number,
+            "body": body,
+            "author": author,
+        });
+        let response = self.send(Method::POST, "/comments", Some(payload))?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    fn delete_comment(
+        &self,
+        file_name: &str,
+        line_number: usize,
+        comment_id: &str,
+        author: Option<String>,
+    ) -> Result<()> {
+        let payload = json!({
+            "file_name": file_name,
+            "line_number": line_number,
+            "comment_id": comment_id,
+            "author": author,
+        });
+        self.send(Method::DELETE, "/comments", Some(payload))?;
+        Ok(())
+    }
+
+    fn set_metadata(&self, request: UpdateMetadataRequest) -> Result<()> {
+        self.send(
+            Method::POST,
+            "/metadata",
+            Some(serde_json::to_value(request)?),
+        )?;
+        Ok(())
+    }
+}
+
+/// Percent-encodes a query parameter.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Reads lines as editors show them, `5` or `5-10`, into the range of lines starting at 0.
+pub fn parse_lines(lines: &str) -> Option<RangeInclusive<usize>> {
+    let (start, end) = lines.split_once('-').unwrap_or((lines, lines));
+    let (start, end): (usize, usize) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    if start == 0 || start > end {
+        return None;
+    }
+    Some(RangeInclusive::new(start - 1, end - 1))
+}
+
+/// Turns a path on disk into the file name relative to its repository, along with its number of
+/// lines. Paths that don't exist, e.g. of files of a remote repository, are taken as relative
+/// already and have no number of lines.
+pub fn resolve_file(path: &str) -> Result<(String, Option<usize>)> {
+    let Ok(full_path) = fs::canonicalize(path) else {
+        return Ok((file_key(path, ""), None));
+    };
+    let repository = git2::Repository::discover(full_path.parent().unwrap_or(Path::new("/")))?;
+    let workdir = repository
+        .workdir()
+        .ok_or(AuditorError::InvalidAbsolutePath(path.to_string()))?;
+    let file_name = full_path
+        .strip_prefix(fs::canonicalize(workdir)?)
+        .map_err(|_| AuditorError::InvalidAbsolutePath(path.to_string()))?
+        .to_str()
+        .ok_or(AuditorError::OsStringError)?;
+    Ok((
+        file_key(file_name, ""),
+        Some(count_lines(&fs::read_to_string(&full_path)?)),
+    ))
+}
+
+/// Counts lines like editors do, a trailing newline starts another, empty line.
+pub fn count_lines(content: &str) -> usize {
+    content.split('\n').count()
+}
+
+/// One line per file with its coverage, followed by the total.
+pub fn format_status(infos: &[LatestFileInfo]) -> String {
+    let mut infos: Vec<&LatestFileInfo> = infos.iter().collect();
+    infos.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    let width = infos
+        .iter()
+        .map(|info| info.file_name.len())
+        .max()
+        .unwrap_or_default();
+    let mut output = String::new();
+    let (mut all_reviewed, mut all_lines) = (0, 0);
+    for info in infos {
+        let reviews = &info.line_reviews;
+        let reviewed = count(&reviews.reviewed);
+        all_reviewed += reviewed;
+        all_lines += reviews.total_lines;
+        output += &format!(
+            "{:width$}  {:>3}% reviewed ({reviewed}/{} lines), {} modified, {} ignored",
+            info.file_name,
+            percent(reviewed, reviews.total_lines),
+            reviews.total_lines,
+            count(&reviews.modified),
+            count(&reviews.ignored),
+        );
+        if let Some(metadata) = &info.metadata {
+            output += &format!(", {:?} priority", metadata.priority);
+        }
+        if info.open_threads > 0 {
+            output += &format!(", {} open threads", info.open_threads);
+        }
+        output += "\n";
+    }
+    output += &format!(
+        "{}% of {all_lines} lines reviewed\n",
+        percent(all_reviewed, all_lines)
+    );
+    output
+}
+
+/// Every thread of the file by line, with the replies below their comment.
+pub fn format_comments(comments: &FileComments) -> String {
+    let mut lines: Vec<&usize> = comments.0.keys().collect();
+    lines.sort();
+    let mut output = String::new();
+    for line in lines {
+        for comment in &comments.0[line] {
+            output += &format!(
+                "line {} [{:?}] {} {}: {}\n",
+                line + 1,
+                comment.state,
+                comment.id,
+                comment.author,
+                comment.body
+            );
+            for reply in &comment.replies {
+                output += &format!("    reply {} {}: {}\n", reply.id, reply.author, reply.body);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        git::tests::{commit_file, init_repo},
+        Priority, State,
+    };
+
+    #[test]
+    fn test_parse_lines() {
+        assert_eq!(parse_lines("5"), Some(RangeInclusive::new(4, 4)));
+        assert_eq!(parse_lines("5-10"), Some(RangeInclusive::new(4, 9)));
+        assert_eq!(parse_lines("0-3"), None);
+        assert_eq!(parse_lines("10-5"), None);
+        assert_eq!(parse_lines("a"), None);
+        assert_eq!(encode("src/a b.rs"), "src/a%20b.rs");
+        assert_eq!(count_lines("a\nb\n"), 3);
+    }
+
+    #[test]
+    fn test_local_backend() {
+        let repo_path = init_repo();
+        commit_file(&repo_path, "src/main.rs", "fn main() {\n    run();\n}\n");
+        let db_path = std::env::temp_dir().join(format!("auditor-cli-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&db_path).unwrap();
+        let backend = LocalBackend::new(Config {
+            repository_path: format!("{repo_path}/"),
+            db_path: db_path.to_str().unwrap().to_string(),
+            db_backend: "json".to_string(),
+            allowed_file_extensions: vec![".rs".to_string()],
+            included_prefixes: vec!["".to_string()],
+            ..Config::default()
+        });
+
+        let (file_name, total_lines) = resolve_file(&format!("{repo_path}/src/main.rs")).unwrap();
+        assert_eq!(file_name, "src/main.rs");
+        assert_eq!(total_lines, Some(4));
+
+        backend
+            .mark(UpdateReviewState::new(
+                file_name.clone(),
+                parse_lines("1-2").unwrap(),
+                State::Reviewed,
+                4,
+                Some("alice".to_string()),
+            ))
+            .unwrap();
+        let comment_id = backend
+            .add_comment(
+                &file_name,
+                1,
+                "unchecked".to_string(),
+                Some("alice".to_string()),
+            )
+            .unwrap();
+        let mut metadata = backend.infos(Some(&file_name)).unwrap()[0].metadata.clone();
+        assert!(metadata.is_none());
+        backend
+            .set_metadata(UpdateMetadataRequest {
+                file_name: file_name.clone(),
+                metadata: crate::Metadata {
+                    priority: Priority::High,
+                    reviewer: "alice".to_string(),
+                    note: "".to_string(),
+                },
+                author: None,
+            })
+            .unwrap();
+        metadata = backend.infos(None).unwrap()[0].metadata.clone();
+        assert_eq!(metadata.unwrap().priority, Priority::High);
+
+        let comments = backend.comments(&file_name).unwrap();
+        assert!(format_comments(&comments)
+            .starts_with(&format!("line 2 [Open] {comment_id} alice: unchecked\n")));
+        assert_eq!(
+            format_status(&backend.infos(None).unwrap()),
+            "src/main.rs   50% reviewed (2/4 lines), 0 modified, 0 ignored, High priority, 1 open threads\n50% of 4 lines reviewed\n"
+        );
+
+        backend
+            .delete_comment(&file_name, 1, &comment_id, None)
+            .unwrap();
+        assert_eq!(format_comments(&backend.comments(&file_name).unwrap()), "");
+
+        commit_file(
+            &repo_path,
+            "src/main.rs",
+            "// entry\nfn main() {\n    run();\n}\n",
+        );
+        backend.transform(&file_name, None).unwrap();
+        assert_eq!(
+            backend.infos(None).unwrap()[0].line_reviews.reviewed,
+            vec![RangeInclusive::new(1, 2)]
+        );
+        fs::remove_dir_all(db_path).unwrap();
+        fs::remove_dir_all(repo_path).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::errors::AuditorError;
+use crate::{errors::AuditorError, file_key};
 use serde::Deserialize;
 use std::{env, fs::read_to_string};
 
@@ -23,20 +23,53 @@ macro_rules! update_from_env {
 pub struct ConfigBuilder {
     repository_path: Option<String>,
     db_path: Option<String>,
+    db_backend: Option<String>,
     port: Option<String>,
     allowed_file_extensions: Option<String>,
     excluded_prefixes: Option<String>,
     included_prefixes: Option<String>,
+    high_priority_reviewers: Option<String>,
+    bind_address: Option<String>,
+    authentication: Option<String>,
+    dashboard_origin: Option<String>,
 }
 
 #[derive(Deserialize, Default, Clone, Debug)]
 pub struct Config {
     pub repository_path: String,
     pub db_path: String,
+    pub db_backend: String,
     pub port: String,
     pub allowed_file_extensions: Vec<String>,
     pub excluded_prefixes: Vec<String>,
     pub included_prefixes: Vec<String>,
+    // Distinct reviewers that must review a line of a high priority file
+    pub high_priority_reviewers: usize,
+    // Address the service listens on, only the local machine by default
+    pub bind_address: String,
+    // Whether requests need an API token, which then identifies the author of every change
+    pub authentication: bool,
+    // Origin of the web dashboard, the only one browsers may send requests from
+    pub dashboard_origin: String,
+}
+
+impl Config {
+    /// Whether the file is part of the audit: it has an allowed extension, one of the included
+    /// prefixes and none of the excluded prefixes. No allowed extensions allows every language.
+    pub fn in_scope(&self, file_name: &str) -> bool {
+        // Prefixes may be given as absolute paths or relative to the repository, like file names
+        let file_name = file_key(file_name, &self.repository_path);
+        let has_prefix =
+            |prefix: &String| file_name.starts_with(&file_key(prefix, &self.repository_path));
+        let extension_allowed = self.allowed_file_extensions.is_empty()
+            || self
+                .allowed_file_extensions
+                .iter()
+                .any(|ext| file_name.ends_with(ext));
+        extension_allowed
+            && self.included_prefixes.iter().any(has_prefix)
+            && !self.excluded_prefixes.iter().any(has_prefix)
+    }
 }
 
 impl ConfigBuilder {
@@ -51,10 +84,15 @@ impl ConfigBuilder {
 
             update_from_toml!(self, c, repository_path);
             update_from_toml!(self, c, db_path);
+            update_from_toml!(self, c, db_backend);
             update_from_toml!(self, c, port);
             update_from_toml!(self, c, allowed_file_extensions);
             update_from_toml!(self, c, excluded_prefixes);
             update_from_toml!(self, c, included_prefixes);
+            update_from_toml!(self, c, high_priority_reviewers);
+            update_from_toml!(self, c, bind_address);
+            update_from_toml!(self, c, authentication);
+            update_from_toml!(self, c, dashboard_origin);
         }
 
         Ok(self)
@@ -63,10 +101,15 @@ impl ConfigBuilder {
     pub fn env(&mut self) -> Result<&mut Self, AuditorError> {
         update_from_env!(self, "REPO_PATH", repository_path);
         update_from_env!(self, "DB_PATH", db_path);
+        update_from_env!(self, "DB_BACKEND", db_backend);
         update_from_env!(self, "PORT", port);
         update_from_env!(self, "ALLOWED_EXTENSIONS", allowed_file_extensions);
         update_from_env!(self, "EXCLUDED_PREFIXES", excluded_prefixes);
         update_from_env!(self, "INCLUDED_PREFIXES", included_prefixes);
+        update_from_env!(self, "HIGH_PRIORITY_REVIEWERS", high_priority_reviewers);
+        update_from_env!(self, "BIND_ADDRESS", bind_address);
+        update_from_env!(self, "AUTHENTICATION", authentication);
+        update_from_env!(self, "DASHBOARD_ORIGIN", dashboard_origin);
         Ok(self)
     }
 
@@ -87,6 +130,10 @@ impl ConfigBuilder {
                 .db_path
                 .clone()
                 .ok_or(AuditorError::MissingConfig("database path".to_string()))?,
+            db_backend: self
+                .db_backend
+                .clone()
+                .unwrap_or_else(|| "json".to_string()),
             port: self
                 .port
                 .clone()
@@ -95,6 +142,26 @@ impl ConfigBuilder {
             allowed_file_extensions: split(self.allowed_file_extensions.clone()),
             excluded_prefixes: split(self.excluded_prefixes.clone()),
             included_prefixes: split(self.included_prefixes.clone()),
+            high_priority_reviewers: match &self.high_priority_reviewers {
+                Some(reviewers) => reviewers.parse().map_err(|_| {
+                    AuditorError::InvalidConfig("high priority reviewers".to_string())
+                })?,
+                None => 2,
+            },
+            bind_address: self
+                .bind_address
+                .clone()
+                .unwrap_or_else(|| "127.0.0.1".to_string()),
+            authentication: match &self.authentication {
+                Some(authentication) => authentication
+                    .parse()
+                    .map_err(|_| AuditorError::InvalidConfig("authentication".to_string()))?,
+                None => false,
+            },
+            dashboard_origin: self
+                .dashboard_origin
+                .clone()
+                .unwrap_or_else(|| "http://localhost:8080".to_string()),
         })
     }
 }
@@ -103,22 +170,49 @@ impl ConfigBuilder {
 mod tests {
     use super::ConfigBuilder;
 
+    #[test]
+    fn test_in_scope() {
+        let mut builder = ConfigBuilder::try_from_string(
+            r#"
+repository_path = "/path/to/repo"
+db_path = "/path/to/db"
+allowed_file_extensions = ".rs,.go"
+excluded_prefixes = "src/vendor"
+included_prefixes = "src"
+        "#
+            .to_string(),
+        )
+        .unwrap();
+        let c = builder.build().unwrap();
+        assert!(c.in_scope("src/main.rs"));
+        assert!(!c.in_scope("src/vendor/dep.rs"));
+        assert!(!c.in_scope("src/index.js"));
+        assert!(!c.in_scope("tests/main.rs"));
+
+        // Files of every language are in scope without allowed extensions
+        builder.allowed_file_extensions = None;
+        assert!(builder.build().unwrap().in_scope("src/index.js"));
+    }
+
     #[test]
     fn test_load_from_string() {
         let builder = ConfigBuilder::try_from_string(
             r#"
 repository_path = "/path/to/repo"
 db_path = "/path/to/db"
+db_backend = "sqlite"
 port = "3000"
 allowed_file_extensions = ".rs,.go"
 excluded_prefixes = "/path/1,/path/2"
 included_prefixes = "/path/3"
+high_priority_reviewers = "3"
         "#
             .to_string(),
         )
         .unwrap();
         assert_eq!(builder.repository_path, Some("/path/to/repo".to_string()));
         assert_eq!(builder.db_path, Some("/path/to/db".to_string()));
+        assert_eq!(builder.db_backend, Some("sqlite".to_string()));
         assert_eq!(builder.port, Some("3000".to_string()));
         assert_eq!(builder.allowed_file_extensions, Some(".rs,.go".to_string()));
         assert_eq!(
@@ -126,12 +220,16 @@ included_prefixes = "/path/3"
             Some("/path/1,/path/2".to_string())
         );
         assert_eq!(builder.included_prefixes, Some("/path/3".to_string()));
+        assert_eq!(builder.high_priority_reviewers, Some("3".to_string()));
         let c = builder.build().unwrap();
         assert_eq!(c.repository_path, "/path/to/repo".to_string());
         assert_eq!(c.db_path, "/path/to/db".to_string());
+        assert_eq!(c.db_backend, "sqlite".to_string());
         assert_eq!(c.port, "3000".to_string());
         assert_eq!(c.allowed_file_extensions, vec![".rs", ".go"]);
         assert_eq!(c.excluded_prefixes, vec!["/path/1", "/path/2"]);
         assert_eq!(c.included_prefixes, vec!["/path/3"]);
+        assert_eq!(c.high_priority_reviewers, 3);
+        assert_eq!(c.dashboard_origin, "http://localhost:8080");
     }
 }
@@ -1,46 +1,77 @@
 use crate::{
-    AuditorError, Comment, FileComments, Metadata, StoredReviewForCommit, StoredReviewForFile,
+    auth::{ApiToken, User},
+    finding::Finding,
+    history::Event,
+    line_map::LineMap,
+    now,
+    session::Session,
+    storage::Storage,
+    AuditorError, Comment, FileComments, LatestFileInfo, Metadata, Reply, StoredReviewForCommit,
+    StoredReviewForFile, ThreadState,
 };
 use anyhow::{Context, Result};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::hash::{Hash, Hasher};
 use std::path::Path;
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{HashMap, HashSet},
     fs::File,
     io::{Read, Write},
     vec,
 };
 use uuid::Uuid;
 
+/// Version of the layout of stored files, bumped whenever `DB::migrate` has to upgrade them.
+pub const SCHEMA_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DBForFile {
-    file_name: String,
-    total_lines: usize,
-    latest_reviewed_commit: String,
+    // Files written before versioning was introduced have version 0
+    #[serde(default)]
+    pub(crate) schema_version: u32,
+    pub(crate) file_name: String,
+    pub(crate) total_lines: usize,
+    pub(crate) latest_reviewed_commit: String,
     // Maps commit to reviews
-    commit_reviews: HashMap<String, StoredReviewForFile>,
-    comments: FileComments,
-    metadata: Option<Metadata>,
+    pub(crate) commit_reviews: HashMap<String, StoredReviewForFile>,
+    pub(crate) comments: FileComments,
+    pub(crate) metadata: Option<Metadata>,
+    // Paths the file had before it was moved or copied
+    #[serde(default)]
+    pub(crate) aliases: Vec<String>,
+    // Incremented on every save, used to detect concurrent modifications
+    #[serde(default)]
+    pub(crate) revision: u64,
 }
 
 impl DBForFile {
-    pub fn get_latest_info(
-        &self,
-    ) -> Result<(String, StoredReviewForFile, FileComments, Option<Metadata>)> {
-        Ok((
-            self.file_name.clone(),
+    pub fn get_latest_info(&self) -> Result<LatestFileInfo> {
+        // Files that only have comments or metadata were never reviewed
+        let line_reviews = if self.latest_reviewed_commit.is_empty() {
+            StoredReviewForFile::default()
+        } else {
             self.commit_reviews
                 .get(&self.latest_reviewed_commit)
                 .ok_or(AuditorError::UnknownCommit(
                     self.latest_reviewed_commit.clone(),
                 ))?
-                .clone(),
-            self.comments.clone(),
-            self.metadata.clone(),
-        ))
+                .clone()
+        };
+        let (open_threads, resolved_threads) = self.comments.thread_counts();
+        Ok(LatestFileInfo {
+            file_name: self.file_name.clone(),
+            open_threads,
+            resolved_threads,
+            reviewer_coverage: line_reviews.reviewer_coverage(),
+            line_reviews,
+            comments: self.comments.0.clone(),
+            metadata: self.metadata.clone(),
+            aliases: self.aliases.clone(),
+            policy_status: None,
+            scope: None,
+        })
     }
 }
 
@@ -51,17 +82,65 @@ pub struct DB {
     pub file_dbs: HashMap<String, DBForFile>,
     // Contains the list of excluded directories
     exclusions: Vec<String>,
+    // Files that were moved and whose old stored file should be deleted on save
+    #[serde(skip)]
+    moved_files: Vec<String>,
+    // Files that were changed since they were loaded, only these are written on save
+    #[serde(skip)]
+    changed_files: HashSet<String>,
+    // Events that are appended to the history once the changes are saved
+    #[serde(skip)]
+    pending_events: Vec<Event>,
+    // Renames of files, in order, whose stored events are moved to the new path on save
+    #[serde(skip)]
+    renamed_files: Vec<(String, String)>,
+    // Findings span several files so they are stored on their own
+    #[serde(skip)]
+    findings: Vec<Finding>,
+    #[serde(skip)]
+    findings_changed: bool,
+    #[serde(skip)]
+    sessions: Vec<Session>,
+    #[serde(skip)]
+    sessions_changed: bool,
+    #[serde(skip)]
+    accounts: Accounts,
+    #[serde(skip)]
+    accounts_changed: bool,
 }
 
+/// Users and their API tokens, which are looked up together.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+struct Accounts {
+    users: Vec<User>,
+    tokens: Vec<ApiToken>,
+}
+
+/// Name of the append-only log of events, one JSON encoded event per line.
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+const FINDINGS_FILE_NAME: &str = "findings.json";
+
+const ACCOUNTS_FILE_NAME: &str = "accounts.json";
+
+const SESSIONS_FILE_NAME: &str = "sessions.json";
+
+/// Locked while saving, so that other processes cannot write between the check of a file's
+/// revision and its write.
+const LOCK_FILE_NAME: &str = "db.lock";
+
 impl DBForFile {
     pub fn default(file_name: String) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             file_name,
             total_lines: 0,
             latest_reviewed_commit: "".to_string(),
             commit_reviews: HashMap::default(),
             comments: FileComments(HashMap::default()),
             metadata: None,
+            aliases: vec![],
+            revision: 0,
         }
     }
 }
@@ -69,13 +148,10 @@ impl DBForFile {
 impl DB {
     pub fn new(db_dir: String) -> Result<Self> {
         let paths = fs::read_dir(db_dir.clone()).context(format!("dir: {db_dir}"))?;
-        let mut db = Self {
-            db_dir,
-            exclusions: vec![],
-            file_dbs: HashMap::default(),
-        };
+        let mut db = Self::new_without_files(db_dir)?;
 
-        let re = Regex::new(r"^db_.*\.(go|cpp|c|h)-\d*\.json$")?;
+        // Files of every language are loaded, `/info` filters them by the allowed extensions
+        let re = Regex::new(r"^db_.+-[0-9a-f]{64}\.json$")?;
         for path in paths {
             let path = path?;
             let base_name = path.file_name();
@@ -99,12 +175,8 @@ impl DB {
         Ok(db)
     }
 
-    pub fn new_single_file(db_dir: String, file_name: &String) -> Result<Self> {
-        let mut db = Self {
-            db_dir: db_dir.clone(),
-            exclusions: vec![],
-            file_dbs: HashMap::default(),
-        };
+    pub fn new_single_file(db_dir: String, file_name: &str) -> Result<Self> {
+        let mut db = Self::new_without_files(db_dir.clone())?;
 
         let path = Self::stored_file_name(file_name)?;
         let path = format!("{db_dir}/{path}");
@@ -119,70 +191,382 @@ impl DB {
         Ok(db)
     }
 
-    pub fn save(&self) -> Result<()> {
-        self.file_dbs
-            .iter()
-            .try_for_each(|(file_name, _)| -> Result<()> {
-                self.save_file(file_name)?;
-                Ok(())
-            })?;
-        Ok(())
+    /// Only loads the findings, the accounts and the sessions, saving it does not touch the stored
+    /// files.
+    pub fn new_without_files(db_dir: String) -> Result<Self> {
+        let findings_path = format!("{db_dir}/{FINDINGS_FILE_NAME}");
+        let findings = if Path::new(&findings_path).exists() {
+            serde_json::from_str(&fs::read_to_string(findings_path)?)?
+        } else {
+            vec![]
+        };
+        let accounts_path = format!("{db_dir}/{ACCOUNTS_FILE_NAME}");
+        let accounts = if Path::new(&accounts_path).exists() {
+            serde_json::from_str(&fs::read_to_string(accounts_path)?)?
+        } else {
+            Accounts::default()
+        };
+        let sessions_path = format!("{db_dir}/{SESSIONS_FILE_NAME}");
+        let sessions = if Path::new(&sessions_path).exists() {
+            serde_json::from_str(&fs::read_to_string(sessions_path)?)?
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            db_dir,
+            exclusions: vec![],
+            file_dbs: HashMap::default(),
+            moved_files: vec![],
+            changed_files: HashSet::default(),
+            pending_events: vec![],
+            renamed_files: vec![],
+            findings,
+            findings_changed: false,
+            sessions,
+            sessions_changed: false,
+            accounts,
+            accounts_changed: false,
+        })
     }
 
-    pub fn save_file(&self, file_name: &String) -> Result<()> {
-        let db_content = self
-            .file_dbs
-            .get(file_name)
-            .ok_or(AuditorError::UnknownFileName(file_name.clone()))?;
-        let ser = serde_json::to_string(&db_content)?;
-        let db_path = format!("{}/{}", &self.db_dir, Self::stored_file_name(file_name)?);
-        let mut output = File::create(db_path)?;
-        output.write_all(ser.as_bytes())?;
-        Ok(())
+    /// Upgrades the stored files of older schema versions in place and moves files that were
+    /// named with the previous hash to their current name. Returns the number of migrated files.
+    pub fn migrate(db_dir: &str) -> Result<usize> {
+        let re = Regex::new(r"^db_.+-[0-9a-f]+\.json$")?;
+        let mut migrated = 0;
+        for path in fs::read_dir(db_dir).context(format!("dir: {db_dir}"))? {
+            let path = path?.path();
+            let base_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(AuditorError::OsStringError)?;
+            if !re.is_match(base_name) {
+                continue;
+            }
+            let path = path.to_str().ok_or(AuditorError::OsStringError)?;
+            let mut db_content: DBForFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+            let stored_path = format!(
+                "{db_dir}/{}",
+                Self::stored_file_name(&db_content.file_name)?
+            );
+            // Compared as paths, `db_dir` may end with a slash
+            let same_file = Path::new(&stored_path) == Path::new(path);
+            if db_content.schema_version == SCHEMA_VERSION && same_file {
+                continue;
+            }
+            if db_content.schema_version > SCHEMA_VERSION {
+                return Err(
+                    AuditorError::UnsupportedSchemaVersion(db_content.schema_version).into(),
+                );
+            }
+
+            // Version 0 files only lack fields that have serde defaults
+            db_content.schema_version = SCHEMA_VERSION;
+            write_atomically(&stored_path, &serde_json::to_string(&db_content)?)?;
+            if !same_file {
+                fs::remove_file(path)?;
+            }
+            migrated += 1;
+        }
+        Ok(migrated)
     }
 
-    fn stored_file_name(file_name: &String) -> Result<String> {
-        let mut s = DefaultHasher::new();
-        file_name.hash(&mut s);
-        let id_from_path = s.finish().to_string();
+    /// The name is derived from the SHA-256 of the repo-relative path, which unlike
+    /// `DefaultHasher` is the same for every build of the service.
+    fn stored_file_name(file_name: &str) -> Result<String> {
+        let id_from_path = format!("{:x}", Sha256::digest(file_name.as_bytes()));
         let name: Vec<&str> = file_name.split('/').collect();
         let base_name = name
             .last()
-            .ok_or(AuditorError::InvalidAbsolutePath(file_name.clone()))?;
+            .ok_or(AuditorError::InvalidAbsolutePath(file_name.to_string()))?;
         Ok(format!("db_{}-{}.json", base_name, id_from_path))
     }
 
-    pub fn latest_reviewed_commit(&self, file_name: &String) -> Option<String> {
-        self.file_dbs
+    /// Waits until no other process saves the database. The lock is released when the returned
+    /// file is dropped.
+    fn lock(&self) -> Result<File> {
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(format!("{}/{LOCK_FILE_NAME}", self.db_dir))?;
+        lock.lock()?;
+        Ok(lock)
+    }
+
+    /// Writes the file unless it is unchanged, the caller holds the lock.
+    fn write_file(&mut self, file_name: &str) -> Result<()> {
+        let db_path = format!("{}/{}", &self.db_dir, Self::stored_file_name(file_name)?);
+        let db_content = self
+            .file_dbs
+            .get_mut(file_name)
+            .ok_or(AuditorError::UnknownFileName(file_name.to_string()))?;
+        if !self.changed_files.contains(file_name) {
+            return Ok(());
+        }
+
+        // Someone else saved the file after we loaded it
+        if let Some(stored_revision) = Self::stored_revision(&db_path)? {
+            if stored_revision != db_content.revision {
+                return Err(AuditorError::ConcurrentModification(file_name.to_string()).into());
+            }
+        }
+        db_content.revision += 1;
+        let ser = serde_json::to_string(&db_content)?;
+        if let Err(err) = write_atomically(&db_path, &ser) {
+            db_content.revision -= 1;
+            return Err(err);
+        }
+        self.changed_files.remove(file_name);
+        Ok(())
+    }
+
+    fn history_path(&self) -> String {
+        format!("{}/{HISTORY_FILE_NAME}", self.db_dir)
+    }
+
+    fn flush_events(&mut self) -> Result<()> {
+        if !self.renamed_files.is_empty() {
+            // The stored events of renamed files change, so the whole history is written again
+            let mut lines = String::new();
+            for event in self.events()? {
+                lines.push_str(&serde_json::to_string(&event)?);
+                lines.push('\n');
+            }
+            write_atomically(&self.history_path(), &lines)?;
+            self.renamed_files.clear();
+            self.pending_events.clear();
+            return Ok(());
+        }
+        if self.pending_events.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for event in &self.pending_events {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+        let mut history = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.history_path())?;
+        history.write_all(lines.as_bytes())?;
+        history.sync_all()?;
+        self.pending_events.clear();
+        Ok(())
+    }
+
+    /// Reads the stored history followed by the events that are not saved yet.
+    pub(crate) fn events(&self) -> Result<Vec<Event>> {
+        let mut events: Vec<Event> = vec![];
+        if Path::new(&self.history_path()).exists() {
+            for line in fs::read_to_string(self.history_path())?.lines() {
+                if !line.is_empty() {
+                    events.push(serde_json::from_str(line)?);
+                }
+            }
+        }
+        for (old_file_name, new_file_name) in &self.renamed_files {
+            for event in &mut events {
+                if &event.file_name == old_file_name {
+                    event.file_name = new_file_name.clone();
+                }
+            }
+        }
+        events.extend(self.pending_events.iter().cloned());
+        Ok(events)
+    }
+
+    fn thread_mut(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+    ) -> Result<&mut Comment> {
+        self.changed_files.insert(file_name.to_string());
+        let thread = self
+            .file_dbs
+            .get_mut(file_name)
+            .ok_or(AuditorError::UnknownFileName(file_name.to_string()))?
+            .comments
+            .0
+            .get_mut(&line_number)
+            .ok_or(AuditorError::UnknownLinenumberInFile(
+                line_number,
+                file_name.to_string(),
+            ))?
+            .iter_mut()
+            .find(|comment| comment.id == thread_id)
+            .ok_or(AuditorError::UnknownCommentId(thread_id.to_string()))?;
+        Ok(thread)
+    }
+
+    /// Returns the revision of the file as it is currently stored on disk.
+    fn stored_revision(db_path: &str) -> Result<Option<u64>> {
+        if !Path::new(db_path).exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(db_path)?;
+        let stored: DBForFile = serde_json::from_str(&contents)?;
+        Ok(Some(stored.revision))
+    }
+}
+
+/// Writes to a temporary file first so that a crash never leaves a half written file behind.
+fn write_atomically(path: &str, contents: &str) -> Result<()> {
+    let tmp_path = format!("{path}.{}.tmp", Uuid::new_v4());
+    let mut output = File::create(&tmp_path)?;
+    output.write_all(contents.as_bytes())?;
+    output.sync_all()?;
+    if let Err(err) = fs::rename(&tmp_path, path) {
+        fs::remove_file(&tmp_path)?;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+impl Storage for DB {
+    fn save(&mut self) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut file_names: Vec<String> = self
+            .changed_files
+            .iter()
+            .filter(|file_name| self.file_dbs.contains_key(*file_name))
+            .cloned()
+            .collect();
+        file_names.sort();
+        for file_name in &file_names {
+            self.write_file(file_name)?;
+        }
+        self.flush_events()?;
+        for file_name in &self.moved_files {
+            if self.file_dbs.contains_key(file_name) {
+                continue;
+            }
+            let db_path = format!("{}/{}", &self.db_dir, Self::stored_file_name(file_name)?);
+            if Path::new(&db_path).exists() {
+                fs::remove_file(db_path)?;
+            }
+        }
+        if self.findings_changed {
+            let ser = serde_json::to_string(&self.findings)?;
+            write_atomically(&format!("{}/{FINDINGS_FILE_NAME}", self.db_dir), &ser)?;
+            self.findings_changed = false;
+        }
+        if self.sessions_changed {
+            let ser = serde_json::to_string(&self.sessions)?;
+            write_atomically(&format!("{}/{SESSIONS_FILE_NAME}", self.db_dir), &ser)?;
+            self.sessions_changed = false;
+        }
+        if self.accounts_changed {
+            let ser = serde_json::to_string(&self.accounts)?;
+            write_atomically(&format!("{}/{ACCOUNTS_FILE_NAME}", self.db_dir), &ser)?;
+            self.accounts_changed = false;
+        }
+        Ok(())
+    }
+
+    fn save_file(&mut self, file_name: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        self.write_file(file_name)?;
+        self.flush_events()
+    }
+
+    fn latest_reviewed_commit(&self, file_name: &str) -> Result<Option<String>> {
+        Ok(self
+            .file_dbs
             .get(file_name)
             .map(|db| db.latest_reviewed_commit.clone())
+            .filter(|commit| !commit.is_empty()))
     }
 
-    pub fn review_status_of_commit(&self, commit: &Option<String>) -> StoredReviewForCommit {
+    fn reviewed_commits_of_file(&self, file_name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .file_dbs
+            .get(file_name)
+            .map(|db| db.commit_reviews.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn review_status_of_file_at(
+        &self,
+        file_name: &str,
+        commit: &str,
+    ) -> Result<Option<StoredReviewForFile>> {
+        Ok(self
+            .file_dbs
+            .get(file_name)
+            .and_then(|db| db.commit_reviews.get(commit).cloned()))
+    }
+
+    fn reviewed_commits(&self) -> Result<Vec<String>> {
+        let mut commits: Vec<String> = self
+            .file_dbs
+            .values()
+            .map(|db| db.latest_reviewed_commit.clone())
+            .filter(|commit| !commit.is_empty())
+            .collect();
+        commits.sort();
+        commits.dedup();
+        Ok(commits)
+    }
+
+    fn rename_file(&mut self, old_file_name: &str, new_file_name: &str) -> Result<()> {
+        if let Some(mut db_content) = self.file_dbs.remove(old_file_name) {
+            self.changed_files.insert(new_file_name.to_string());
+            db_content.file_name = new_file_name.to_string();
+            db_content.aliases.retain(|alias| alias != new_file_name);
+            db_content.aliases.push(old_file_name.to_string());
+            self.file_dbs.insert(new_file_name.to_string(), db_content);
+            self.moved_files.push(old_file_name.to_string());
+            for event in &mut self.pending_events {
+                if event.file_name == old_file_name {
+                    event.file_name = new_file_name.to_string();
+                }
+            }
+            self.renamed_files
+                .push((old_file_name.to_string(), new_file_name.to_string()));
+        }
+        Ok(())
+    }
+
+    fn add_alias(&mut self, file_name: &str, alias: &str) -> Result<()> {
+        self.changed_files.insert(file_name.to_string());
+        if let Some(db_content) = self.file_dbs.get_mut(file_name) {
+            if !db_content.aliases.iter().any(|existing| existing == alias) {
+                db_content.aliases.push(alias.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn review_status_of_commit(&self, commit: &Option<String>) -> Result<StoredReviewForCommit> {
         let mut commit_reviews = StoredReviewForCommit {
             exclusions: vec![],
             files: HashMap::default(),
         };
         if let Some(commit) = commit {
+            // Files last reviewed at other commits are left out, otherwise storing the state would
+            // overwrite their latest reviews with older ones
             for (file_name, db_content) in &self.file_dbs {
-                let review = match db_content.commit_reviews.get(commit) {
-                    Some(review) => review.clone(),
-                    None => StoredReviewForFile::default(),
-                };
-                commit_reviews.files.insert(file_name.clone(), review);
+                if &db_content.latest_reviewed_commit != commit {
+                    continue;
+                }
+                if let Some(review) = db_content.commit_reviews.get(commit) {
+                    commit_reviews
+                        .files
+                        .insert(file_name.clone(), review.clone());
+                }
             }
-            commit_reviews
+            Ok(commit_reviews)
         } else {
-            StoredReviewForCommit::new(self.exclusions.clone())
+            Ok(StoredReviewForCommit::new(self.exclusions.clone()))
         }
     }
 
-    pub fn store_review_status(
-        &mut self,
-        commit: &str,
-        state: &StoredReviewForCommit,
-    ) -> Result<()> {
+    fn store_review_status(&mut self, commit: &str, state: &StoredReviewForCommit) -> Result<()> {
         for file_name in state.files.keys() {
+            self.changed_files.insert(file_name.clone());
             let db_content = self
                 .file_dbs
                 .entry(file_name.clone())
@@ -202,7 +586,7 @@ impl DB {
         Ok(())
     }
 
-    pub fn add_new_comment(
+    fn add_new_comment(
         &mut self,
         file_name: String,
         line_number: usize,
@@ -210,11 +594,8 @@ impl DB {
         author: String,
     ) -> Result<String> {
         let id = Uuid::new_v4().to_string();
-        let comment = Comment {
-            id: id.clone(),
-            body,
-            author,
-        };
+        let comment = Comment::new(id.clone(), body, author);
+        self.changed_files.insert(file_name.clone());
         let db_content = self
             .file_dbs
             .entry(file_name.clone())
@@ -224,12 +605,13 @@ impl DB {
         Ok(id)
     }
 
-    pub fn delete_comment(
+    fn delete_comment(
         &mut self,
         file_name: String,
         comment_id: String,
         line_number: usize,
     ) -> Result<()> {
+        self.changed_files.insert(file_name.clone());
         let current_comments = self
             .file_dbs
             .get_mut(&file_name)
@@ -249,6 +631,18 @@ impl DB {
                 break;
             }
         }
+        // Replies are removed from their thread, which stays in place
+        for comment in current_comments.iter_mut() {
+            if let Some(i) = comment
+                .replies
+                .iter()
+                .position(|reply| reply.id == comment_id)
+            {
+                comment.replies.remove(i);
+                comment.updated_at = now();
+                return Ok(());
+            }
+        }
         if let Some(index) = index {
             current_comments.remove(index);
             if current_comments.is_empty() {
@@ -265,14 +659,45 @@ impl DB {
         }
     }
 
-    pub fn update_comment(
+    fn add_reply(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        body: String,
+        author: String,
+    ) -> Result<String> {
+        let thread = self.thread_mut(file_name, line_number, thread_id)?;
+        let reply = Reply::new(Uuid::new_v4().to_string(), body, author);
+        let id = reply.id.clone();
+        thread.updated_at = reply.created_at;
+        thread.replies.push(reply);
+        Ok(id)
+    }
+
+    fn set_thread_state(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        state: ThreadState,
+        user: Option<String>,
+    ) -> Result<()> {
+        self.thread_mut(file_name, line_number, thread_id)?
+            .set_state(state, user);
+        Ok(())
+    }
+
+    fn update_comment(
         &mut self,
         file_name: String,
         comment_id: String,
         line_number: usize,
         body: String,
-        author: String,
+        editor: String,
+        force: bool,
     ) -> Result<()> {
+        self.changed_files.insert(file_name.clone());
         let current_comments = self
             .file_dbs
             .get_mut(&file_name)
@@ -286,29 +711,220 @@ impl DB {
             ))?;
 
         for comment in current_comments.iter_mut() {
-            if comment.id == comment_id {
-                comment.body = body;
-                comment.author = author;
+            if comment.edit(&comment_id, body.clone(), editor.clone(), force)? {
                 return Ok(());
             }
         }
         Err(AuditorError::UnknownCommentId(comment_id).into())
     }
 
-    pub fn get_file_comments(&self, file_name: &String) -> Option<FileComments> {
+    fn transform_comments(
+        &mut self,
+        file_name: &str,
+        line_map: &LineMap,
+        total_lines: Option<usize>,
+    ) -> Result<()> {
+        self.changed_files.insert(file_name.to_string());
+        if let Some(db_content) = self.file_dbs.get_mut(file_name) {
+            let mut transformed: HashMap<usize, Vec<Comment>> = HashMap::default();
+            for (line_number, comments) in db_content.comments.0.drain() {
+                let mut line_number = line_map.map_line_nearest(line_number);
+                if let Some(total_lines) = total_lines {
+                    line_number = std::cmp::min(line_number, total_lines.saturating_sub(1));
+                }
+                transformed
+                    .entry(line_number)
+                    .or_insert(vec![])
+                    .extend(comments);
+            }
+            db_content.comments = FileComments(transformed);
+        }
+        Ok(())
+    }
+
+    fn get_file_comments(&self, file_name: &str) -> Result<Option<FileComments>> {
+        Ok(self
+            .file_dbs
+            .get(file_name)
+            .map(|db_content| db_content.comments.clone()))
+    }
+
+    fn set_comments(&mut self, file_name: &str, comments: FileComments) -> Result<()> {
+        self.changed_files.insert(file_name.to_string());
         self.file_dbs
+            .entry(file_name.to_string())
+            .or_insert(DBForFile::default(file_name.to_string()))
+            .comments = comments;
+        Ok(())
+    }
+
+    fn get_metadata(&self, file_name: &str) -> Result<Option<Metadata>> {
+        Ok(self
+            .file_dbs
             .get(file_name)
-            .map(|db_content| db_content.comments.clone())
+            .and_then(|db_content| db_content.metadata.clone()))
+    }
+
+    fn clear_metadata(&mut self, file_name: &str) -> Result<()> {
+        self.changed_files.insert(file_name.to_string());
+        if let Some(db_content) = self.file_dbs.get_mut(file_name) {
+            db_content.metadata = None;
+        }
+        Ok(())
     }
 
-    pub fn set_metadata(&mut self, file_name: &String, metadata: Metadata) -> Result<()> {
+    fn record_event(&mut self, event: Event) -> Result<()> {
+        self.pending_events.push(event);
+        Ok(())
+    }
+
+    fn events_of_file(&self, file_name: &str) -> Result<Vec<Event>> {
+        Ok(self
+            .events()?
+            .into_iter()
+            .filter(|event| event.file_name == file_name)
+            .collect())
+    }
+
+    fn find_event(&self, event_id: &str) -> Result<Option<Event>> {
+        Ok(self
+            .events()?
+            .into_iter()
+            .find(|event| event.id == event_id))
+    }
+
+    fn set_metadata(&mut self, file_name: &str, metadata: Metadata) -> Result<()> {
+        self.changed_files.insert(file_name.to_string());
         let db_content = self
             .file_dbs
             .get_mut(file_name)
-            .ok_or(AuditorError::UnknownFileName(file_name.clone()))?;
+            .ok_or(AuditorError::UnknownFileName(file_name.to_string()))?;
         db_content.metadata = Some(metadata);
         Ok(())
     }
+
+    fn latest_infos(&self) -> Result<Vec<LatestFileInfo>> {
+        self.file_dbs
+            .values()
+            .map(|db_content| db_content.get_latest_info())
+            .collect()
+    }
+
+    fn get_findings(&self) -> Result<Vec<Finding>> {
+        Ok(self.findings.clone())
+    }
+
+    fn get_finding(&self, finding_id: &str) -> Result<Option<Finding>> {
+        Ok(self
+            .findings
+            .iter()
+            .find(|finding| finding.id == finding_id)
+            .cloned())
+    }
+
+    fn store_finding(&mut self, finding: Finding) -> Result<()> {
+        match self
+            .findings
+            .iter_mut()
+            .find(|existing| existing.id == finding.id)
+        {
+            Some(existing) => *existing = finding,
+            None => self.findings.push(finding),
+        }
+        self.findings_changed = true;
+        Ok(())
+    }
+
+    fn delete_finding(&mut self, finding_id: &str) -> Result<()> {
+        let index = self
+            .findings
+            .iter()
+            .position(|finding| finding.id == finding_id)
+            .ok_or(AuditorError::UnknownFindingId(finding_id.to_string()))?;
+        self.findings.remove(index);
+        self.findings_changed = true;
+        Ok(())
+    }
+
+    fn get_sessions(&self) -> Result<Vec<Session>> {
+        Ok(self.sessions.clone())
+    }
+
+    fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
+        Ok(self
+            .sessions
+            .iter()
+            .find(|session| session.id == session_id)
+            .cloned())
+    }
+
+    fn store_session(&mut self, session: Session) -> Result<()> {
+        match self
+            .sessions
+            .iter_mut()
+            .find(|existing| existing.id == session.id)
+        {
+            Some(existing) => *existing = session,
+            None => self.sessions.push(session),
+        }
+        self.sessions_changed = true;
+        Ok(())
+    }
+
+    fn get_users(&self) -> Result<Vec<User>> {
+        Ok(self.accounts.users.clone())
+    }
+
+    fn store_user(&mut self, user: User) -> Result<()> {
+        match self
+            .accounts
+            .users
+            .iter_mut()
+            .find(|existing| existing.name == user.name)
+        {
+            Some(existing) => *existing = user,
+            None => self.accounts.users.push(user),
+        }
+        self.accounts_changed = true;
+        Ok(())
+    }
+
+    fn get_tokens(&self) -> Result<Vec<ApiToken>> {
+        Ok(self.accounts.tokens.clone())
+    }
+
+    fn store_token(&mut self, token: ApiToken) -> Result<()> {
+        self.accounts.tokens.push(token);
+        self.accounts_changed = true;
+        Ok(())
+    }
+
+    fn user_of_token(&self, token_hash: &str) -> Result<Option<User>> {
+        Ok(self
+            .accounts
+            .tokens
+            .iter()
+            .find(|token| token.token_hash == token_hash)
+            .and_then(|token| {
+                self.accounts
+                    .users
+                    .iter()
+                    .find(|user| user.name == token.user)
+            })
+            .cloned())
+    }
+
+    fn delete_token(&mut self, token_id: &str) -> Result<()> {
+        let index = self
+            .accounts
+            .tokens
+            .iter()
+            .position(|token| token.id == token_id)
+            .ok_or(AuditorError::UnknownTokenId(token_id.to_string()))?;
+        self.accounts.tokens.remove(index);
+        self.accounts_changed = true;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -331,6 +947,7 @@ mod tests {
                 modified: vec![RangeInclusive::new(1, 1)],
                 ignored: vec![], // TODO: add tests for this case
                 total_lines: 0,  // TODO: add tests for this case
+                reviewers: HashMap::default(),
             },
         );
         let state = &StoredReviewForCommit {
@@ -343,15 +960,266 @@ mod tests {
         db.save().unwrap();
         let db = DB::new(path).unwrap();
 
-        assert_eq!(db.latest_reviewed_commit(&file1), Some(commit.clone()));
-        assert_eq!(db.latest_reviewed_commit(&file2), None);
-        let retrieved_state = db.review_status_of_commit(&Some(commit));
+        assert_eq!(
+            db.latest_reviewed_commit(&file1).unwrap(),
+            Some(commit.clone())
+        );
+        assert_eq!(db.latest_reviewed_commit(&file2).unwrap(), None);
+        let retrieved_state = db.review_status_of_commit(&Some(commit)).unwrap();
         assert_eq!(state.exclusions, retrieved_state.exclusions);
-        assert!(retrieved_state.files.get(&file1).is_some());
-        assert!(retrieved_state.files.get(&file2).is_none());
+        assert!(retrieved_state.files.contains_key(&file1));
+        assert!(!retrieved_state.files.contains_key(&file2));
         assert_eq!(
             state.files.get(&file1).unwrap(),
             retrieved_state.files.get(&file1).unwrap()
         );
     }
+
+    #[test]
+    fn test_concurrent_modification() {
+        let path = std::env::temp_dir().join(format!("auditor-db-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let file = "src/file.go".to_string();
+        let author = "auditor".to_string();
+
+        let mut db = DB::new(path.clone()).unwrap();
+        db.add_new_comment(file.clone(), 0, "first".to_string(), author.clone())
+            .unwrap();
+        db.save_file(&file).unwrap();
+
+        // Two requests load the same file and both change it
+        let mut db1 = DB::new_single_file(path.clone(), &file).unwrap();
+        let mut db2 = DB::new_single_file(path.clone(), &file).unwrap();
+        db1.add_new_comment(file.clone(), 1, "second".to_string(), author.clone())
+            .unwrap();
+        db2.add_new_comment(file.clone(), 2, "third".to_string(), author)
+            .unwrap();
+        db1.save_file(&file).unwrap();
+        let err = db2.save_file(&file).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AuditorError>(),
+            Some(AuditorError::ConcurrentModification(_))
+        ));
+
+        // The first change survived and no temporary files were left behind
+        let db = DB::new_single_file(path.clone(), &file).unwrap();
+        let comments = db.get_file_comments(&file).unwrap().unwrap();
+        assert!(comments.0.contains_key(&1));
+        assert!(!comments.0.contains_key(&2));
+        let tmp_files = fs::read_dir(&path)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_str().unwrap().ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(tmp_files, 0);
+
+        // Saving at the same time never lets both changes through
+        let saves: Vec<_> = (0..2)
+            .map(|line| {
+                let mut db = DB::new_single_file(path.clone(), &file).unwrap();
+                db.add_new_comment(file.clone(), line, "racing".to_string(), "bob".to_string())
+                    .unwrap();
+                let file = file.clone();
+                std::thread::spawn(move || db.save_file(&file).is_ok())
+            })
+            .collect();
+        let saved: Vec<bool> = saves.into_iter().map(|save| save.join().unwrap()).collect();
+        assert_eq!(saved.iter().filter(|saved| **saved).count(), 1);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_save_only_changed_files() {
+        let path = std::env::temp_dir().join(format!("auditor-db-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let (file1, file2) = ("src/a.rs".to_string(), "src/b.rs".to_string());
+        let mut db = DB::new(path.clone()).unwrap();
+        for file in [&file1, &file2] {
+            db.add_new_comment(file.clone(), 0, "body".to_string(), "bob".to_string())
+                .unwrap();
+        }
+        db.save().unwrap();
+
+        let mut db = DB::new(path.clone()).unwrap();
+        db.add_new_comment(file1.clone(), 1, "body".to_string(), "bob".to_string())
+            .unwrap();
+        db.save().unwrap();
+        // Saving again without changes writes nothing
+        db.save().unwrap();
+        let db = DB::new(path.clone()).unwrap();
+        assert_eq!(db.file_dbs[&file1].revision, 2);
+        assert_eq!(db.file_dbs[&file2].revision, 1);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_load_every_language() {
+        let path = std::env::temp_dir().join(format!("auditor-db-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let files = [
+            "src/lib.rs",
+            "contracts/Token.sol",
+            "include/util.hpp",
+            "Makefile",
+        ];
+
+        let mut db = DB::new(path.clone()).unwrap();
+        for file in files {
+            db.add_new_comment(
+                file.to_string(),
+                0,
+                "body".to_string(),
+                "auditor".to_string(),
+            )
+            .unwrap();
+        }
+        db.save().unwrap();
+
+        let db = DB::new(path.clone()).unwrap();
+        for file in files {
+            assert!(db.file_dbs.contains_key(file), "{file} was not loaded");
+        }
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_comment_threads() {
+        let path = std::env::temp_dir().join(format!("auditor-db-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let file = "src/file.go".to_string();
+
+        let mut db = DB::new(path.clone()).unwrap();
+        let first = add_comment(
+            file.clone(),
+            3,
+            "unchecked overflow".to_string(),
+            "alice".to_string(),
+            &mut db,
+        )
+        .unwrap();
+        let second = db
+            .add_new_comment(file.clone(), 3, "typo".to_string(), "bob".to_string())
+            .unwrap();
+        let reply = reply_to_comment(
+            file.clone(),
+            3,
+            first.clone(),
+            "fixed upstream".to_string(),
+            "bob".to_string(),
+            &mut db,
+        )
+        .unwrap();
+        set_thread_state(
+            file.clone(),
+            3,
+            first.clone(),
+            ThreadState::Resolved,
+            Some("alice".to_string()),
+            &mut db,
+        )
+        .unwrap();
+        assert!(db
+            .add_reply(&file, 4, &first, "".to_string(), "bob".to_string())
+            .is_err());
+
+        // Only the author edits without forcing, every edit keeps the previous body
+        let edit = |db: &mut DB, body: &str, editor: &str, force| {
+            update_comment(
+                file.clone(),
+                reply.clone(),
+                3,
+                body.to_string(),
+                editor.to_string(),
+                force,
+                db,
+            )
+        };
+        let err = edit(&mut db, "fixed", "alice", false).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AuditorError>(),
+            Some(AuditorError::NotCommentAuthor(_))
+        ));
+        edit(&mut db, "fixed in v2", "bob", false).unwrap();
+        edit(&mut db, "fixed in v2.1", "alice", true).unwrap();
+        assert!(db
+            .add_reply(&file, 3, "unknown", "".to_string(), "bob".to_string())
+            .is_err());
+        db.save().unwrap();
+
+        let mut db = DB::new(path.clone()).unwrap();
+        let threads = &db.get_file_comments(&file).unwrap().unwrap().0[&3];
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].replies[0].id, reply);
+        assert_eq!(threads[0].replies[0].body, "fixed in v2.1");
+        let edits: Vec<(&str, &str)> = threads[0].replies[0]
+            .edits
+            .iter()
+            .map(|edit| (edit.body.as_str(), edit.editor.as_str()))
+            .collect();
+        assert_eq!(
+            edits,
+            vec![("fixed upstream", "bob"), ("fixed in v2", "alice")]
+        );
+        assert_eq!(threads[0].state, ThreadState::Resolved);
+        assert_eq!(threads[0].resolved_by, Some("alice".to_string()));
+        assert_eq!(threads[1].state, ThreadState::Open);
+        let info = &db.latest_infos().unwrap()[0];
+        assert_eq!((info.open_threads, info.resolved_threads), (1, 1));
+
+        // Reopening clears who resolved the thread, deleting a reply keeps the thread
+        db.set_thread_state(&file, 3, &first, ThreadState::Open, None)
+            .unwrap();
+        db.delete_comment(file.clone(), reply, 3).unwrap();
+        db.delete_comment(file.clone(), second, 3).unwrap();
+        let threads = &db.get_file_comments(&file).unwrap().unwrap().0[&3];
+        assert_eq!(threads.len(), 1);
+        assert!(threads[0].replies.is_empty());
+        assert_eq!(threads[0].resolved_by, None);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_stored_file_name() {
+        assert_eq!(
+            DB::stored_file_name("src/main.rs").unwrap(),
+            "db_main.rs-42cb6807ad74b3e201c5a7ca98b911c5fa08380e942be6e4ac5807f8377f87fc.json"
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let path = std::env::temp_dir().join(format!("auditor-db-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let file = "src/main.go".to_string();
+
+        // A file written before versioning, named after the old `DefaultHasher` id
+        let legacy = r#"{"file_name":"src/main.go","total_lines":0,"latest_reviewed_commit":"","commit_reviews":{},"comments":{"3":[{"id":"1","body":"body","author":"auditor"}]},"metadata":null}"#;
+        let legacy_path = format!("{path}/db_main.go-1234567890.json");
+        fs::write(&legacy_path, legacy).unwrap();
+
+        assert_eq!(DB::migrate(&path).unwrap(), 1);
+        assert!(!Path::new(&legacy_path).exists());
+        let db = DB::new(path.clone()).unwrap();
+        let db_content = db.file_dbs.get(&file).unwrap();
+        assert_eq!(db_content.schema_version, SCHEMA_VERSION);
+        assert!(db_content.comments.0.contains_key(&3));
+
+        // Migrating again is a no-op
+        assert_eq!(DB::migrate(&path).unwrap(), 0);
+        assert_eq!(DB::migrate(&format!("{path}/")).unwrap(), 0);
+        assert!(DB::new(path.clone()).unwrap().file_dbs.contains_key(&file));
+
+        fs::remove_dir_all(path).unwrap();
+    }
 }
@@ -1,3 +1,4 @@
+use crate::auth::Permission;
 use thiserror::Error;
 
 /// WordCountError enumerates all possible errors returned by this library.
@@ -6,15 +7,68 @@ pub enum AuditorError {
     #[error("Config is missing an item")]
     MissingConfig(String),
 
+    #[error("Config has an invalid item")]
+    InvalidConfig(String),
+
     #[error("Comment id is not found")]
     UnknownCommentId(String),
 
+    #[error("Only the author of the comment can edit it")]
+    NotCommentAuthor(String),
+
+    #[error("Finding id is not found")]
+    UnknownFindingId(String),
+
+    #[error("The finding is invalid")]
+    InvalidFinding(String),
+
+    #[error("A valid API token is required")]
+    Unauthorized,
+
+    #[error("User is not found")]
+    UnknownUser(String),
+
+    #[error("User names cannot be empty")]
+    InvalidUserName(String),
+
+    #[error("User already exists")]
+    UserExists(String),
+
+    #[error("Token id is not found")]
+    UnknownTokenId(String),
+
+    #[error("Unknown role")]
+    InvalidRole(String),
+
+    #[error("The role of the user does not allow this")]
+    Forbidden(Permission),
+
+    #[error("Session id is not found")]
+    UnknownSessionId(String),
+
+    #[error("Event id is not found")]
+    UnknownEventId(String),
+
+    #[error("The event was followed by changes that cannot be reverted automatically")]
+    StaleEvent(String),
+
+    #[error(
+        "Commit {0} is not in the repository, fetch the full history instead of a shallow clone"
+    )]
+    MissingCommit(String),
+
     #[error("Commit is older than the latest commit")]
     OldCommitError(String),
 
     #[error("Commit in db is newer than the latest commit")]
     ShouldUpdateToLatest(String),
 
+    #[error("The lines only exist in uncommitted changes, commit them before marking them")]
+    UncommittedLines(String),
+
+    #[error("The document has unsaved changes, save it before marking lines")]
+    UnsavedChanges(String),
+
     #[error("Commit not found in the db")]
     UnknownCommit(String),
 
@@ -27,9 +81,33 @@ pub enum AuditorError {
     #[error("The line number does not exits")]
     UnknownLinenumberInFile(usize, String),
 
+    #[error("Unknown report format")]
+    UnknownReportFormat(String),
+
+    #[error("Unknown priority")]
+    InvalidPriority(String),
+
+    #[error("Unknown review state")]
+    InvalidReviewState(String),
+
+    #[error("Changes to the db need an author")]
+    MissingAuthor,
+
+    #[error("The service rejected the request: {1}")]
+    ServiceError(String, String),
+
     #[error("OsString is None")]
     OsStringError,
 
+    #[error("The file was modified by someone else since it was loaded")]
+    ConcurrentModification(String),
+
+    #[error("The db was written by a newer version of the service")]
+    UnsupportedSchemaVersion(u32),
+
+    #[error("Unknown database backend")]
+    UnknownBackend(String),
+
     #[error(transparent)]
     RegexError(#[from] regex::Error),
 
@@ -44,4 +122,7 @@ pub enum AuditorError {
 
     #[error(transparent)]
     ConfigError(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
 }
@@ -0,0 +1,213 @@
+use crate::{errors::AuditorError, now, storage::Storage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Informational,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub enum FindingStatus {
+    #[default]
+    Open,
+    Confirmed,
+    Fixed,
+    FalsePositive,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Location {
+    pub file_name: String,
+    pub lines: RangeInclusive<usize>,
+}
+
+/// A security issue found during the audit, which unlike a comment can span several files.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub id: String,
+    pub title: String,
+    pub severity: Severity,
+    pub status: FindingStatus,
+    // Category of the issue or a CWE id such as CWE-190
+    pub category: Option<String>,
+    pub description: String,
+    pub recommendation: String,
+    pub locations: Vec<Location>,
+    pub author: String,
+    // Seconds since the unix epoch
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Finding {
+    pub fn touches(&self, file_name: &str) -> bool {
+        self.locations
+            .iter()
+            .any(|location| location.file_name == file_name)
+    }
+}
+
+/// The fields of a finding that are set when it is created or updated.
+#[derive(Deserialize, Clone, Debug)]
+pub struct FindingRequest {
+    pub title: String,
+    pub severity: Severity,
+    #[serde(default)]
+    pub status: FindingStatus,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub recommendation: String,
+    pub locations: Vec<Location>,
+    // Replaced by the authenticated user when authentication is enabled
+    #[serde(default)]
+    pub author: String,
+}
+
+impl FindingRequest {
+    fn validate(&self) -> Result<()> {
+        if self.title.trim().is_empty() {
+            return Err(AuditorError::InvalidFinding("title is empty".to_string()).into());
+        }
+        if self.locations.is_empty() {
+            return Err(AuditorError::InvalidFinding("no location is given".to_string()).into());
+        }
+        if let Some(location) = self
+            .locations
+            .iter()
+            .find(|location| location.lines.start() > location.lines.end())
+        {
+            return Err(AuditorError::InvalidFinding(format!(
+                "invalid lines in {}",
+                location.file_name
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+pub fn create_finding(request: FindingRequest, db: &mut dyn Storage) -> Result<String> {
+    request.validate()?;
+    let created_at = now();
+    let finding = Finding {
+        id: Uuid::new_v4().to_string(),
+        title: request.title,
+        severity: request.severity,
+        status: request.status,
+        category: request.category,
+        description: request.description,
+        recommendation: request.recommendation,
+        locations: request.locations,
+        author: request.author,
+        created_at,
+        updated_at: created_at,
+    };
+    let id = finding.id.clone();
+    db.store_finding(finding)?;
+    Ok(id)
+}
+
+/// Replaces the fields of the finding, the author remains the one who created it.
+pub fn update_finding(id: &str, request: FindingRequest, db: &mut dyn Storage) -> Result<()> {
+    request.validate()?;
+    let finding = db
+        .get_finding(id)?
+        .ok_or(AuditorError::UnknownFindingId(id.to_string()))?;
+    db.store_finding(Finding {
+        title: request.title,
+        severity: request.severity,
+        status: request.status,
+        category: request.category,
+        description: request.description,
+        recommendation: request.recommendation,
+        locations: request.locations,
+        updated_at: now(),
+        ..finding
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{db::DB, sqlite::SqliteDB};
+
+    fn temp_dir() -> String {
+        let path = std::env::temp_dir().join(format!("auditor-findings-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn request(title: &str, locations: Vec<(&str, RangeInclusive<usize>)>) -> FindingRequest {
+        FindingRequest {
+            title: title.to_string(),
+            severity: Severity::High,
+            status: FindingStatus::Open,
+            category: Some("CWE-190".to_string()),
+            description: "The length can overflow".to_string(),
+            recommendation: "Use checked arithmetic".to_string(),
+            locations: locations
+                .into_iter()
+                .map(|(file_name, lines)| Location {
+                    file_name: file_name.to_string(),
+                    lines,
+                })
+                .collect(),
+            author: "alice".to_string(),
+        }
+    }
+
+    fn check_crud(db: &mut dyn Storage) {
+        let id = create_finding(
+            request("Overflow", vec![("src/a.rs", 1..=3), ("src/b.rs", 7..=7)]),
+            db,
+        )
+        .unwrap();
+        assert!(create_finding(request("", vec![("src/a.rs", 1..=1)]), db).is_err());
+        assert!(create_finding(request("No location", vec![]), db).is_err());
+
+        let mut update = request("Overflow in length", vec![("src/b.rs", 7..=9)]);
+        update.status = FindingStatus::Confirmed;
+        update.author = "bob".to_string();
+        update_finding(&id, update.clone(), db).unwrap();
+        assert!(update_finding("unknown", update, db).is_err());
+
+        let finding = db.get_finding(&id).unwrap().unwrap();
+        assert_eq!(finding.title, "Overflow in length");
+        assert_eq!(finding.status, FindingStatus::Confirmed);
+        assert_eq!(finding.author, "alice");
+        assert!(finding.touches("src/b.rs"));
+        assert!(!finding.touches("src/a.rs"));
+        assert_eq!(db.get_findings().unwrap(), vec![finding]);
+
+        db.delete_finding(&id).unwrap();
+        assert!(db.delete_finding(&id).is_err());
+        assert!(db.get_findings().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_findings() {
+        let path = temp_dir();
+        let mut db = DB::new(path.clone()).unwrap();
+        check_crud(&mut db);
+
+        // Findings of the JSON backend are kept once saved
+        let id = create_finding(request("Reentrancy", vec![("src/a.rs", 4..=4)]), &mut db).unwrap();
+        db.save().unwrap();
+        let db = DB::new_single_file(path.clone(), "src/a.rs").unwrap();
+        assert_eq!(db.get_findings().unwrap()[0].id, id);
+
+        check_crud(&mut SqliteDB::new(&path).unwrap());
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    config::Config, errors::AuditorError, file_key, git::Git, storage::Storage, Diff, Priority,
+};
+use anyhow::Result;
+use std::{collections::HashMap, fmt, ops::RangeInclusive};
+
+/// A high or medium priority file with lines that changed since its reviews were last stored.
+#[derive(Debug, PartialEq)]
+pub struct GateViolation {
+    pub file_name: String,
+    pub priority: Priority,
+    // Commit the reviews of the file were last stored at
+    pub commit: String,
+    // 0-based lines at HEAD that a transform would mark as modified
+    pub lines: Vec<RangeInclusive<usize>>,
+    // 0-based lines at `commit` that were deleted since, removed checks need a review too
+    pub deleted: Vec<RangeInclusive<usize>>,
+}
+
+/// Checks that no high or medium priority code changed since it was last reviewed, so that CI can
+/// block merges until an auditor looked at the changes.
+#[derive(Debug, Default)]
+pub struct Gate {
+    pub violations: Vec<GateViolation>,
+}
+
+impl Gate {
+    /// Diffs HEAD against the commit each file was last reviewed at, without storing anything.
+    /// Fails with `MissingCommit` if that commit was not fetched, like in a shallow clone.
+    pub fn check(config: &Config, db: &dyn Storage, git: &Git) -> Result<Self> {
+        let mut diffs: HashMap<String, Option<Diff>> = HashMap::default();
+        let mut violations = vec![];
+        for info in db.latest_infos()? {
+            let priority = match info.metadata.map(|metadata| metadata.priority) {
+                Some(priority @ (Priority::High | Priority::Medium)) => priority,
+                _ => continue,
+            };
+            if !config.in_scope(&info.file_name) {
+                continue;
+            }
+            let commit = match db.latest_reviewed_commit(&info.file_name)? {
+                Some(commit) => commit,
+                None => continue,
+            };
+            if !diffs.contains_key(&commit) {
+                if !git.has_commit(&commit) {
+                    return Err(AuditorError::MissingCommit(commit).into());
+                }
+                let exclusions = db
+                    .review_status_of_commit(&Some(commit.clone()))?
+                    .exclusions;
+                let diff = git.diff_current_and_commit(Some(commit.clone()), &exclusions)?;
+                diffs.insert(commit.clone(), diff);
+            }
+            let Some(diff) = &diffs[&commit] else {
+                continue;
+            };
+            // Moved files are keyed by their new path in the diff
+            let stored_name = file_key(&info.file_name, &config.repository_path);
+            let changed = diff.files.iter().find(|(file_name, file_diff)| {
+                **file_name == stored_name || file_diff.old_file_name.as_ref() == Some(&stored_name)
+            });
+            let Some((file_name, file_diff)) = changed else {
+                continue;
+            };
+            let line_map = file_diff.line_map();
+            let lines = line_map.added_ranges();
+            let deleted = line_map.deleted_ranges();
+            if !lines.is_empty() || !deleted.is_empty() {
+                violations.push(GateViolation {
+                    file_name: file_name.clone(),
+                    priority,
+                    commit,
+                    lines,
+                    deleted,
+                });
+            }
+        }
+        violations.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        Ok(Self { violations })
+    }
+
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return writeln!(
+                f,
+                "No high or medium priority code changed since it was last reviewed"
+            );
+        }
+        writeln!(
+            f,
+            "{} high or medium priority files changed since they were last reviewed:",
+            self.violations.len()
+        )?;
+        for violation in &self.violations {
+            let mut changes = vec![];
+            if !violation.lines.is_empty() {
+                changes.push(format!(
+                    "{} changed lines at {}",
+                    count(&violation.lines),
+                    to_line_list(&violation.lines)
+                ));
+            }
+            if !violation.deleted.is_empty() {
+                changes.push(format!(
+                    "{} deleted lines at {} of the reviewed version",
+                    count(&violation.deleted),
+                    to_line_list(&violation.deleted)
+                ));
+            }
+            writeln!(
+                f,
+                "  {} ({:?} priority, reviewed at {:.7}): {}",
+                violation.file_name,
+                violation.priority,
+                violation.commit,
+                changes.join(", ")
+            )?;
+        }
+        writeln!(
+            f,
+            "Review the changed lines and transform the reviews to HEAD before merging"
+        )
+    }
+}
+
+fn count(ranges: &[RangeInclusive<usize>]) -> usize {
+    ranges
+        .iter()
+        .map(|range| range.end() - range.start() + 1)
+        .sum()
+}
+
+/// Lists the lines 1-based, the way editors number them.
+fn to_line_list(ranges: &[RangeInclusive<usize>]) -> String {
+    ranges
+        .iter()
+        .map(|range| match (range.start() + 1, range.end() + 1) {
+            (start, end) if start == end => start.to_string(),
+            (start, end) => format!("{start}-{end}"),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        db::DB, git, storage::Storage, update_metadata, update_review_state, Metadata, State,
+        StoredReviewForCommit, StoredReviewForFile, UpdateMetadataRequest, UpdateReviewState,
+    };
+
+    #[test]
+    fn test_gate() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "src/auth.rs", "a\nb\nc\n");
+        git::tests::commit_file(&repo_path, "src/util.rs", "a\nb\nc\n");
+        git::tests::commit_file(&repo_path, "src/acl.rs", "a\nb\nc\n");
+        let git = git::Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = DB::new(db_path).unwrap();
+        let config = Config {
+            repository_path: repo_path.clone(),
+            included_prefixes: vec!["".to_string()],
+            ..Config::default()
+        };
+        for (file_name, priority) in [
+            ("src/auth.rs", Priority::High),
+            ("src/util.rs", Priority::Low),
+            ("src/acl.rs", Priority::Medium),
+        ] {
+            update_review_state(
+                UpdateReviewState::new(file_name.to_string(), 0..=3, State::Reviewed, 4, None),
+                &mut db,
+                &git,
+            )
+            .unwrap();
+            update_metadata(
+                UpdateMetadataRequest {
+                    file_name: file_name.to_string(),
+                    metadata: Metadata {
+                        priority,
+                        reviewer: "Unassigned".to_string(),
+                        note: String::new(),
+                    },
+                    author: None,
+                },
+                &mut db,
+            )
+            .unwrap();
+        }
+        let gate = Gate::check(&config, &db, &git).unwrap();
+        assert!(gate.passed());
+
+        git::tests::commit_file(&repo_path, "src/auth.rs", "a\nx\ny\nc\n");
+        git::tests::commit_file(&repo_path, "src/util.rs", "a\nx\nc\n");
+        let gate = Gate::check(&config, &db, &git).unwrap();
+        assert_eq!(gate.violations.len(), 1);
+        assert_eq!(gate.violations[0].file_name, "src/auth.rs");
+        assert_eq!(gate.violations[0].priority, Priority::High);
+        assert_eq!(gate.violations[0].lines, vec![1..=2]);
+        assert!(gate
+            .to_string()
+            .contains("src/auth.rs (High priority, reviewed at "));
+        assert!(gate.to_string().contains("2 changed lines at 2-3"));
+
+        // Deleting reviewed code, like a removed check, needs a review as well
+        git::tests::commit_file(&repo_path, "src/acl.rs", "a\nc\n");
+        let gate = Gate::check(&config, &db, &git).unwrap();
+        assert_eq!(gate.violations.len(), 2);
+        assert_eq!(gate.violations[0].file_name, "src/acl.rs");
+        assert_eq!(gate.violations[0].lines, vec![]);
+        assert_eq!(gate.violations[0].deleted, vec![1..=1]);
+        assert!(gate
+            .to_string()
+            .contains("src/acl.rs (Medium priority, reviewed at "));
+        assert!(gate
+            .to_string()
+            .contains("1 deleted lines at 2 of the reviewed version"));
+        assert_eq!(gate.violations[1].deleted, vec![1..=1]);
+
+        // Shallow clones lack the reviewed commit
+        let mut state = StoredReviewForCommit::new(vec![]);
+        state
+            .files
+            .insert("src/auth.rs".to_string(), StoredReviewForFile::default());
+        db.store_review_status(&"0".repeat(40), &state).unwrap();
+        let err = Gate::check(&config, &db, &git).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AuditorError>(),
+            Some(AuditorError::MissingCommit(_))
+        ));
+        assert!(err.to_string().contains("fetch the full history"));
+    }
+}
@@ -1,12 +1,30 @@
-use crate::{Diff, LineDiff};
+use crate::{errors::AuditorError, line_map::LineMap, Diff, FileDiff, LineDiff};
 use anyhow::Result;
-use git2::{Oid, Patch, Repository, Tree};
-use std::collections::HashMap;
+use git2::{
+    Delta, DiffFile, DiffFindOptions, DiffOptions, ErrorCode, Oid, Patch, Repository, Tree,
+};
+use std::{collections::HashMap, path::Path};
 
 pub struct Git {
     repo: Repository,
 }
 
+/// Where a commit stands relative to HEAD in the commit graph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommitRelation {
+    Same,
+    // HEAD builds on top of the commit
+    Ancestor,
+    // The commit builds on top of HEAD
+    Descendant,
+    // Both moved on from a merge base, e.g. after a rebase or an amend
+    Diverged(String),
+    // No shared history at all
+    Unrelated,
+    // The commit is not in the repository anymore
+    Missing,
+}
+
 impl Git {
     pub fn new(path: &String) -> Result<Self> {
         let repo = Repository::open(path)?;
@@ -18,20 +36,93 @@ impl Git {
         Ok(commit.id().to_string())
     }
 
-    pub fn is_commit_older_than_latest(&self, commit: &String) -> Result<bool> {
-        let commit = Oid::from_str(&commit)?;
-        let commit = self.repo.find_commit(commit)?;
-        let latest_commit = self.repo.head()?.peel_to_commit()?;
-        Ok(commit.time().seconds() < latest_commit.time().seconds())
+    pub fn relation_to_head(&self, commit: &str) -> Result<CommitRelation> {
+        let commit = match Oid::from_str(commit) {
+            Ok(commit) if self.repo.find_commit(commit).is_ok() => commit,
+            _ => return Ok(CommitRelation::Missing),
+        };
+        let head = self.repo.head()?.peel_to_commit()?.id();
+        if commit == head {
+            return Ok(CommitRelation::Same);
+        }
+        if self.repo.graph_descendant_of(head, commit)? {
+            return Ok(CommitRelation::Ancestor);
+        }
+        if self.repo.graph_descendant_of(commit, head)? {
+            return Ok(CommitRelation::Descendant);
+        }
+        match self.repo.merge_base(commit, head) {
+            Ok(base) => Ok(CommitRelation::Diverged(base.to_string())),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(CommitRelation::Unrelated),
+            Err(err) => Err(err.into()),
+        }
     }
 
-    pub fn get_tree_from_commit(&self, commit: &str) -> Result<Tree> {
+    /// Returns true if reviews stored at `commit` have to be transformed before they match HEAD.
+    pub fn is_commit_older_than_latest(&self, commit: &str) -> Result<bool> {
+        Ok(!matches!(
+            self.relation_to_head(commit)?,
+            CommitRelation::Same | CommitRelation::Descendant
+        ))
+    }
+
+    /// Picks, among `commits`, the one closest to HEAD that HEAD still builds on.
+    pub fn closest_reachable(&self, commits: &[String]) -> Result<Option<String>> {
+        let mut closest: Option<String> = None;
+        for commit in commits {
+            if !matches!(
+                self.relation_to_head(commit)?,
+                CommitRelation::Same | CommitRelation::Ancestor
+            ) {
+                continue;
+            }
+            let is_closer = match &closest {
+                Some(current) => self
+                    .repo
+                    .graph_descendant_of(Oid::from_str(commit)?, Oid::from_str(current)?)?,
+                None => true,
+            };
+            if is_closer {
+                closest = Some(commit.clone());
+            }
+        }
+        Ok(closest)
+    }
+
+    /// Whether the commit is in the repository, shallow clones lack the older commits.
+    pub fn has_commit(&self, commit: &str) -> bool {
+        Oid::from_str(commit).is_ok_and(|oid| self.repo.find_commit(oid).is_ok())
+    }
+
+    pub fn get_tree_from_commit(&self, commit: &str) -> Result<Tree<'_>> {
         let commit = Oid::from_str(commit)?;
         let commit = self.repo.find_commit(commit)?;
         let tree = commit.tree()?;
         Ok(tree)
     }
 
+    /// Reads the file as it was at `commit`, None if the file did not exist at that commit.
+    pub fn file_content_at(&self, commit: &str, file_name: &str) -> Result<Option<String>> {
+        let tree = self.get_tree_from_commit(commit)?;
+        let entry = match tree.get_path(Path::new(file_name)) {
+            Ok(entry) => entry,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let blob = entry.to_object(&self.repo)?.peel_to_blob()?;
+        Ok(Some(String::from_utf8_lossy(blob.content()).to_string()))
+    }
+
+    /// Resolves a commit id, a branch or any other revision to the id of its commit.
+    pub fn resolve_commit(&self, revision: &str) -> Result<String> {
+        let commit = self
+            .repo
+            .revparse_single(revision)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| AuditorError::UnknownCommit(revision.to_string()))?;
+        Ok(commit.id().to_string())
+    }
+
     pub fn diff_current_and_commit(
         &self,
         old_commit: Option<String>,
@@ -41,29 +132,61 @@ impl Git {
             return Ok(None);
         }
         let old_commit = old_commit.expect("will never fail");
-        let old_tree = self.get_tree_from_commit(&old_commit)?;
-        let current_tree = self.get_tree_from_commit(&self.current_commit()?)?;
-        let diff = self
+        self.diff_commits(&old_commit, &self.current_commit()?, exclusions)
+    }
+
+    pub fn diff_commits(
+        &self,
+        old_commit: &str,
+        new_commit: &str,
+        exclusions: &Vec<String>,
+    ) -> Result<Option<Diff>> {
+        let old_tree = self.get_tree_from_commit(old_commit)?;
+        let new_tree = self.get_tree_from_commit(new_commit)?;
+        let mut diff = self
             .repo
-            .diff_tree_to_tree(Some(&old_tree), Some(&current_tree), None)?;
+            .diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+        let mut find_options = DiffFindOptions::new();
+        find_options.renames(true).copies(true);
+        diff.find_similar(Some(&mut find_options))?;
 
-        let mut files: HashMap<String, Vec<LineDiff>> = HashMap::default();
         if diff.deltas().len() == 0 {
             return Ok(None);
         }
+        Ok(Some(Diff {
+            files: self.file_diffs(&diff, exclusions)?,
+        }))
+    }
+
+    /// Diffs the working tree of the file, including staged changes, back to HEAD so that its
+    /// lines map to the lines they were committed as. None if the file has no uncommitted changes.
+    pub fn uncommitted_changes(&self, file_name: &str) -> Result<Option<FileDiff>> {
+        let head_tree = self.get_tree_from_commit(&self.current_commit()?)?;
+        let mut options = DiffOptions::new();
+        options
+            .pathspec(file_name)
+            .disable_pathspec_match(true)
+            .include_untracked(true)
+            .show_untracked_content(true)
+            .reverse(true);
+        let diff = self
+            .repo
+            .diff_tree_to_workdir_with_index(Some(&head_tree), Some(&mut options))?;
+        Ok(self.file_diffs(&diff, &vec![])?.remove(file_name))
+    }
+
+    fn file_diffs(
+        &self,
+        diff: &git2::Diff<'_>,
+        exclusions: &Vec<String>,
+    ) -> Result<HashMap<String, FileDiff>> {
+        let mut files: HashMap<String, FileDiff> = HashMap::default();
         for (delta_index, delta) in diff.deltas().enumerate() {
-            // TODO: what if a file is moved?
-            // TODO: handle the unwraps properly
-            let old_file_name = delta
-                .old_file()
-                .path()
-                .unwrap()
-                .as_os_str()
-                .to_str()
-                .unwrap();
+            let old_file_name = Self::path_of(&delta.old_file())?;
+            let new_file_name = Self::path_of(&delta.new_file())?;
             let mut in_exclusion = false;
             for prefix in exclusions {
-                if old_file_name.starts_with(prefix) {
+                if old_file_name.starts_with(prefix) || new_file_name.starts_with(prefix) {
                     in_exclusion = true;
                     break;
                 }
@@ -71,43 +194,168 @@ impl Git {
             if in_exclusion {
                 continue;
             }
-            let diff_content = Patch::from_diff(&diff, delta_index)?;
-            let mut line_diffs = vec![];
-            if let Some(diff_content) = diff_content {
-                for hunk_index in 0..diff_content.num_hunks() {
-                    let hunk_line_count = diff_content.num_lines_in_hunk(hunk_index)?;
-                    for line_index in 0..hunk_line_count {
-                        let diff_line = diff_content.line_in_hunk(hunk_index, line_index)?;
-                        let old = diff_line.old_lineno();
-                        let new = diff_line.new_lineno();
-                        let is_modified = old.is_none() || new.is_none();
-                        if is_modified {
-                            line_diffs.push(LineDiff { old, new });
-                        }
-                    }
-                }
-            }
-            files.insert(old_file_name.to_string(), line_diffs);
+            let line_diffs = match Patch::from_diff(diff, delta_index)? {
+                Some(patch) => line_diffs_of(&patch)?,
+                None => vec![],
+            };
+            let total_lines = if delta.new_file().exists() {
+                let blob = self.repo.find_blob(delta.new_file().id())?;
+                // Counted the same way editors do: a trailing newline starts an empty last line
+                Some(blob.content().split(|byte| *byte == b'\n').count())
+            } else {
+                Some(0)
+            };
+            // Files are keyed by their new path so that renamed and copied files carry their
+            // reviews over, while the old path points to where they came from
+            let old_file_name = match delta.status() {
+                Delta::Added | Delta::Untracked => None,
+                _ => Some(old_file_name),
+            };
+            files.insert(
+                new_file_name,
+                FileDiff {
+                    line_diffs,
+                    total_lines,
+                    old_file_name,
+                    is_copy: delta.status() == Delta::Copied,
+                },
+            );
         }
+        Ok(files)
+    }
 
-        Ok(Some(Diff { files }))
+    fn path_of(file: &DiffFile) -> Result<String> {
+        Ok(file
+            .path()
+            .ok_or(AuditorError::OsStringError)?
+            .to_str()
+            .ok_or(AuditorError::OsStringError)?
+            .to_string())
     }
 }
 
+/// The added and deleted lines of the patch.
+fn line_diffs_of(patch: &Patch) -> Result<Vec<LineDiff>> {
+    let mut line_diffs = vec![];
+    for hunk_index in 0..patch.num_hunks() {
+        let hunk_line_count = patch.num_lines_in_hunk(hunk_index)?;
+        for line_index in 0..hunk_line_count {
+            let diff_line = patch.line_in_hunk(hunk_index, line_index)?;
+            let old = diff_line.old_lineno();
+            let new = diff_line.new_lineno();
+            let is_modified = old.is_none() || new.is_none();
+            if is_modified {
+                line_diffs.push(LineDiff { old, new });
+            }
+        }
+    }
+    Ok(line_diffs)
+}
+
+/// Maps the lines of `old` to where they are in `new`, for texts that are not in the repository,
+/// like the unsaved text of an editor.
+pub fn line_map_between(old: &str, new: &str) -> Result<LineMap> {
+    let patch = Patch::from_buffers(old.as_bytes(), None, new.as_bytes(), None, None)?;
+    Ok(LineMap::new(&line_diffs_of(&patch)?))
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use super::*;
+    use git2::Signature;
+    use std::{fs, path::Path};
+
+    /// Creates an empty repository in a fresh temporary directory and returns its path.
+    pub(crate) fn init_repo() -> String {
+        let path = std::env::temp_dir().join(format!("auditor-git-{}", uuid::Uuid::new_v4()));
+        Repository::init(&path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Moves `old_file_name` to `new_file_name` and commits it on top of HEAD.
+    pub(crate) fn commit_move(repo_path: &str, old_file_name: &str, new_file_name: &str) -> String {
+        let repo = Repository::open(repo_path).unwrap();
+        let new_path = Path::new(repo_path).join(new_file_name);
+        fs::create_dir_all(new_path.parent().unwrap()).unwrap();
+        fs::rename(Path::new(repo_path).join(old_file_name), new_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new(old_file_name)).unwrap();
+        index.add_path(Path::new(new_file_name)).unwrap();
+        index.write().unwrap();
+        commit_index(&repo, new_file_name)
+    }
+
+    fn commit_index(repo: &Repository, message: &str) -> String {
+        let mut index = repo.index().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("auditor", "auditor@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    /// Commits `content` to the top-level `file_name` on top of `parent` without moving HEAD.
+    pub(crate) fn commit_off_head(
+        repo_path: &str,
+        parent: &str,
+        file_name: &str,
+        content: &str,
+    ) -> String {
+        let repo = Repository::open(repo_path).unwrap();
+        let parent = repo.find_commit(Oid::from_str(parent).unwrap()).unwrap();
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        let mut builder = repo.treebuilder(Some(&parent.tree().unwrap())).unwrap();
+        builder.insert(file_name, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = Signature::now("auditor", "auditor@example.com").unwrap();
+        repo.commit(None, &signature, &signature, file_name, &tree, &[&parent])
+            .unwrap()
+            .to_string()
+    }
+
+    /// Writes `content` to `file_name` and commits it on top of HEAD.
+    pub(crate) fn commit_file(repo_path: &str, file_name: &str, content: &str) -> String {
+        let repo = Repository::open(repo_path).unwrap();
+        let full_path = Path::new(repo_path).join(file_name);
+        fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        fs::write(full_path, content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file_name)).unwrap();
+        index.write().unwrap();
+        commit_index(&repo, file_name)
+    }
 
     #[test]
     fn test_diff() {
-        let git = Git::new(&"..".to_string()).unwrap();
-        let current = git.current_commit().unwrap();
-        let prev = "700610cb3aa6b28dde21e854ba4547e29b766a48".to_string();
-        // assert_eq!(current, "c9085e7d80b737d25c3986fa55c8968d48ce8898");
-        let _diff = git
+        let repo_path = init_repo();
+        let prev = commit_file(&repo_path, "src/a.rs", "1\n2\n3\n");
+        commit_file(&repo_path, "service/b.rs", "1\n");
+        let current = commit_file(&repo_path, "src/a.rs", "0\n1\n3\n");
+        let git = Git::new(&repo_path).unwrap();
+        assert_eq!(git.current_commit().unwrap(), current);
+
+        let diff = git
             .diff_current_and_commit(Some(prev), &vec!["service".to_string()])
+            .unwrap()
             .unwrap();
+        assert!(!diff.files.contains_key("service/b.rs"));
+        let file_diff = diff.files.get("src/a.rs").unwrap();
+        assert_eq!(file_diff.total_lines, Some(4));
+        let line_map = file_diff.line_map();
+        assert_eq!(line_map.map_line(0), Some(1));
+        assert_eq!(line_map.map_line(1), None);
+        assert_eq!(line_map.map_line(2), Some(2));
+
         assert!(git
             .diff_current_and_commit(None, &vec![])
             .unwrap()
@@ -117,4 +365,68 @@ mod tests {
             .unwrap()
             .is_none());
     }
+
+    #[test]
+    fn test_diff_follows_renames() {
+        let repo_path = init_repo();
+        let prev = commit_file(&repo_path, "src/a.rs", "1\n2\n3\n4\n5\n6\n");
+        commit_move(&repo_path, "src/a.rs", "lib/b.rs");
+        let git = Git::new(&repo_path).unwrap();
+
+        let diff = git
+            .diff_current_and_commit(Some(prev), &vec![])
+            .unwrap()
+            .unwrap();
+        assert!(!diff.files.contains_key("src/a.rs"));
+        let file_diff = diff.files.get("lib/b.rs").unwrap();
+        assert_eq!(
+            file_diff.moved_from(&"lib/b.rs".to_string()),
+            Some(&"src/a.rs".to_string())
+        );
+        assert!(!file_diff.is_copy);
+    }
+
+    #[test]
+    fn test_relation_to_head() {
+        let repo_path = init_repo();
+        let first = commit_file(&repo_path, "a.rs", "1\n");
+        let head = commit_file(&repo_path, "a.rs", "1\n2\n");
+        let rebased = commit_off_head(&repo_path, &first, "a.rs", "0\n1\n");
+        let ahead = commit_off_head(&repo_path, &head, "a.rs", "1\n2\n3\n");
+        let missing = "0123456789012345678901234567890123456789".to_string();
+        let git = Git::new(&repo_path).unwrap();
+
+        assert_eq!(git.relation_to_head(&head).unwrap(), CommitRelation::Same);
+        assert_eq!(
+            git.relation_to_head(&first).unwrap(),
+            CommitRelation::Ancestor
+        );
+        assert_eq!(
+            git.relation_to_head(&ahead).unwrap(),
+            CommitRelation::Descendant
+        );
+        assert_eq!(
+            git.relation_to_head(&rebased).unwrap(),
+            CommitRelation::Diverged(first.clone())
+        );
+        assert_eq!(
+            git.relation_to_head(&missing).unwrap(),
+            CommitRelation::Missing
+        );
+
+        assert!(git.is_commit_older_than_latest(&first).unwrap());
+        assert!(git.is_commit_older_than_latest(&rebased).unwrap());
+        assert!(!git.is_commit_older_than_latest(&head).unwrap());
+        assert!(!git.is_commit_older_than_latest(&ahead).unwrap());
+
+        assert_eq!(
+            git.closest_reachable(&[first.clone(), rebased, missing, head.clone(), ahead])
+                .unwrap(),
+            Some(head)
+        );
+        assert_eq!(
+            git.closest_reachable(std::slice::from_ref(&first)).unwrap(),
+            Some(first)
+        );
+    }
 }
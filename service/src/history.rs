@@ -0,0 +1,516 @@
+use crate::{
+    auth::{Permission, User},
+    errors::AuditorError,
+    now,
+    storage::Storage,
+    FileComments, Metadata, State, StoredReviewForCommit, StoredReviewForFile, ThreadState,
+    UpdateMetadataRequest,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ops::RangeInclusive};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Action {
+    Mark(State),
+    Transform,
+    AddComment,
+    DeleteComment,
+    UpdateComment,
+    Reply,
+    SetThreadState(ThreadState),
+    Metadata,
+    // Contains the id of the reverted event
+    Revert(String),
+}
+
+/// Part of a file as it was right before an event changed it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Prior {
+    Review {
+        // Commit the file was last reviewed at, None if it was never reviewed
+        commit: Option<String>,
+        review: Option<StoredReviewForFile>,
+    },
+    // Only the comments at the lines of the event, or of the whole file for events without lines
+    Comments(FileComments),
+    Metadata(Option<Metadata>),
+}
+
+/// An entry of the append-only log of changes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Event {
+    pub id: String,
+    pub file_name: String,
+    pub author: Option<String>,
+    // Seconds since the unix epoch
+    pub timestamp: u64,
+    pub action: Action,
+    pub range: Option<RangeInclusive<usize>>,
+    // Commit at which reviews were stored by this event
+    pub commit: Option<String>,
+    pub prior: Vec<Prior>,
+}
+
+impl Event {
+    pub fn new(file_name: &str, author: Option<String>, action: Action, prior: Vec<Prior>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            file_name: file_name.to_string(),
+            author,
+            timestamp: now(),
+            action,
+            range: None,
+            commit: None,
+            prior,
+        }
+    }
+
+    pub fn with_range(mut self, range: RangeInclusive<usize>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn with_commit(mut self, commit: &str) -> Self {
+        self.commit = Some(commit.to_string());
+        self
+    }
+
+    /// Whether a later event changed any of the lines or the metadata this event changed.
+    fn overlaps(&self, later: &Event) -> bool {
+        let same_part = self.prior.iter().any(|part| {
+            later
+                .prior
+                .iter()
+                .any(|other| std::mem::discriminant(part) == std::mem::discriminant(other))
+        });
+        // Events without a range, like the transform of a file, change all of its lines
+        let same_lines = match (&self.range, &later.range) {
+            (Some(range), Some(other)) => {
+                range.start() <= other.end() && other.start() <= range.end()
+            }
+            _ => true,
+        };
+        same_part && same_lines
+    }
+}
+
+/// Captures the reviews of a file before they are changed.
+pub fn prior_review(db: &dyn Storage, file_name: &str) -> Result<Prior> {
+    let commit = db.latest_reviewed_commit(file_name)?;
+    let review = match &commit {
+        Some(commit) => db.review_status_of_file_at(file_name, commit)?,
+        None => None,
+    };
+    Ok(Prior::Review { commit, review })
+}
+
+/// Captures the comments of a file before they are changed, only those at `lines` if given.
+pub fn prior_comments(
+    db: &dyn Storage,
+    file_name: &str,
+    lines: Option<&RangeInclusive<usize>>,
+) -> Result<Prior> {
+    let mut comments = db
+        .get_file_comments(file_name)?
+        .unwrap_or(FileComments(HashMap::default()));
+    if let Some(lines) = lines {
+        comments.0.retain(|line, _| lines.contains(line));
+    }
+    Ok(Prior::Comments(comments))
+}
+
+/// Captures the metadata of a file before it is changed.
+pub fn prior_metadata(db: &dyn Storage, file_name: &str) -> Result<Prior> {
+    Ok(Prior::Metadata(db.get_metadata(file_name)?))
+}
+
+/// Fails with `Forbidden` unless the user may make the change that reverts the event: restoring
+/// the priority or the reviewer of a file assigns it, restoring reviews may clear reviewed lines
+/// and restoring comments changes the comments of others, unless the user made the event.
+pub fn authorize_revert(event_id: &str, user: &User, db: &dyn Storage) -> Result<()> {
+    let event = db
+        .find_event(event_id)?
+        .ok_or(AuditorError::UnknownEventId(event_id.to_string()))?;
+    let own_event = event.author.as_deref() == Some(user.name.as_str())
+        && !matches!(event.action, Action::Revert(_));
+    for part in &event.prior {
+        match part {
+            Prior::Review { commit, review } => {
+                let current = match db.latest_reviewed_commit(&event.file_name)? {
+                    Some(commit) => db.review_status_of_file_at(&event.file_name, &commit)?,
+                    None => None,
+                };
+                // A revert that goes stale fails later without restoring anything
+                let restored = restored_review(&event, commit, review, db).ok();
+                if let (Some(current), Some((_, restored))) = (current, restored) {
+                    if current.unreviewed_in(&restored) {
+                        user.authorize(Permission::ClearReviews)?;
+                    }
+                }
+            }
+            Prior::Comments(_) => {
+                if !own_event {
+                    user.authorize(Permission::ModerateComments)?;
+                }
+            }
+            Prior::Metadata(metadata) => {
+                let current = db.get_metadata(&event.file_name)?;
+                // Clearing the metadata unassigns what the current metadata assigns
+                let assigns = match (metadata, &current) {
+                    (Some(metadata), _) => UpdateMetadataRequest {
+                        file_name: event.file_name.clone(),
+                        metadata: metadata.clone(),
+                        author: None,
+                    }
+                    .assigns(current.as_ref()),
+                    (None, Some(current)) => UpdateMetadataRequest {
+                        file_name: event.file_name.clone(),
+                        metadata: current.clone(),
+                        author: None,
+                    }
+                    .assigns(None),
+                    (None, None) => false,
+                };
+                if assigns {
+                    user.authorize(Permission::AssignFiles)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The commit and the reviews of the file once the event is reverted. Events with a range only
+/// restore the marks of their lines.
+fn restored_review(
+    event: &Event,
+    commit: &Option<String>,
+    review: &Option<StoredReviewForFile>,
+    db: &dyn Storage,
+) -> Result<(String, StoredReviewForFile)> {
+    let review = review.clone().unwrap_or(StoredReviewForFile::default());
+    match (&event.range, &event.commit) {
+        (Some(range), Some(event_commit)) => {
+            let mut current = db
+                .review_status_of_file_at(&event.file_name, event_commit)?
+                .unwrap_or(StoredReviewForFile::default());
+            current.restore_lines(&review, range);
+            Ok((event_commit.clone(), current))
+        }
+        _ => {
+            let commit = commit
+                .clone()
+                .or(event.commit.clone())
+                .ok_or(AuditorError::StaleEvent(event.id.clone()))?;
+            Ok((commit, review))
+        }
+    }
+}
+
+/// Restores what the event changed to its prior state and records the revert as a new event, so
+/// that a revert can itself be reverted. Only the lines of the event are restored, later changes
+/// to other lines are kept. Fails with `StaleEvent` if a later event changed the same lines or
+/// the metadata again.
+pub fn revert_event(event_id: &str, author: Option<String>, db: &mut dyn Storage) -> Result<()> {
+    let event = db
+        .find_event(event_id)?
+        .ok_or(AuditorError::UnknownEventId(event_id.to_string()))?;
+    let file_name = &event.file_name;
+    let stale = db
+        .events_of_file(file_name)?
+        .iter()
+        .skip_while(|later| later.id != event.id)
+        .skip(1)
+        .any(|later| event.overlaps(later));
+    if stale {
+        return Err(AuditorError::StaleEvent(event_id.to_string()).into());
+    }
+
+    let mut prior = vec![];
+    for part in &event.prior {
+        match part {
+            Prior::Review { commit, review } => {
+                // Reviews are only restored while no later change moved them to another commit
+                if db.latest_reviewed_commit(file_name)? != event.commit {
+                    return Err(AuditorError::StaleEvent(event_id.to_string()).into());
+                }
+                prior.push(prior_review(db, file_name)?);
+                let (commit, review) = restored_review(&event, commit, review, db)?;
+                let mut state = StoredReviewForCommit::new(vec![]);
+                state.files.insert(file_name.clone(), review);
+                db.store_review_status(&commit, &state)?;
+            }
+            Prior::Comments(comments) => {
+                prior.push(prior_comments(db, file_name, event.range.as_ref())?);
+                let comments = match &event.range {
+                    Some(range) => {
+                        let mut current = db
+                            .get_file_comments(file_name)?
+                            .unwrap_or(FileComments(HashMap::default()));
+                        for line in range.clone() {
+                            match comments.0.get(&line) {
+                                Some(line_comments) => {
+                                    current.0.insert(line, line_comments.clone());
+                                }
+                                None => {
+                                    current.0.remove(&line);
+                                }
+                            }
+                        }
+                        current
+                    }
+                    None => comments.clone(),
+                };
+                db.set_comments(file_name, comments)?;
+            }
+            Prior::Metadata(metadata) => {
+                prior.push(prior_metadata(db, file_name)?);
+                match metadata {
+                    Some(metadata) => db.set_metadata(file_name, metadata.clone())?,
+                    None => db.clear_metadata(file_name)?,
+                }
+            }
+        }
+    }
+
+    let mut revert = Event::new(file_name, author, Action::Revert(event.id.clone()), prior);
+    revert.range = event.range.clone();
+    revert.commit = db.latest_reviewed_commit(file_name)?;
+    db.record_event(revert)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        add_comment, api_error::ApiError, auth::Role, db::DB, delete_comment, git,
+        transform_review_state, update_metadata, update_review_state, Priority, UpdateReviewState,
+    };
+    use axum::http::StatusCode;
+
+    fn mark(file_name: &str, range: (usize, usize), review_state: State) -> UpdateReviewState {
+        UpdateReviewState {
+            file_name: file_name.to_string(),
+            start_line: range.0,
+            end_line: range.1,
+            review_state,
+            total_lines: 3,
+            reviewer: Some("alice".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_revert_events() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "a.c", "1\n2\n3\n");
+        let git = git::Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = DB::new(db_path.clone()).unwrap();
+        let file_name = "a.c";
+
+        update_review_state(mark(file_name, (0, 2), State::Reviewed), &mut db, &git).unwrap();
+        update_review_state(mark(file_name, (0, 2), State::Cleared), &mut db, &git).unwrap();
+        let comment_id = add_comment(
+            file_name.to_string(),
+            1,
+            "body".to_string(),
+            "bob".to_string(),
+            &mut db,
+        )
+        .unwrap();
+        delete_comment(file_name.to_string(), comment_id, 1, None, &mut db).unwrap();
+        db.save().unwrap();
+
+        let db = &mut DB::new(db_path).unwrap();
+        let events = db.events_of_file(file_name).unwrap();
+        let actions: Vec<Action> = events.iter().map(|event| event.action.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                Action::Mark(State::Reviewed),
+                Action::Mark(State::Cleared),
+                Action::AddComment,
+                Action::DeleteComment
+            ]
+        );
+        assert_eq!(events[1].author, Some("alice".to_string()));
+        assert_eq!(events[1].range, Some(0..=2));
+
+        // Undo the accidental clear
+        revert_event(&events[1].id, None, db).unwrap();
+        let commit = db.latest_reviewed_commit(file_name).unwrap().unwrap();
+        let review = db.review_status_of_file_at(file_name, &commit).unwrap();
+        assert_eq!(review.unwrap().reviewed, vec![0..=2]);
+
+        // Undo the deletion of the comment
+        revert_event(&events[3].id, None, db).unwrap();
+        let comments = db.get_file_comments(file_name).unwrap().unwrap();
+        assert_eq!(comments.0.get(&1).unwrap()[0].body, "body");
+
+        // Reverts are recorded and can be reverted themselves
+        let events = db.events_of_file(file_name).unwrap();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[4].action, Action::Revert(events[1].id.clone()));
+        revert_event(&events[4].id, None, db).unwrap();
+        let review = db.review_status_of_file_at(file_name, &commit).unwrap();
+        assert_eq!(review.unwrap().reviewed, vec![]);
+
+        assert!(revert_event("unknown", None, db).is_err());
+
+        // Reverting an older event keeps the later changes to other lines
+        let comment = |line, body: &str, db: &mut DB| {
+            add_comment(
+                file_name.to_string(),
+                line,
+                body.to_string(),
+                "bob".to_string(),
+                db,
+            )
+            .unwrap()
+        };
+        comment(0, "A", db);
+        let comment_b = comment(2, "B", db);
+        let add_a = db.events_of_file(file_name).unwrap()[7].clone();
+        let add_b = db.events_of_file(file_name).unwrap()[8].clone();
+        assert_eq!(add_a.action, Action::AddComment);
+        // Only the comments at the line of the event are kept, not those of the whole file
+        assert!(matches!(&add_b.prior[..], [Prior::Comments(comments)] if comments.0.is_empty()));
+        revert_event(&add_a.id, None, db).unwrap();
+        let comments = db.get_file_comments(file_name).unwrap().unwrap();
+        assert!(!comments.0.contains_key(&0));
+        assert_eq!(comments.0[&1][0].body, "body");
+        assert_eq!(comments.0[&2][0].id, comment_b);
+
+        // Unless a later event changed the same lines again
+        comment(2, "C", db);
+        let error = revert_event(&add_b.id, None, db).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AuditorError>(),
+            Some(AuditorError::StaleEvent(_))
+        ));
+        assert_eq!(
+            db.get_file_comments(file_name).unwrap().unwrap().0[&2].len(),
+            2
+        );
+
+        update_review_state(mark(file_name, (0, 0), State::Reviewed), &mut *db, &git).unwrap();
+        update_review_state(mark(file_name, (2, 2), State::Reviewed), &mut *db, &git).unwrap();
+        let events = db.events_of_file(file_name).unwrap();
+        revert_event(&events[events.len() - 2].id, None, db).unwrap();
+        let review = db.review_status_of_file_at(file_name, &commit).unwrap();
+        assert_eq!(review.unwrap().reviewed, vec![2..=2]);
+        // An event can only be reverted once, the revert itself is reverted instead
+        assert!(revert_event(&events[events.len() - 2].id, None, db).is_err());
+    }
+
+    #[test]
+    fn test_events_follow_renames() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "a.c", "1\n2\n3\n");
+        let git = git::Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = DB::new(db_path.clone()).unwrap();
+
+        update_review_state(mark("a.c", (0, 2), State::Reviewed), &mut db, &git).unwrap();
+        add_comment(
+            "a.c".to_string(),
+            1,
+            "body".to_string(),
+            "bob".to_string(),
+            &mut db,
+        )
+        .unwrap();
+        db.save().unwrap();
+        git::tests::commit_move(&repo_path, "a.c", "b.c");
+        let git = git::Git::new(&repo_path).unwrap();
+        let mut db = DB::new(db_path.clone()).unwrap();
+        transform_review_state(&"b.c".to_string(), None, &mut db, &git).unwrap();
+        db.save().unwrap();
+
+        let db = DB::new(db_path).unwrap();
+        assert!(db.events_of_file("a.c").unwrap().is_empty());
+        let events = db.events_of_file("b.c").unwrap();
+        let actions: Vec<Action> = events.iter().map(|event| event.action.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                Action::Mark(State::Reviewed),
+                Action::AddComment,
+                Action::Transform
+            ]
+        );
+        let event = db.find_event(&events[1].id).unwrap().unwrap();
+        assert_eq!(event.file_name, "b.c");
+    }
+
+    #[test]
+    fn test_revert_permissions() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "a.c", "1\n2\n3\n");
+        let git = git::Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let db = &mut DB::new(db_path).unwrap();
+        let file_name = "a.c";
+        let user = |name: &str, role| User {
+            name: name.to_string(),
+            created_at: 0,
+            role,
+        };
+        let (lead, auditor) = (user("lead", Role::Lead), user("alice", Role::Auditor));
+
+        update_review_state(mark(file_name, (0, 2), State::Reviewed), &mut *db, &git).unwrap();
+        update_metadata(
+            UpdateMetadataRequest {
+                file_name: file_name.to_string(),
+                metadata: Metadata {
+                    priority: Priority::High,
+                    reviewer: "alice".to_string(),
+                    note: String::new(),
+                },
+                author: Some("lead".to_string()),
+            },
+            db,
+        )
+        .unwrap();
+        add_comment(
+            file_name.to_string(),
+            0,
+            "by lead".to_string(),
+            "lead".to_string(),
+            db,
+        )
+        .unwrap();
+        add_comment(
+            file_name.to_string(),
+            1,
+            "by alice".to_string(),
+            "alice".to_string(),
+            db,
+        )
+        .unwrap();
+        let events = db.events_of_file(file_name).unwrap();
+
+        // Reverting the metadata of the lead would unassign the file
+        let error = authorize_revert(&events[1].id, &auditor, db).unwrap_err();
+        assert_eq!(ApiError::from(error).status, StatusCode::FORBIDDEN);
+        assert!(authorize_revert(&events[1].id, &lead, db).is_ok());
+        // Deleting the comment of someone else needs moderation, deleting one's own does not
+        assert!(authorize_revert(&events[2].id, &auditor, db).is_err());
+        assert!(authorize_revert(&events[3].id, &auditor, db).is_ok());
+        // Reverting marks as reviewed clears them
+        assert!(authorize_revert(&events[0].id, &auditor, db).is_err());
+        assert!(authorize_revert(&events[0].id, &lead, db).is_ok());
+        // Reverting marks as ignored only restores reviewed lines
+        update_review_state(mark(file_name, (2, 2), State::Ignored), &mut *db, &git).unwrap();
+        let ignore = db.events_of_file(file_name).unwrap()[4].clone();
+        assert!(authorize_revert(&ignore.id, &auditor, db).is_ok());
+        assert!(authorize_revert(&events[0].id, &auditor, db).is_err());
+        assert!(authorize_revert("unknown", &lead, db).is_err());
+    }
+}
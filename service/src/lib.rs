@@ -1,25 +1,213 @@
 use anyhow::Result;
-use db::DB;
+use config::Config;
 use errors::AuditorError;
-use git::Git;
+use git::{CommitRelation, Git};
+use history::{prior_comments, prior_metadata, prior_review, Action, Event, Prior};
+use line_map::LineMap;
+use policy::{PolicyStatus, SignOffPolicy};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, ops::RangeInclusive};
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use storage::Storage;
+pub mod api_error;
+pub mod auth;
+pub mod changes;
+pub mod cli;
 pub mod config;
 pub mod db;
 pub mod errors;
+pub mod finding;
+pub mod gate;
 pub mod git;
+pub mod history;
+pub mod line_map;
+pub mod lsp;
+pub mod policy;
+pub mod report;
+pub mod sarif;
+pub mod session;
+pub mod sqlite;
+pub mod storage;
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ThreadState {
+    #[default]
+    Open,
+    Resolved,
+    WontFix,
+}
+
+/// The root comment of a thread.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Comment {
     pub id: String,
     pub body: String,
     pub author: String,
+    #[serde(default)]
+    pub replies: Vec<Reply>,
+    #[serde(default)]
+    pub state: ThreadState,
+    // Seconds since the unix epoch, 0 for comments created before threads existed
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+    #[serde(default)]
+    pub resolved_by: Option<String>,
+    #[serde(default)]
+    pub edits: Vec<CommentEdit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Reply {
+    pub id: String,
+    pub body: String,
+    pub author: String,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub edits: Vec<CommentEdit>,
+}
+
+/// The body of a comment as it was before an edit.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CommentEdit {
+    pub body: String,
+    pub editor: String,
+    pub edited_at: u64,
+}
+
+impl Reply {
+    pub fn new(id: String, body: String, author: String) -> Self {
+        Self {
+            id,
+            body,
+            author,
+            created_at: now(),
+            edits: vec![],
+        }
+    }
+}
+
+impl Comment {
+    pub fn new(id: String, body: String, author: String) -> Self {
+        let now = now();
+        Self {
+            id,
+            body,
+            author,
+            replies: vec![],
+            state: ThreadState::Open,
+            created_at: now,
+            updated_at: now,
+            resolved_by: None,
+            edits: vec![],
+        }
+    }
+
+    /// Replaces the body of this comment or of its reply with the id `comment_id` and keeps the
+    /// previous body in the edit history. Returns false if neither has that id.
+    /// Only the author may edit a comment, unless the edit is forced.
+    pub fn edit(
+        &mut self,
+        comment_id: &str,
+        body: String,
+        editor: String,
+        force: bool,
+    ) -> Result<bool> {
+        let (author, current_body, edits) = if self.id == comment_id {
+            (&self.author, &mut self.body, &mut self.edits)
+        } else if let Some(reply) = self.replies.iter_mut().find(|reply| reply.id == comment_id) {
+            (&reply.author, &mut reply.body, &mut reply.edits)
+        } else {
+            return Ok(false);
+        };
+        if *author != editor && !force {
+            return Err(AuditorError::NotCommentAuthor(comment_id.to_string()).into());
+        }
+        let edited_at = now();
+        edits.push(CommentEdit {
+            body: std::mem::replace(current_body, body),
+            editor,
+            edited_at,
+        });
+        self.updated_at = edited_at;
+        Ok(true)
+    }
+
+    /// Resolving records who closed the thread, reopening forgets it.
+    pub fn set_state(&mut self, state: ThreadState, user: Option<String>) {
+        self.resolved_by = match state {
+            ThreadState::Open => None,
+            ThreadState::Resolved | ThreadState::WontFix => user,
+        };
+        self.state = state;
+        self.updated_at = now();
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileComments(pub HashMap<usize, Vec<Comment>>);
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl FileComments {
+    /// Counts the open threads and the threads that were closed, either resolved or won't fix.
+    pub fn thread_counts(&self) -> (usize, usize) {
+        let threads = self.0.values().flatten();
+        let open = threads
+            .clone()
+            .filter(|thread| thread.state == ThreadState::Open)
+            .count();
+        (open, threads.count() - open)
+    }
+
+    /// Returns the author of the comment or reply with the id on the line.
+    pub fn author_of(&self, line_number: usize, comment_id: &str) -> Option<&str> {
+        self.0.get(&line_number)?.iter().find_map(|comment| {
+            if comment.id == comment_id {
+                return Some(comment.author.as_str());
+            }
+            comment
+                .replies
+                .iter()
+                .find(|reply| reply.id == comment_id)
+                .map(|reply| reply.author.as_str())
+        })
+    }
+}
+
+/// Seconds since the unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// The name a file is stored under: its path relative to the repository at `repository_path`,
+/// without a leading `./` or `/`, the way git names it. The service, the CLI, the language server
+/// and the gate all name files through here, so that their names of a file agree.
+pub fn file_key(path: &str, repository_path: &str) -> String {
+    let repository_path = repository_path.trim_end_matches('/');
+    let relative = match path.strip_prefix(repository_path) {
+        Some(relative)
+            if !repository_path.is_empty()
+                && (relative.is_empty() || relative.starts_with('/')) =>
+        {
+            relative
+        }
+        _ => path,
+    };
+    relative
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum Priority {
     Unspecified,
     High,
@@ -28,20 +216,74 @@ pub enum Priority {
     Ignore,
 }
 
+impl FromStr for Priority {
+    type Err = AuditorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "unspecified" => Ok(Priority::Unspecified),
+            "high" => Ok(Priority::High),
+            "medium" => Ok(Priority::Medium),
+            "low" => Ok(Priority::Low),
+            "ignore" => Ok(Priority::Ignore),
+            _ => Err(AuditorError::InvalidPriority(s.to_string())),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Metadata {
-    priority: Priority,
-    reviewer: String,
-    note: String,
+    pub priority: Priority,
+    pub reviewer: String,
+    pub note: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LatestFileInfo {
+    pub file_name: String,
+    pub line_reviews: StoredReviewForFile,
+    pub comments: HashMap<usize, Vec<Comment>>,
+    pub metadata: Option<Metadata>,
+    pub aliases: Vec<String>,
+    // Percentage of the file reviewed by each reviewer
+    pub reviewer_coverage: HashMap<String, usize>,
+    // Only set for files that a sign-off policy applies to
+    pub policy_status: Option<PolicyStatus>,
+    pub open_threads: usize,
+    // Threads that were resolved or won't be fixed
+    pub resolved_threads: usize,
+    // Only set in a diff audit, the lines that changed and that coverage is relative to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Vec<RangeInclusive<usize>>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct UpdateMetadataRequest {
     pub file_name: String,
     pub metadata: Metadata,
+    // Who made the change, recorded in the history
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+impl UpdateMetadataRequest {
+    /// Whether the request changes the priority or the reviewer of the file.
+    pub fn assigns(&self, current: Option<&Metadata>) -> bool {
+        match current {
+            Some(current) => {
+                self.metadata.priority != current.priority
+                    || self.metadata.reviewer != current.reviewer
+            }
+            // The dashboard sends "Unassigned" when no reviewer is selected
+            None => {
+                self.metadata.priority != Priority::Unspecified
+                    || !matches!(self.metadata.reviewer.as_str(), "" | "Unassigned")
+            }
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum State {
     Reviewed,
     Modified,
@@ -49,19 +291,99 @@ pub enum State {
     Cleared,
 }
 
-#[derive(Deserialize, Debug)]
+impl FromStr for State {
+    type Err = AuditorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reviewed" => Ok(State::Reviewed),
+            "modified" => Ok(State::Modified),
+            "ignored" => Ok(State::Ignored),
+            "cleared" => Ok(State::Cleared),
+            _ => Err(AuditorError::InvalidReviewState(s.to_string())),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UpdateReviewState {
     pub file_name: String,
     start_line: usize,
     end_line: usize,
     review_state: State,
     total_lines: usize,
+    // Who reviewed the lines, requests without it only update the shared reviewed lines
+    #[serde(default)]
+    pub reviewer: Option<String>,
 }
 
 impl UpdateReviewState {
+    pub fn new(
+        file_name: String,
+        lines: RangeInclusive<usize>,
+        review_state: State,
+        total_lines: usize,
+        reviewer: Option<String>,
+    ) -> Self {
+        Self {
+            file_name,
+            start_line: *lines.start(),
+            end_line: *lines.end(),
+            review_state,
+            total_lines,
+            reviewer,
+        }
+    }
+
     fn range(&self) -> RangeInclusive<usize> {
         RangeInclusive::new(self.start_line, self.end_line)
     }
+
+    /// Whether the request removes reviewed lines, which marking them as anything but reviewed
+    /// does.
+    pub fn unreviews(&self, db: &dyn Storage, git: &Git) -> Result<bool> {
+        if self.review_state == State::Reviewed {
+            return Ok(false);
+        }
+        let current = match db.latest_reviewed_commit(&self.file_name)? {
+            Some(commit) => db.review_status_of_file_at(&self.file_name, &commit)?,
+            None => None,
+        };
+        let Some(current) = current else {
+            return Ok(false);
+        };
+        let mut updated = current.clone();
+        for changes in self.clone().at_head(git)? {
+            updated.mark_lines(&changes.review_state, &changes.range());
+        }
+        Ok(current.unreviewed_in(&updated))
+    }
+
+    /// Splits the request into requests for the lines at HEAD. Reviews are stored at HEAD, so
+    /// the lines of a file with uncommitted changes are moved to where they are at HEAD. Lines
+    /// that were not committed yet cannot be marked.
+    fn at_head(mut self, git: &Git) -> Result<Vec<UpdateReviewState>> {
+        let Some(file_diff) = git.uncommitted_changes(&self.file_name)? else {
+            return Ok(vec![self]);
+        };
+        let ranges = match file_diff.total_lines {
+            // The file does not exist at HEAD
+            Some(0) => vec![],
+            _ => file_diff.line_map().map_range(&self.range()),
+        };
+        if ranges.is_empty() {
+            return Err(AuditorError::UncommittedLines(self.file_name).into());
+        }
+        self.total_lines = file_diff.total_lines.unwrap_or(self.total_lines);
+        Ok(ranges
+            .into_iter()
+            .map(|range| Self {
+                start_line: *range.start(),
+                end_line: *range.end(),
+                ..self.clone()
+            })
+            .collect())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -70,6 +392,10 @@ pub struct StoredReviewForFile {
     pub modified: Vec<RangeInclusive<usize>>,
     pub ignored: Vec<RangeInclusive<usize>>,
     pub total_lines: usize,
+    // Maps a reviewer to the lines they reviewed, `reviewed` is the union of these and the lines
+    // that were reviewed anonymously
+    #[serde(default)]
+    pub reviewers: HashMap<String, Vec<RangeInclusive<usize>>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -93,18 +419,43 @@ pub struct LineDiff {
     new: Option<u32>,
 }
 
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    line_diffs: Vec<LineDiff>,
+    // Line count of the file in the new commit, if known
+    total_lines: Option<usize>,
+    // Path of the file in the old commit, None if the file is new
+    old_file_name: Option<String>,
+    // The old file still exists and this one is a copy of it
+    is_copy: bool,
+}
+
+impl FileDiff {
+    pub fn line_map(&self) -> LineMap {
+        LineMap::new(&self.line_diffs)
+    }
+
+    /// Returns the old path if the file was moved or copied from somewhere else.
+    pub fn moved_from(&self, file_name: &String) -> Option<&String> {
+        self.old_file_name
+            .as_ref()
+            .filter(|old_file_name| *old_file_name != file_name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Diff {
-    files: HashMap<String, Vec<LineDiff>>,
+    files: HashMap<String, FileDiff>,
 }
 
 impl StoredReviewForFile {
-    fn default() -> Self {
+    pub(crate) fn default() -> Self {
         Self {
             reviewed: vec![],
             modified: vec![],
             ignored: vec![],
             total_lines: 0,
+            reviewers: HashMap::default(),
         }
     }
 
@@ -120,6 +471,79 @@ impl StoredReviewForFile {
         instance
     }
 
+    /// Marks the lines as reviewed and attributes them to `reviewer`.
+    pub(crate) fn mark_reviewed_by(&mut self, reviewer: &str, new_range: &RangeInclusive<usize>) {
+        self.mark_lines(&State::Reviewed, new_range);
+        let ranges = self.reviewers.entry(reviewer.to_string()).or_default();
+        *ranges = Self::add_range_to_list(new_range.clone(), ranges.clone());
+    }
+
+    /// Lines reviewed by `reviewer`.
+    pub fn reviewed_by(&self, reviewer: &str) -> Vec<RangeInclusive<usize>> {
+        self.reviewers.get(reviewer).cloned().unwrap_or_default()
+    }
+
+    /// Lines reviewed by at least `count` different reviewers.
+    pub fn reviewed_by_at_least(&self, count: usize) -> Vec<RangeInclusive<usize>> {
+        self.reviewer_counts()
+            .into_iter()
+            .filter(|(_, reviewers)| *reviewers >= count)
+            .fold(vec![], |acc, (range, _)| {
+                Self::add_range_to_list(range, acc)
+            })
+    }
+
+    /// Lines reviewed by exactly one reviewer.
+    pub fn reviewed_by_only_one(&self) -> Vec<RangeInclusive<usize>> {
+        self.reviewer_counts()
+            .into_iter()
+            .filter(|(_, reviewers)| *reviewers == 1)
+            .fold(vec![], |acc, (range, _)| {
+                Self::add_range_to_list(range, acc)
+            })
+    }
+
+    /// Percentage of the file reviewed by each reviewer.
+    pub fn reviewer_coverage(&self) -> HashMap<String, usize> {
+        self.reviewers
+            .iter()
+            .map(|(reviewer, ranges)| {
+                let lines: usize = ranges
+                    .iter()
+                    .map(|range| range.end() - range.start() + 1)
+                    .sum();
+                let percent = match self.total_lines {
+                    0 => 0,
+                    total_lines => std::cmp::min(100, 100 * lines / total_lines),
+                };
+                (reviewer.clone(), percent)
+            })
+            .collect()
+    }
+
+    /// Splits the attributed lines into ranges with the number of reviewers of each range.
+    fn reviewer_counts(&self) -> Vec<(RangeInclusive<usize>, usize)> {
+        // +1 where a reviewed range starts and -1 right after it ends
+        let mut boundaries: Vec<(usize, isize)> = self
+            .reviewers
+            .values()
+            .flatten()
+            .flat_map(|range| [(*range.start(), 1), (range.end().saturating_add(1), -1)])
+            .collect();
+        boundaries.sort_unstable();
+
+        let mut counts = vec![];
+        let (mut start, mut reviewers) = (0, 0);
+        for (line, delta) in boundaries {
+            if line > start && reviewers > 0 {
+                counts.push((RangeInclusive::new(start, line - 1), reviewers as usize));
+            }
+            reviewers += delta;
+            start = line;
+        }
+        counts
+    }
+
     fn mark_lines(&mut self, state: &State, new_range: &RangeInclusive<usize>) {
         // Add the changes to the current field
         match state {
@@ -155,6 +579,86 @@ impl StoredReviewForFile {
                 self.ignored = Self::remove_overlapping_range(new_range, &self.ignored);
             }
         };
+
+        // Lines that are no longer reviewed lose their reviewers
+        if !matches!(state, State::Reviewed) {
+            for ranges in self.reviewers.values_mut() {
+                *ranges = Self::remove_overlapping_range(new_range, ranges);
+            }
+            self.reviewers.retain(|_, ranges| !ranges.is_empty());
+        }
+    }
+
+    /// Whether some of the reviewed lines are no longer reviewed in `updated`.
+    pub(crate) fn unreviewed_in(&self, updated: &Self) -> bool {
+        updated
+            .reviewed
+            .iter()
+            .fold(self.reviewed.clone(), |remaining, range| {
+                Self::remove_overlapping_range(range, &remaining)
+            })
+            .iter()
+            .any(|range| range.start() <= range.end())
+    }
+
+    /// Gives the lines of `range` the marks and reviewers they have in `other` and keeps the
+    /// marks of all other lines.
+    pub(crate) fn restore_lines(&mut self, other: &Self, range: &RangeInclusive<usize>) {
+        let within = |ranges: &[RangeInclusive<usize>]| -> Vec<RangeInclusive<usize>> {
+            ranges
+                .iter()
+                .map(|r| *r.start().max(range.start())..=*r.end().min(range.end()))
+                .filter(|r| r.start() <= r.end())
+                .collect()
+        };
+        self.mark_lines(&State::Cleared, range);
+        for restored in within(&other.reviewed) {
+            self.reviewed = Self::add_range_to_list(restored, self.reviewed.clone());
+        }
+        for restored in within(&other.modified) {
+            self.modified = Self::add_range_to_list(restored, self.modified.clone());
+        }
+        for restored in within(&other.ignored) {
+            self.ignored = Self::add_range_to_list(restored, self.ignored.clone());
+        }
+        for (reviewer, ranges) in &other.reviewers {
+            for restored in within(ranges) {
+                let current = self.reviewers.entry(reviewer.clone()).or_default();
+                *current = Self::add_range_to_list(restored, current.clone());
+            }
+        }
+    }
+
+    /// Moves all ranges along with their code and marks the added lines as modified.
+    fn transform(&self, line_map: &LineMap, total_lines: Option<usize>) -> Self {
+        let shift = |ranges: &[RangeInclusive<usize>]| {
+            ranges
+                .iter()
+                .flat_map(|range| line_map.map_range(range))
+                .fold(vec![], |acc, range| Self::add_range_to_list(range, acc))
+        };
+        let mut transformed = Self {
+            reviewed: shift(&self.reviewed),
+            modified: shift(&self.modified),
+            ignored: shift(&self.ignored),
+            total_lines: total_lines.unwrap_or(self.total_lines),
+            reviewers: self
+                .reviewers
+                .iter()
+                .map(|(reviewer, ranges)| (reviewer.clone(), shift(ranges)))
+                .filter(|(_, ranges)| !ranges.is_empty())
+                .collect(),
+        };
+        for added_range in line_map.added_ranges() {
+            transformed.mark_lines(&State::Modified, &added_range);
+        }
+        if let Some(total_lines) = total_lines {
+            transformed.mark_lines(
+                &State::Cleared,
+                &RangeInclusive::new(total_lines, usize::MAX),
+            );
+        }
+        transformed
     }
 
     fn add_range_to_list(
@@ -210,13 +714,17 @@ impl StoredReviewForFile {
     }
 }
 
-pub fn get_review_state(file_name: &String, db: &DB, git: &Git) -> Result<StoredReviewForFile> {
-    let commit = db.latest_reviewed_commit(file_name);
+pub fn get_review_state(
+    file_name: &String,
+    db: &dyn Storage,
+    git: &Git,
+) -> Result<StoredReviewForFile> {
+    let commit = db.latest_reviewed_commit(file_name)?;
     if let Some(commit) = commit {
         if git.is_commit_older_than_latest(&commit)? {
             return Err(AuditorError::OldCommitError(commit.to_string()).into());
         }
-        let state = db.review_status_of_commit(&Some(commit));
+        let state = db.review_status_of_commit(&Some(commit))?;
         Ok(match state.files.get(file_name) {
             Some(state) => state.clone(),
             None => StoredReviewForFile::default(),
@@ -226,24 +734,101 @@ pub fn get_review_state(file_name: &String, db: &DB, git: &Git) -> Result<Stored
     }
 }
 
+/// Lists the files in scope with the sign-off policy applied, or only `file_name` if given.
+pub fn infos_in_scope(
+    config: &Config,
+    db: &dyn Storage,
+    file_name: Option<&str>,
+) -> Result<Vec<LatestFileInfo>> {
+    let policy = SignOffPolicy::from_config(config);
+    let mut infos = vec![];
+    for mut info in db.latest_infos()? {
+        if !config.in_scope(&info.file_name)
+            || file_name.is_some_and(|file_name| info.file_name != file_name)
+        {
+            continue;
+        }
+        policy.apply(&mut info);
+        infos.push(info);
+    }
+    Ok(infos)
+}
+
 pub fn transform_review_state(
     file_name: &String,
-    db: &mut DB,
+    author: Option<String>,
+    db: &mut dyn Storage,
     git: &Git,
 ) -> Result<StoredReviewForFile> {
-    let commit = db.latest_reviewed_commit(file_name);
-    if let Some(commit) = &commit {
-        let same_commit = commit == &git.current_commit()?;
-        let older_commit = git.is_commit_older_than_latest(commit)?;
-        if !(same_commit || older_commit) {
-            return Err(AuditorError::ShouldUpdateToLatest(commit.to_string()).into());
+    let mut commit = commit_to_transform_from(file_name, db, git)?;
+    let mut state = db.review_status_of_commit(&commit)?;
+    if let Some(stored_commit) = commit.clone() {
+        // Diverged and unrelated commits are diffed directly, comparing trees needs no ancestry
+        match git.relation_to_head(&stored_commit)? {
+            CommitRelation::Descendant => {
+                return Err(AuditorError::ShouldUpdateToLatest(stored_commit).into());
+            }
+            CommitRelation::Missing => {
+                // The commit is gone, e.g. rebased away and garbage collected, so start over from
+                // the closest earlier review of the file that HEAD still builds on
+                let fallback = git
+                    .closest_reachable(&db.reviewed_commits_of_file(file_name)?)?
+                    .ok_or(AuditorError::UnknownCommit(stored_commit))?;
+                state = db.review_status_of_commit(&Some(fallback.clone()))?;
+                if let Some(review) = db.review_status_of_file_at(file_name, &fallback)? {
+                    state.files.insert(file_name.clone(), review);
+                }
+                commit = Some(fallback);
+            }
+            _ => (),
         }
     }
-    let mut state = db.review_status_of_commit(&commit);
-    let diff = git.diff_current_and_commit(commit, (state.exclusions).as_ref())?;
-    if diff.is_some() {
-        state = transform_reviews(&state, diff);
-        db.store_review_status(&git.current_commit()?, &state)?;
+    let diff = git.diff_current_and_commit(commit.clone(), (state.exclusions).as_ref())?;
+    if let Some(diff) = diff {
+        let mut copies = vec![];
+        let mut events = vec![];
+        for (diff_file_name, file_diff) in &diff.files {
+            if let Some(old_file_name) = file_diff.moved_from(diff_file_name) {
+                if file_diff.is_copy {
+                    copies.push((diff_file_name.clone(), old_file_name.clone()));
+                } else {
+                    db.rename_file(old_file_name, diff_file_name)?;
+                }
+            }
+
+            // Only files with reviews or comments get an event that can undo the transform
+            let prior_file_review = file_diff
+                .old_file_name
+                .as_ref()
+                .and_then(|old_file_name| state.files.get(old_file_name))
+                .cloned();
+            let prior_file_comments = prior_comments(db, diff_file_name, None)?;
+            let has_comments =
+                matches!(&prior_file_comments, Prior::Comments(comments) if !comments.0.is_empty());
+            if prior_file_review.is_some() || has_comments {
+                let prior_file_review = Prior::Review {
+                    commit: commit.clone(),
+                    review: prior_file_review,
+                };
+                events.push(Event::new(
+                    diff_file_name,
+                    author.clone(),
+                    Action::Transform,
+                    vec![prior_file_review, prior_file_comments],
+                ));
+            }
+
+            db.transform_comments(diff_file_name, &file_diff.line_map(), file_diff.total_lines)?;
+        }
+        state = transform_reviews(&state, Some(diff));
+        let current_commit = git.current_commit()?;
+        db.store_review_status(&current_commit, &state)?;
+        for (file_name, old_file_name) in copies {
+            db.add_alias(&file_name, &old_file_name)?;
+        }
+        for event in events {
+            db.record_event(event.with_commit(&current_commit))?;
+        }
     }
     Ok(match state.files.get(file_name) {
         Some(state) => state.clone(),
@@ -251,17 +836,61 @@ pub fn transform_review_state(
     })
 }
 
-pub fn update_review_state(changes: UpdateReviewState, db: &mut DB, git: &Git) -> Result<()> {
-    let commit = db.latest_reviewed_commit(&changes.file_name);
+/// Finds the commit at which the reviews of a file were last stored, following the file back to
+/// its old path if it has been moved since then.
+fn commit_to_transform_from(
+    file_name: &String,
+    db: &dyn Storage,
+    git: &Git,
+) -> Result<Option<String>> {
+    let commit = db.latest_reviewed_commit(file_name)?;
+    if commit.is_some() {
+        return Ok(commit);
+    }
+    for commit in db.reviewed_commits()? {
+        if git.relation_to_head(&commit)? == CommitRelation::Missing {
+            continue;
+        }
+        let diff = git.diff_current_and_commit(Some(commit.clone()), &vec![])?;
+        let is_moved = diff
+            .and_then(|diff| diff.files.get(file_name).cloned())
+            .is_some_and(|file_diff| file_diff.moved_from(file_name).is_some());
+        if is_moved {
+            return Ok(Some(commit));
+        }
+    }
+    Ok(None)
+}
+
+pub fn update_review_state(
+    changes: UpdateReviewState,
+    db: &mut dyn Storage,
+    git: &Git,
+) -> Result<()> {
+    let commit = db.latest_reviewed_commit(&changes.file_name)?;
     if let Some(commit) = &commit {
         if git.is_commit_older_than_latest(commit)? {
             return Err(AuditorError::OldCommitError(commit.to_string()).into());
         }
     }
-    let state = db.review_status_of_commit(&commit);
-    let new_state = update_reviews(&state, changes);
-    db.store_review_status(&git.current_commit()?, &new_state)?;
-    Ok(())
+    let event = Event::new(
+        &changes.file_name,
+        changes.reviewer.clone(),
+        Action::Mark(changes.review_state.clone()),
+        vec![prior_review(db, &changes.file_name)?],
+    );
+    let updates = changes.at_head(git)?;
+    let first_line = updates.first().expect("at least one update").start_line;
+    let last_line = updates.last().expect("at least one update").end_line;
+    let event = event.with_range(first_line..=last_line);
+    let state = db.review_status_of_commit(&commit)?;
+    let mut new_state = state;
+    for changes in updates {
+        new_state = update_reviews(&new_state, changes);
+    }
+    let current_commit = git.current_commit()?;
+    db.store_review_status(&current_commit, &new_state)?;
+    db.record_event(event.with_commit(&current_commit))
 }
 
 fn transform_reviews(
@@ -273,27 +902,26 @@ fn transform_reviews(
     }
     let diff = diff.unwrap();
     let mut new_state = current_state.clone();
-    for (file_name, line_diffs) in diff.files {
-        let file_review = new_state
-            .files
-            .entry(file_name)
-            .or_insert(StoredReviewForFile::default());
-        for line_diff in line_diffs {
-            if let Some(new_line) = line_diff.new {
-                let new_line_number: usize = new_line.try_into().unwrap();
-                let new_line_number = new_line_number - 1;
-                file_review.mark_lines(
-                    &State::Modified,
-                    &RangeInclusive::new(new_line_number, new_line_number),
-                );
-            }
-            if line_diff.old.is_some() && line_diff.new.is_none() {
-                // TODO: for now going with a simplistic approach and ignoring the deleted lines.
-                // A good portion of these cases are "modifications" meaning that a line was deleted and another line was added in its place.
-                // println!("The old line {} is deleted.", line_diff.old.unwrap());
+    // Moved files leave their old path, they are re-added under the new one below
+    for (file_name, file_diff) in &diff.files {
+        if let Some(old_file_name) = file_diff.moved_from(file_name) {
+            if !file_diff.is_copy {
+                new_state.files.remove(old_file_name);
             }
         }
     }
+    for (file_name, file_diff) in diff.files {
+        let file_review = file_diff
+            .old_file_name
+            .as_ref()
+            .and_then(|old_file_name| current_state.files.get(old_file_name))
+            .cloned()
+            .unwrap_or(StoredReviewForFile::default());
+        new_state.files.insert(
+            file_name,
+            file_review.transform(&file_diff.line_map(), file_diff.total_lines),
+        );
+    }
     new_state
 }
 
@@ -319,11 +947,125 @@ fn update_reviews(
                 .insert(changes.file_name.clone(), new_file_review);
         }
     };
+    if let (State::Reviewed, Some(reviewer)) = (&changes.review_state, &changes.reviewer) {
+        if let Some(file_reviews) = new_state.files.get_mut(&changes.file_name) {
+            file_reviews.mark_reviewed_by(reviewer, &changes.range());
+        }
+    }
     new_state
 }
 
-pub fn update_metadata(request: UpdateMetadataRequest, db: &mut DB) -> Result<()> {
-    db.set_metadata(&request.file_name, request.metadata)
+pub fn update_metadata(request: UpdateMetadataRequest, db: &mut dyn Storage) -> Result<()> {
+    let event = Event::new(
+        &request.file_name,
+        request.author,
+        Action::Metadata,
+        vec![prior_metadata(db, &request.file_name)?],
+    );
+    db.set_metadata(&request.file_name, request.metadata)?;
+    db.record_event(event)
+}
+
+pub fn add_comment(
+    file_name: String,
+    line_number: usize,
+    body: String,
+    author: String,
+    db: &mut dyn Storage,
+) -> Result<String> {
+    let lines = RangeInclusive::new(line_number, line_number);
+    let event = Event::new(
+        &file_name,
+        Some(author.clone()),
+        Action::AddComment,
+        vec![prior_comments(db, &file_name, Some(&lines))?],
+    )
+    .with_range(lines);
+    let comment_id = db.add_new_comment(file_name, line_number, body, author)?;
+    db.record_event(event)?;
+    Ok(comment_id)
+}
+
+pub fn delete_comment(
+    file_name: String,
+    comment_id: String,
+    line_number: usize,
+    author: Option<String>,
+    db: &mut dyn Storage,
+) -> Result<()> {
+    let lines = RangeInclusive::new(line_number, line_number);
+    let event = Event::new(
+        &file_name,
+        author,
+        Action::DeleteComment,
+        vec![prior_comments(db, &file_name, Some(&lines))?],
+    )
+    .with_range(lines);
+    db.delete_comment(file_name, comment_id, line_number)?;
+    db.record_event(event)
+}
+
+pub fn update_comment(
+    file_name: String,
+    comment_id: String,
+    line_number: usize,
+    body: String,
+    editor: String,
+    force: bool,
+    db: &mut dyn Storage,
+) -> Result<()> {
+    let lines = RangeInclusive::new(line_number, line_number);
+    let event = Event::new(
+        &file_name,
+        Some(editor.clone()),
+        Action::UpdateComment,
+        vec![prior_comments(db, &file_name, Some(&lines))?],
+    )
+    .with_range(lines);
+    db.update_comment(file_name, comment_id, line_number, body, editor, force)?;
+    db.record_event(event)
+}
+
+pub fn reply_to_comment(
+    file_name: String,
+    line_number: usize,
+    thread_id: String,
+    body: String,
+    author: String,
+    db: &mut dyn Storage,
+) -> Result<String> {
+    let lines = RangeInclusive::new(line_number, line_number);
+    let event = Event::new(
+        &file_name,
+        Some(author.clone()),
+        Action::Reply,
+        vec![prior_comments(db, &file_name, Some(&lines))?],
+    )
+    .with_range(lines);
+    let reply_id = db.add_reply(&file_name, line_number, &thread_id, body, author)?;
+    db.record_event(event)?;
+    Ok(reply_id)
+}
+
+/// Resolves, dismisses or reopens the thread started by the comment with id `thread_id`.
+pub fn set_thread_state(
+    file_name: String,
+    line_number: usize,
+    thread_id: String,
+    state: ThreadState,
+    author: Option<String>,
+    db: &mut dyn Storage,
+) -> Result<()> {
+    let lines = RangeInclusive::new(line_number, line_number);
+    let event = Event::new(
+        &file_name,
+        author.clone(),
+        Action::SetThreadState(state),
+        vec![prior_comments(db, &file_name, Some(&lines))?],
+    )
+    .with_range(lines);
+    db.set_thread_state(&file_name, line_number, &thread_id, state, author)?;
+    db.record_event(event)
 }
 
 #[cfg(test)]
@@ -351,6 +1093,7 @@ mod tests {
                 modified: ranges(vec![(1, 1)]),
                 ignored: ranges(vec![(2, 2)]),
                 total_lines: 0, // TODO: add tests for this case
+                reviewers: HashMap::default(),
             },
         );
         let current_state = &StoredReviewForCommit {
@@ -367,10 +1110,15 @@ mod tests {
         let mut diff_files = HashMap::default();
         diff_files.insert(
             file2.clone(),
-            vec![LineDiff {
-                old: None,
-                new: Some(3),
-            }],
+            FileDiff {
+                line_diffs: vec![LineDiff {
+                    old: None,
+                    new: Some(3),
+                }],
+                total_lines: None,
+                old_file_name: None,
+                is_copy: false,
+            },
         );
         let diff = Some(Diff { files: diff_files });
         let state = &transform_reviews(&state, diff);
@@ -384,17 +1132,173 @@ mod tests {
         let mut diff_files = HashMap::default();
         diff_files.insert(
             file1.clone(),
-            vec![LineDiff {
-                old: None,
-                new: Some(1),
-            }],
+            FileDiff {
+                line_diffs: vec![LineDiff {
+                    old: None,
+                    new: Some(1),
+                }],
+                total_lines: None,
+                old_file_name: Some(file1.clone()),
+                is_copy: false,
+            },
         );
         let diff = Some(Diff { files: diff_files });
         let state = &transform_reviews(state, diff);
-        assert_eq!(access(&file1, state).reviewed, ranges(vec![]));
-        assert_eq!(access(&file1, state).modified, ranges(vec![(0, 1)]));
+        assert_eq!(access(&file1, state).reviewed, ranges(vec![(1, 1)]));
+        assert_eq!(access(&file1, state).modified, ranges(vec![(0, 0), (2, 2)]));
+        assert_eq!(access(&file1, state).ignored, ranges(vec![(3, 3)]));
         assert_eq!(access(&file2, state).reviewed, ranges(vec![]));
         assert_eq!(access(&file2, state).modified, ranges(vec![(2, 2)]));
+
+        // ----------- test: Deleted lines shrink ranges and the rest is clamped to the file
+        let mut diff_files = HashMap::default();
+        diff_files.insert(
+            file1.clone(),
+            FileDiff {
+                line_diffs: vec![
+                    LineDiff {
+                        old: Some(2),
+                        new: None,
+                    },
+                    LineDiff {
+                        old: Some(3),
+                        new: None,
+                    },
+                ],
+                total_lines: Some(1),
+                old_file_name: Some(file1.clone()),
+                is_copy: false,
+            },
+        );
+        let diff = Some(Diff { files: diff_files });
+        let state = &transform_reviews(state, diff);
+        assert_eq!(access(&file1, state).reviewed, ranges(vec![]));
+        assert_eq!(access(&file1, state).modified, ranges(vec![(0, 0)]));
+        assert_eq!(access(&file1, state).ignored, ranges(vec![]));
+        assert_eq!(access(&file1, state).total_lines, 1);
+
+        // ----------- test: Reviews follow a moved file, and are shared with a copied one
+        let file3 = "file3".to_string();
+        let mut diff_files = HashMap::default();
+        diff_files.insert(
+            file3.clone(),
+            FileDiff {
+                line_diffs: vec![],
+                total_lines: None,
+                old_file_name: Some(file2.clone()),
+                is_copy: false,
+            },
+        );
+        diff_files.insert(
+            file1.clone() + ".copy",
+            FileDiff {
+                line_diffs: vec![],
+                total_lines: None,
+                old_file_name: Some(file1.clone()),
+                is_copy: true,
+            },
+        );
+        let diff = Some(Diff { files: diff_files });
+        let state = &transform_reviews(state, diff);
+        assert!(!state.files.contains_key(&file2));
+        assert_eq!(access(&file3, state).modified, ranges(vec![(2, 2)]));
+        assert_eq!(access(&file1, state).modified, ranges(vec![(0, 0)]));
+        assert_eq!(
+            access(&(file1.clone() + ".copy"), state).modified,
+            ranges(vec![(0, 0)])
+        );
+    }
+
+    #[test]
+    fn test_transform_from_missing_commit() {
+        let repo_path = git::tests::init_repo();
+        let first = git::tests::commit_file(&repo_path, "a.c", "1\n2\n");
+        git::tests::commit_file(&repo_path, "a.c", "0\n1\n2\n");
+        let git = Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = db::DB::new(db_path).unwrap();
+
+        let file_name = "a.c".to_string();
+        let mut files = HashMap::default();
+        files.insert(
+            file_name.clone(),
+            StoredReviewForFile::new(&State::Reviewed, range((0, 1)), 3),
+        );
+        let state = StoredReviewForCommit {
+            files,
+            exclusions: vec![],
+        };
+        db.store_review_status(&first, &state).unwrap();
+        // Reviewed later at a commit that was rebased away
+        let missing = "0123456789012345678901234567890123456789".to_string();
+        db.store_review_status(&missing, &state).unwrap();
+
+        let transformed = transform_review_state(&file_name, None, &mut db, &git).unwrap();
+        assert_eq!(transformed.reviewed, ranges(vec![(1, 2)]));
+        assert_eq!(transformed.modified, ranges(vec![(0, 0)]));
+        assert_eq!(
+            db.latest_reviewed_commit(&file_name).unwrap(),
+            Some(git.current_commit().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_file_key() {
+        for path in ["/repo/src/a.rs", "src/a.rs", "./src/a.rs", "/src/a.rs"] {
+            assert_eq!(file_key(path, "/repo"), "src/a.rs");
+            assert_eq!(file_key(path, "/repo/"), "src/a.rs");
+        }
+        // Only whole directories of the repository path are removed
+        assert_eq!(file_key("/repository/a.rs", "/repo"), "repository/a.rs");
+        assert_eq!(file_key("/repo/a.rs", ""), "repo/a.rs");
+    }
+
+    #[test]
+    fn test_mark_uncommitted_changes() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "a.c", "1\n2\n3\n");
+        let git = Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = db::DB::new(db_path).unwrap();
+        let file_name = "a.c".to_string();
+        let path = std::path::Path::new(&repo_path).join(&file_name);
+        let mark = |lines: RangeInclusive<usize>, total_lines: usize| {
+            UpdateReviewState::new(file_name.clone(), lines, State::Reviewed, total_lines, None)
+        };
+
+        // The line added on top is not committed, the marked lines are stored where they are at
+        // HEAD
+        std::fs::write(&path, "0\n1\n2\n3\n").unwrap();
+        update_review_state(mark(1..=2, 5), &mut db, &git).unwrap();
+        let review = get_review_state(&file_name, &db, &git).unwrap();
+        assert_eq!(review.reviewed, ranges(vec![(0, 1)]));
+        assert_eq!(review.total_lines, 4);
+        // Clients send absolute paths, which name the file like git does once turned into a key
+        let key = file_key(&format!("{repo_path}/a.c"), &repo_path);
+        let file_diff = git.uncommitted_changes(&key).unwrap().unwrap();
+        assert_eq!(file_diff.line_map().map_range(&(1..=2)), vec![0..=1]);
+        let err = update_review_state(mark(0..=0, 5), &mut db, &git).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AuditorError>(),
+            Some(AuditorError::UncommittedLines(_))
+        ));
+
+        // Staged changes are not committed either
+        std::fs::write(&path, "1\n2\nx\n3\n").unwrap();
+        let repo = git2::Repository::open(&repo_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new(&file_name)).unwrap();
+        index.write().unwrap();
+        update_review_state(mark(2..=3, 5), &mut db, &git).unwrap();
+        let review = get_review_state(&file_name, &db, &git).unwrap();
+        assert_eq!(review.reviewed, ranges(vec![(0, 2)]));
+
+        // Files that were never committed have no lines at HEAD
+        std::fs::write(std::path::Path::new(&repo_path).join("b.c"), "1\n").unwrap();
+        let untracked = UpdateReviewState::new("b.c".to_string(), 0..=0, State::Reviewed, 2, None);
+        assert!(update_review_state(untracked, &mut db, &git).is_err());
     }
 
     #[test]
@@ -408,6 +1312,7 @@ mod tests {
                 modified: vec![RangeInclusive::new(1, 1)],
                 ignored: vec![RangeInclusive::new(2, 2)],
                 total_lines: 0, // TODO: add tests for this case
+                reviewers: HashMap::default(),
             },
         );
         let current_state = &StoredReviewForCommit {
@@ -421,6 +1326,7 @@ mod tests {
             end_line: 5,
             review_state: State::Reviewed,
             total_lines: 0,
+            reviewer: None,
         };
         let state = &update_reviews(current_state, changes);
         let access = |file, state: &StoredReviewForCommit| state.files.get(file).unwrap().clone();
@@ -435,6 +1341,7 @@ mod tests {
             end_line: 4,
             review_state: State::Modified,
             total_lines: 0,
+            reviewer: None,
         };
         let state = &update_reviews(state, changes);
         assert_eq!(access(&file1, state).reviewed, ranges(vec![(0, 0), (5, 5)]));
@@ -448,6 +1355,7 @@ mod tests {
             end_line: 3,
             review_state: State::Reviewed,
             total_lines: 0,
+            reviewer: None,
         };
         let state = &update_reviews(state, changes);
         assert_eq!(
@@ -464,6 +1372,7 @@ mod tests {
             end_line: 5,
             review_state: State::Cleared,
             total_lines: 0,
+            reviewer: None,
         };
         let state = &update_reviews(state, changes);
         assert_eq!(access(&file1, state).reviewed, ranges(vec![(0, 0)]));
@@ -479,6 +1388,61 @@ mod tests {
         // );
     }
 
+    #[test]
+    fn test_reviewer_coverage() {
+        let file1 = "file1".to_string();
+        let mark =
+            |state: &StoredReviewForCommit, r: (usize, usize), review_state, reviewer: &str| {
+                let changes = UpdateReviewState {
+                    file_name: file1.clone(),
+                    start_line: r.0,
+                    end_line: r.1,
+                    review_state,
+                    total_lines: 10,
+                    reviewer: Some(reviewer.to_string()),
+                };
+                update_reviews(state, changes)
+            };
+        let state = StoredReviewForCommit::new(vec![]);
+        let state = mark(&state, (0, 5), State::Reviewed, "alice");
+        let state = mark(&state, (3, 7), State::Reviewed, "bob");
+        let file_reviews = state.files.get(&file1).unwrap();
+        assert_eq!(file_reviews.reviewed, ranges(vec![(0, 7)]));
+        assert_eq!(file_reviews.reviewed_by("alice"), ranges(vec![(0, 5)]));
+        assert_eq!(file_reviews.reviewed_by("carol"), ranges(vec![]));
+        assert_eq!(file_reviews.reviewed_by_at_least(2), ranges(vec![(3, 5)]));
+        assert_eq!(
+            file_reviews.reviewed_by_only_one(),
+            ranges(vec![(0, 2), (6, 7)])
+        );
+        assert_eq!(
+            file_reviews.reviewer_coverage(),
+            HashMap::from([("alice".to_string(), 60), ("bob".to_string(), 50)])
+        );
+
+        // Modified lines lose their reviewers
+        let state = mark(&state, (5, 6), State::Modified, "carol");
+        let file_reviews = state.files.get(&file1).unwrap();
+        assert_eq!(file_reviews.reviewed_by("alice"), ranges(vec![(0, 4)]));
+        assert_eq!(
+            file_reviews.reviewed_by("bob"),
+            ranges(vec![(3, 4), (7, 7)])
+        );
+        assert_eq!(file_reviews.reviewed_by_at_least(2), ranges(vec![(3, 4)]));
+
+        // Reviewers move along with their lines
+        let line_map = LineMap::new(&[LineDiff {
+            old: None,
+            new: Some(1),
+        }]);
+        let file_reviews = file_reviews.transform(&line_map, Some(11));
+        assert_eq!(file_reviews.reviewed_by("alice"), ranges(vec![(1, 5)]));
+        assert_eq!(
+            file_reviews.reviewed_by("bob"),
+            ranges(vec![(4, 5), (8, 8)])
+        );
+    }
+
     #[test]
     fn test_mark_lines() {
         // Add to empty ranges
@@ -581,4 +1545,35 @@ mod tests {
         );
         assert_eq!(ranges(vec![(3, 4)]), res);
     }
+
+    #[test]
+    fn test_restricted_changes() {
+        let mut comment = Comment::new("c1".to_string(), "body".to_string(), "alice".to_string());
+        comment.replies.push(Reply::new(
+            "r1".to_string(),
+            "reply".to_string(),
+            "bob".to_string(),
+        ));
+        let comments = FileComments(HashMap::from([(3, vec![comment])]));
+        assert_eq!(comments.author_of(3, "c1"), Some("alice"));
+        assert_eq!(comments.author_of(3, "r1"), Some("bob"));
+        assert_eq!(comments.author_of(4, "c1"), None);
+
+        let request = |priority, reviewer: &str, note: &str| UpdateMetadataRequest {
+            file_name: "a.rs".to_string(),
+            metadata: Metadata {
+                priority,
+                reviewer: reviewer.to_string(),
+                note: note.to_string(),
+            },
+            author: None,
+        };
+        // Only the note changes
+        let current = request(Priority::High, "bob", "").metadata;
+        assert!(!request(Priority::High, "bob", "note").assigns(Some(&current)));
+        assert!(request(Priority::Low, "bob", "").assigns(Some(&current)));
+        assert!(request(Priority::High, "alice", "").assigns(Some(&current)));
+        assert!(!request(Priority::Unspecified, "Unassigned", "note").assigns(None));
+        assert!(request(Priority::Unspecified, "alice", "").assigns(None));
+    }
 }
@@ -0,0 +1,224 @@
+use crate::LineDiff;
+use std::ops::RangeInclusive;
+
+/// Maps 0-based line numbers of the old version of a file to the new version.
+///
+/// The map is built from the added and deleted lines of a diff: every line that is neither
+/// added nor deleted is paired, in order, with the next unchanged line of the new version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMap {
+    // Runs of unchanged lines as (old_start, new_start, len)
+    runs: Vec<(usize, usize, usize)>,
+    // Start of the last run, which extends to the end of the file
+    tail: (usize, usize),
+    deleted: Vec<usize>,
+    added: Vec<usize>,
+}
+
+impl LineMap {
+    pub fn new(line_diffs: &[LineDiff]) -> Self {
+        // Diffs use 1-based line numbers while reviews and comments are 0-based
+        let to_index = |line: u32| -> usize {
+            let line: usize = line.try_into().expect("u32 always fits in usize");
+            line.saturating_sub(1)
+        };
+        let mut deleted: Vec<usize> = line_diffs
+            .iter()
+            .filter(|line_diff| line_diff.new.is_none())
+            .filter_map(|line_diff| line_diff.old.map(to_index))
+            .collect();
+        let mut added: Vec<usize> = line_diffs
+            .iter()
+            .filter(|line_diff| line_diff.old.is_none())
+            .filter_map(|line_diff| line_diff.new.map(to_index))
+            .collect();
+        deleted.sort_unstable();
+        deleted.dedup();
+        added.sort_unstable();
+        added.dedup();
+
+        let mut runs = vec![];
+        let (mut old, mut new, mut d, mut a) = (0, 0, 0, 0);
+        loop {
+            if d < deleted.len() && deleted[d] <= old {
+                old += 1;
+                d += 1;
+                continue;
+            }
+            if a < added.len() && added[a] <= new {
+                new += 1;
+                a += 1;
+                continue;
+            }
+            let next_deleted = deleted.get(d).map(|line| line - old);
+            let next_added = added.get(a).map(|line| line - new);
+            let len = match (next_deleted, next_added) {
+                (None, None) => break,
+                (Some(len), None) | (None, Some(len)) => len,
+                (Some(d_len), Some(a_len)) => std::cmp::min(d_len, a_len),
+            };
+            runs.push((old, new, len));
+            old += len;
+            new += len;
+        }
+
+        Self {
+            runs,
+            tail: (old, new),
+            deleted,
+            added,
+        }
+    }
+
+    /// Returns where `old_line` ended up, or `None` if it was deleted.
+    pub fn map_line(&self, old_line: usize) -> Option<usize> {
+        if self.deleted.binary_search(&old_line).is_ok() {
+            return None;
+        }
+        if old_line >= self.tail.0 {
+            return Some(self.tail.1 + old_line - self.tail.0);
+        }
+        self.runs
+            .iter()
+            .find(|(old_start, _, len)| (*old_start..old_start + len).contains(&old_line))
+            .map(|(old_start, new_start, _)| new_start + old_line - old_start)
+    }
+
+    /// Same as `map_line`, but a deleted line is mapped to where the deletion happened, i.e. to
+    /// the new position of the first line that survived after it.
+    pub fn map_line_nearest(&self, old_line: usize) -> usize {
+        if let Some(new_line) = self.map_line(old_line) {
+            return new_line;
+        }
+        self.runs
+            .iter()
+            .find(|(old_start, _, _)| *old_start > old_line)
+            .map(|(_, new_start, _)| *new_start)
+            .unwrap_or(self.tail.1)
+    }
+
+    /// Maps a range of old lines to the ranges that its surviving lines occupy in the new version.
+    /// A range is split where lines were inserted in its middle and shrinks where lines were deleted.
+    pub fn map_range(&self, old_range: &RangeInclusive<usize>) -> Vec<RangeInclusive<usize>> {
+        let runs = self
+            .runs
+            .iter()
+            .map(|(old_start, new_start, len)| (*old_start, *new_start, Some(*len)))
+            .chain(std::iter::once((self.tail.0, self.tail.1, None)));
+
+        let mut mapped: Vec<RangeInclusive<usize>> = vec![];
+        for (old_start, new_start, len) in runs {
+            if len == Some(0) {
+                continue;
+            }
+            let start = std::cmp::max(*old_range.start(), old_start);
+            let end = match len {
+                Some(len) => std::cmp::min(*old_range.end(), old_start + len - 1),
+                None => *old_range.end(),
+            };
+            if start > end {
+                continue;
+            }
+            let new_range = (new_start + start - old_start)..=(new_start + end - old_start);
+            match mapped.last_mut() {
+                Some(last) if last.end() + 1 == *new_range.start() => {
+                    *last = *last.start()..=*new_range.end();
+                }
+                _ => mapped.push(new_range),
+            }
+        }
+        mapped
+    }
+
+    /// Ranges of lines that only exist in the new version.
+    pub fn added_ranges(&self) -> Vec<RangeInclusive<usize>> {
+        to_ranges(&self.added)
+    }
+
+    /// Ranges of lines of the old version that were deleted.
+    pub fn deleted_ranges(&self) -> Vec<RangeInclusive<usize>> {
+        to_ranges(&self.deleted)
+    }
+}
+
+/// Joins sorted lines into ranges of consecutive lines.
+fn to_ranges(lines: &[usize]) -> Vec<RangeInclusive<usize>> {
+    let mut ranges: Vec<RangeInclusive<usize>> = vec![];
+    for line in lines {
+        match ranges.last_mut() {
+            Some(last) if last.end() + 1 == *line => *last = *last.start()..=*line,
+            _ => ranges.push(*line..=*line),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn deleted(line: u32) -> LineDiff {
+        LineDiff {
+            old: Some(line),
+            new: None,
+        }
+    }
+
+    fn added(line: u32) -> LineDiff {
+        LineDiff {
+            old: None,
+            new: Some(line),
+        }
+    }
+
+    #[test]
+    fn test_map_line() {
+        // Empty diff is the identity
+        let map = LineMap::new(&[]);
+        assert_eq!(map.map_line(0), Some(0));
+        assert_eq!(map.map_line(42), Some(42));
+
+        // Lines added at the top shift everything down
+        let map = LineMap::new(&[added(1), added(2)]);
+        assert_eq!(map.map_line(0), Some(2));
+        assert_eq!(map.map_line(10), Some(12));
+
+        // Deleted lines disappear and the following ones shift up
+        let map = LineMap::new(&[deleted(3), deleted(4)]);
+        assert_eq!(map.map_line(1), Some(1));
+        assert_eq!(map.map_line(2), None);
+        assert_eq!(map.map_line(3), None);
+        assert_eq!(map.map_line(4), Some(2));
+        assert_eq!(map.map_line_nearest(3), 2);
+
+        // A modification: old line 2 replaced by two new lines
+        let map = LineMap::new(&[deleted(2), added(2), added(3)]);
+        assert_eq!(map.map_line(0), Some(0));
+        assert_eq!(map.map_line(1), None);
+        assert_eq!(map.map_line(2), Some(3));
+        assert_eq!(map.map_line_nearest(1), 3);
+    }
+
+    #[test]
+    fn test_map_range() {
+        // Shifted as a whole
+        let map = LineMap::new(&[added(1)]);
+        assert_eq!(map.map_range(&(2..=5)), vec![3..=6]);
+
+        // Split by an insertion in the middle
+        let map = LineMap::new(&[added(4), added(5)]);
+        assert_eq!(map.map_range(&(1..=5)), vec![1..=2, 5..=7]);
+
+        // Shrunk by a deletion in the middle
+        let map = LineMap::new(&[deleted(4), deleted(5)]);
+        assert_eq!(map.map_range(&(1..=5)), vec![1..=3]);
+
+        // Fully deleted
+        assert_eq!(map.map_range(&(3..=4)), vec![]);
+
+        // Added lines are grouped
+        let map = LineMap::new(&[added(1), added(2), added(7)]);
+        assert_eq!(map.added_ranges(), vec![0..=1, 6..=6]);
+    }
+}
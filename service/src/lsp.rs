@@ -0,0 +1,568 @@
+use crate::{
+    cli::{count_lines, resolve_file, Backend},
+    errors::AuditorError,
+    git::{line_map_between, Git},
+    line_map::LineMap,
+    FileComments, LatestFileInfo, State, StoredReviewForFile, UpdateReviewState,
+};
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics, ShowMessage,
+    },
+    request::{CodeActionRequest, ExecuteCommand, HoverRequest, Request as _},
+    CodeAction, CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability, Command,
+    Diagnostic, DiagnosticSeverity, DiagnosticTag, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    ExecuteCommandOptions, ExecuteCommandParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, MarkupContent, MarkupKind, MessageType, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, ShowMessageParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{collections::HashMap, fs, ops::RangeInclusive, path::Path};
+
+/// Shown as the source of the diagnostics.
+const SOURCE: &str = "auditor";
+
+/// The workspace commands and the review state each one marks lines with, transform takes no
+/// lines.
+const MARK_COMMANDS: [(&str, &str, State); 3] = [
+    ("auditor.markReviewed", "Mark as reviewed", State::Reviewed),
+    ("auditor.ignore", "Ignore", State::Ignored),
+    ("auditor.clear", "Clear reviews", State::Cleared),
+];
+const TRANSFORM_COMMAND: &str = "auditor.transform";
+
+/// A language server for editors other than VS Code. Reviews show up as diagnostics, comments as
+/// hovers, and marking and transforming are code actions.
+pub struct Server {
+    backend: Box<dyn Backend>,
+    author: Option<String>,
+    // Text of the open documents, which may not be saved yet
+    documents: HashMap<Url, String>,
+}
+
+impl Server {
+    pub fn new(backend: Box<dyn Backend>, author: Option<String>) -> Self {
+        Self {
+            backend,
+            author,
+            documents: HashMap::default(),
+        }
+    }
+
+    pub fn capabilities() -> ServerCapabilities {
+        let mut commands: Vec<String> = MARK_COMMANDS
+            .iter()
+            .map(|(command, _, _)| command.to_string())
+            .collect();
+        commands.push(TRANSFORM_COMMAND.to_string());
+        ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::FULL),
+                    save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                    ..TextDocumentSyncOptions::default()
+                },
+            )),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            execute_command_provider: Some(ExecuteCommandOptions {
+                commands,
+                ..ExecuteCommandOptions::default()
+            }),
+            ..ServerCapabilities::default()
+        }
+    }
+
+    /// Answers the editor until it shuts the server down.
+    pub fn run(mut self, connection: &Connection) -> Result<()> {
+        connection.initialize(serde_json::to_value(Self::capabilities())?)?;
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let id = request.id.clone();
+                    let response = match self.handle_request(connection, request) {
+                        Ok(result) => Response::new_ok(id, result),
+                        Err(err) => Response::new_err(
+                            id,
+                            ErrorCode::RequestFailed as i32,
+                            format!("{err:#}"),
+                        ),
+                    };
+                    connection.sender.send(response.into())?;
+                }
+                Message::Notification(notification) => {
+                    if let Err(err) = self.handle_notification(connection, notification) {
+                        show_error(connection, &err)?;
+                    }
+                }
+                Message::Response(_) => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, connection: &Connection, request: Request) -> Result<Value> {
+        match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(request.params)?;
+                let position = params.text_document_position_params;
+                let file_name = file_name_of(&position.text_document.uri)?;
+                let comments = self.backend.comments(&file_name)?;
+                let line: usize = position.position.line.try_into()?;
+                Ok(serde_json::to_value(hover_of(&comments, line))?)
+            }
+            CodeActionRequest::METHOD => {
+                let params: CodeActionParams = serde_json::from_value(request.params)?;
+                Ok(serde_json::to_value(code_actions(
+                    &params.text_document.uri,
+                    &params.range,
+                ))?)
+            }
+            ExecuteCommand::METHOD => {
+                let params: ExecuteCommandParams = serde_json::from_value(request.params)?;
+                let uri = self.execute(params)?;
+                self.publish(connection, &uri)?;
+                Ok(Value::Null)
+            }
+            method => Err(anyhow::anyhow!("Unsupported request {method}")),
+        }
+    }
+
+    fn handle_notification(
+        &mut self,
+        connection: &Connection,
+        notification: Notification,
+    ) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish(connection, &uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // The whole document is sent with every change
+                let uri = params.text_document.uri;
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.publish(connection, &uri)
+            }
+            DidSaveTextDocument::METHOD => {
+                let params: DidSaveTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.publish(connection, &params.text_document.uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a workspace command and returns the document it changed.
+    fn execute(&self, params: ExecuteCommandParams) -> Result<Url> {
+        let uri: Url = argument(&params, 0, "document")?;
+        let file_name = file_name_of(&uri)?;
+        if params.command == TRANSFORM_COMMAND {
+            self.backend.transform(&file_name, self.author.clone())?;
+            return Ok(uri);
+        }
+        let review_state = MARK_COMMANDS
+            .iter()
+            .find(|(command, _, _)| *command == params.command)
+            .map(|(_, _, state)| state.clone())
+            .ok_or_else(|| anyhow::anyhow!("Unknown command {}", params.command))?;
+        let lines: RangeInclusive<usize> = argument(&params, 1, "lines")?;
+        // The lines of unsaved text are not where the service looks for them
+        let saved = fs::read_to_string(path_of(&uri)?)
+            .map_err(|_| AuditorError::UnknownFileName(file_name.clone()))?;
+        if self.documents.get(&uri).is_some_and(|text| *text != saved) {
+            return Err(AuditorError::UnsavedChanges(file_name).into());
+        }
+        // Reviews are stored for the committed file
+        let total_lines = match committed_text(&uri, &file_name)? {
+            Some(committed) => count_lines(&committed),
+            None => count_lines(&saved),
+        };
+        self.backend.mark(UpdateReviewState::new(
+            file_name,
+            lines,
+            review_state,
+            total_lines,
+            self.author.clone(),
+        ))?;
+        Ok(uri)
+    }
+
+    /// Sends the reviews of the document as diagnostics, replacing the ones sent before. Reviews
+    /// are stored for the lines of the committed file and are moved to where those lines are in
+    /// the text of the editor.
+    fn publish(&self, connection: &Connection, uri: &Url) -> Result<()> {
+        let file_name = file_name_of(uri)?;
+        let diagnostics = match self.backend.infos(Some(&file_name))?.first() {
+            Some(info) => {
+                let mut info = info.clone();
+                let text = match self.documents.get(uri) {
+                    Some(text) => Some(text.clone()),
+                    None => fs::read_to_string(path_of(uri)?).ok(),
+                };
+                if let (Some(committed), Some(text)) = (committed_text(uri, &file_name)?, text) {
+                    move_reviews(
+                        &mut info.line_reviews,
+                        &line_map_between(&committed, &text)?,
+                    );
+                }
+                diagnostics_of(&info)
+            }
+            None => vec![],
+        };
+        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics, None);
+        connection
+            .sender
+            .send(Notification::new(PublishDiagnostics::METHOD.to_string(), params).into())?;
+        Ok(())
+    }
+}
+
+fn argument<T: DeserializeOwned>(
+    params: &ExecuteCommandParams,
+    index: usize,
+    name: &str,
+) -> Result<T> {
+    let argument = params
+        .arguments
+        .get(index)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Argument {name} of {} is missing", params.command))?;
+    Ok(serde_json::from_value(argument)?)
+}
+
+fn show_error(connection: &Connection, err: &anyhow::Error) -> Result<()> {
+    let params = ShowMessageParams {
+        typ: MessageType::ERROR,
+        message: format!("auditor: {err:#}"),
+    };
+    connection
+        .sender
+        .send(Notification::new(ShowMessage::METHOD.to_string(), params).into())?;
+    Ok(())
+}
+
+fn path_of(uri: &Url) -> Result<String> {
+    let path = uri
+        .to_file_path()
+        .map_err(|_| AuditorError::InvalidAbsolutePath(uri.to_string()))?;
+    Ok(path
+        .to_str()
+        .ok_or(AuditorError::OsStringError)?
+        .to_string())
+}
+
+/// The file name of the document relative to its repository.
+fn file_name_of(uri: &Url) -> Result<String> {
+    Ok(resolve_file(&path_of(uri)?)?.0)
+}
+
+/// The text of the document as committed at HEAD of its repository, None if it is not in a
+/// repository or was never committed.
+fn committed_text(uri: &Url, file_name: &str) -> Result<Option<String>> {
+    let path = path_of(uri)?;
+    let Ok(repository) =
+        git2::Repository::discover(Path::new(&path).parent().unwrap_or(Path::new("/")))
+    else {
+        return Ok(None);
+    };
+    let Some(workdir) = repository.workdir().and_then(|workdir| workdir.to_str()) else {
+        return Ok(None);
+    };
+    let git = Git::new(&workdir.to_string())?;
+    // Nothing is committed in a new repository
+    let Ok(commit) = git.current_commit() else {
+        return Ok(None);
+    };
+    git.file_content_at(&commit, file_name)
+}
+
+/// Moves reviews along with their lines, lines that were changed lose their reviews.
+fn move_reviews(line_reviews: &mut StoredReviewForFile, line_map: &LineMap) {
+    let map = |ranges: &Vec<RangeInclusive<usize>>| -> Vec<RangeInclusive<usize>> {
+        ranges
+            .iter()
+            .flat_map(|range| line_map.map_range(range))
+            .collect()
+    };
+    line_reviews.reviewed = map(&line_reviews.reviewed);
+    line_reviews.modified = map(&line_reviews.modified);
+    line_reviews.ignored = map(&line_reviews.ignored);
+    for ranges in line_reviews.reviewers.values_mut() {
+        *ranges = map(ranges);
+    }
+}
+
+/// Covers whole lines, up to the start of the line after the range.
+fn range_of(lines: &RangeInclusive<usize>) -> Range {
+    let line = |line: usize| u32::try_from(line).unwrap_or(u32::MAX);
+    Range::new(
+        Position::new(line(*lines.start()), 0),
+        Position::new(line(*lines.end()).saturating_add(1), 0),
+    )
+}
+
+/// Reviewed and ignored lines are hints, modified lines need another look and are shown as
+/// information. Ignored lines are also faded out.
+pub fn diagnostics_of(info: &LatestFileInfo) -> Vec<Diagnostic> {
+    let line_reviews = &info.line_reviews;
+    let diagnostic = |lines: &RangeInclusive<usize>, severity, message: String| Diagnostic {
+        range: range_of(lines),
+        severity: Some(severity),
+        source: Some(SOURCE.to_string()),
+        message,
+        ..Diagnostic::default()
+    };
+    let mut diagnostics = vec![];
+    for lines in &line_reviews.reviewed {
+        let mut reviewers: Vec<&str> = line_reviews
+            .reviewers
+            .iter()
+            .filter(|(_, ranges)| {
+                ranges
+                    .iter()
+                    .any(|range| range.start() <= lines.end() && lines.start() <= range.end())
+            })
+            .map(|(reviewer, _)| reviewer.as_str())
+            .collect();
+        reviewers.sort_unstable();
+        let message = match reviewers.is_empty() {
+            true => "Reviewed".to_string(),
+            false => format!("Reviewed by {}", reviewers.join(", ")),
+        };
+        diagnostics.push(diagnostic(lines, DiagnosticSeverity::HINT, message));
+    }
+    for lines in &line_reviews.modified {
+        diagnostics.push(diagnostic(
+            lines,
+            DiagnosticSeverity::INFORMATION,
+            "Modified since it was reviewed".to_string(),
+        ));
+    }
+    for lines in &line_reviews.ignored {
+        diagnostics.push(Diagnostic {
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..diagnostic(lines, DiagnosticSeverity::HINT, "Ignored".to_string())
+        });
+    }
+    diagnostics
+}
+
+/// The threads on the line with their replies, as Markdown.
+pub fn hover_of(comments: &FileComments, line: usize) -> Option<Hover> {
+    let threads = comments
+        .0
+        .get(&line)
+        .filter(|threads| !threads.is_empty())?;
+    let mut value = String::new();
+    for comment in threads {
+        if !value.is_empty() {
+            value += "\n---\n\n";
+        }
+        value += &format!(
+            "**{}** ({:?}): {}\n\n",
+            comment.author, comment.state, comment.body
+        );
+        for reply in &comment.replies {
+            value += &format!("> **{}**: {}\n\n", reply.author, reply.body);
+        }
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(range_of(&(line..=line))),
+    })
+}
+
+/// Marking applies to the selected lines, a selection that ends at the start of a line leaves
+/// that line out.
+pub fn code_actions(uri: &Url, range: &Range) -> Vec<CodeActionOrCommand> {
+    let start = range.start.line as usize;
+    let mut end = range.end.line as usize;
+    if range.end.character == 0 && end > start {
+        end -= 1;
+    }
+    let mut actions: Vec<CodeActionOrCommand> = MARK_COMMANDS
+        .iter()
+        .map(|(command, title, _)| {
+            let arguments = vec![json!(uri), json!(start..=end)];
+            code_action(title, command, arguments)
+        })
+        .collect();
+    actions.push(code_action(
+        "Transform reviews to HEAD",
+        TRANSFORM_COMMAND,
+        vec![json!(uri)],
+    ));
+    actions
+}
+
+fn code_action(title: &str, command: &str, arguments: Vec<Value>) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("auditor: {title}"),
+        command: Some(Command::new(
+            title.to_string(),
+            command.to_string(),
+            Some(arguments),
+        )),
+        ..CodeAction::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{cli::LocalBackend, config::Config, git, Comment};
+
+    #[test]
+    fn test_language_server() {
+        let mut line_reviews = StoredReviewForFile {
+            reviewed: vec![0..=2],
+            modified: vec![3..=3],
+            ignored: vec![5..=6],
+            total_lines: 8,
+            reviewers: HashMap::default(),
+        };
+        line_reviews
+            .reviewers
+            .insert("alice".to_string(), vec![1..=2]);
+        let info = LatestFileInfo {
+            file_name: "src/main.rs".to_string(),
+            line_reviews,
+            comments: HashMap::default(),
+            metadata: None,
+            aliases: vec![],
+            reviewer_coverage: HashMap::default(),
+            policy_status: None,
+            open_threads: 0,
+            resolved_threads: 0,
+            scope: None,
+        };
+        let diagnostics = diagnostics_of(&info);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].message, "Reviewed by alice");
+        assert_eq!(diagnostics[0].range, range_of(&(0..=2)));
+        assert_eq!(diagnostics[0].range.end, Position::new(3, 0));
+        assert_eq!(
+            diagnostics[1].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+        assert_eq!(diagnostics[2].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+
+        let mut comment = Comment::new("c1".to_string(), "Overflow?".to_string(), "bob".into());
+        comment.replies.push(crate::Reply::new(
+            "r1".to_string(),
+            "Checked".to_string(),
+            "alice".to_string(),
+        ));
+        let comments = FileComments(HashMap::from([(4, vec![comment])]));
+        assert!(hover_of(&comments, 3).is_none());
+        let HoverContents::Markup(markup) = hover_of(&comments, 4).unwrap().contents else {
+            panic!("hover is not markup");
+        };
+        assert_eq!(
+            markup.value,
+            "**bob** (Open): Overflow?\n\n> **alice**: Checked\n\n"
+        );
+
+        let uri = Url::parse("file:///repo/src/main.rs").unwrap();
+        let actions = code_actions(&uri, &Range::new(Position::new(2, 4), Position::new(5, 0)));
+        assert_eq!(actions.len(), 4);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("not a code action");
+        };
+        let command = action.command.as_ref().unwrap();
+        assert_eq!(command.command, "auditor.markReviewed");
+        let lines: RangeInclusive<usize> =
+            serde_json::from_value(command.arguments.as_ref().unwrap()[1].clone()).unwrap();
+        assert_eq!(lines, 2..=4);
+    }
+
+    #[test]
+    fn test_unsaved_changes() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "a.c", "1\n2\n3\n");
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let config = Config {
+            repository_path: repo_path.clone(),
+            db_path,
+            db_backend: "json".to_string(),
+            included_prefixes: vec!["".to_string()],
+            ..Config::default()
+        };
+        let mut server = Server::new(Box::new(LocalBackend::new(config)), None);
+        let (connection, client) = Connection::memory();
+        let uri = Url::from_file_path(Path::new(&repo_path).join("a.c")).unwrap();
+        let notify = |method: &str, params: Value| Notification::new(method.to_string(), params);
+        let mark = |lines: RangeInclusive<usize>| ExecuteCommandParams {
+            command: "auditor.markReviewed".to_string(),
+            arguments: vec![json!(uri), json!(lines)],
+            ..ExecuteCommandParams::default()
+        };
+        let published = || {
+            let Message::Notification(notification) = client.receiver.try_recv().unwrap() else {
+                panic!("not a notification");
+            };
+            let params: PublishDiagnosticsParams =
+                serde_json::from_value(notification.params).unwrap();
+            params.diagnostics
+        };
+
+        let open = json!({"textDocument": {
+            "uri": uri, "languageId": "c", "version": 1, "text": "1\n2\n3\n",
+        }});
+        server
+            .handle_notification(&connection, notify(DidOpenTextDocument::METHOD, open))
+            .unwrap();
+        assert!(published().is_empty());
+        server.execute(mark(0..=1)).unwrap();
+
+        // The reviews move along with their lines while typing
+        let change = json!({
+            "textDocument": {"uri": uri, "version": 2},
+            "contentChanges": [{"text": "0\n1\n2\n3\n"}],
+        });
+        server
+            .handle_notification(&connection, notify(DidChangeTextDocument::METHOD, change))
+            .unwrap();
+        let diagnostics = published();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range, range_of(&(1..=2)));
+
+        // Lines of unsaved text are not marked
+        let err = server.execute(mark(3..=3)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AuditorError>(),
+            Some(AuditorError::UnsavedChanges(_))
+        ));
+    }
+}
@@ -1,48 +1,84 @@
 use auditor::{
+    add_comment,
+    api_error::ApiError,
+    auth::{self, Permission, Role, User},
+    changes::{Change, Changes},
     config::{Config, ConfigBuilder},
     db::DB,
+    delete_comment,
+    errors::AuditorError,
+    file_key,
+    finding::{create_finding, update_finding, Finding, FindingRequest},
+    gate::Gate,
     get_review_state,
     git::Git,
-    transform_review_state, update_metadata, update_review_state, Comment, FileComments, Metadata,
-    StoredReviewForFile, UpdateMetadataRequest, UpdateReviewState,
+    history::{authorize_revert, revert_event, Event},
+    infos_in_scope, reply_to_comment,
+    report::{Report, ReportFormat},
+    sarif::{self, ImportSummary, SarifLog},
+    session::{Session, SessionRequest},
+    set_thread_state,
+    sqlite::SqliteDB,
+    storage::{open_findings_storage, open_storage, Storage},
+    transform_review_state, update_comment, update_metadata, update_review_state, FileComments,
+    LatestFileInfo, StoredReviewForFile, ThreadState, UpdateMetadataRequest, UpdateReviewState,
 };
 use axum::http;
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::{Request, StatusCode},
-    routing::{delete, get, post},
-    Json, Router,
+    middleware::{self, Next},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        Response,
+    },
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
 };
+use futures_util::stream::{self, Stream};
 use hyper::Method;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, net::SocketAddr, time::Duration};
-use tower_http::{
-    classify::ServerErrorsFailureClass,
-    cors::{Any, CorsLayer},
-    trace::TraceLayer,
+use std::{
+    collections::HashMap,
+    env,
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    sync::Arc,
+    time::Duration,
 };
+use tokio::sync::RwLock;
+use tower_http::{classify::ServerErrorsFailureClass, cors::CorsLayer, trace::TraceLayer};
 use tracing::{info_span, Span};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Serialize, Deserialize, Clone)]
 struct LatestFileInfos(Vec<LatestFileInfo>);
 
-#[derive(Serialize, Deserialize, Clone)]
-struct LatestFileInfo {
-    file_name: String,
-    line_reviews: StoredReviewForFile,
-    comments: HashMap<usize, Vec<Comment>>,
-    metadata: Option<Metadata>,
-}
-
 #[derive(Clone, Debug)]
 pub struct AppState {
     config: Config,
+    // Handlers that write to the db hold the write lock so that requests never interleave
+    db_lock: Arc<RwLock<()>>,
+    // Changes are pushed to the clients subscribed to /changes
+    changes: Changes,
 }
 
+/// The user that sent the request, known when authentication is enabled.
+#[derive(Clone, Debug)]
+struct Identity(User);
+
 #[derive(Deserialize)]
 pub struct Transform {
     file_name: String,
+    #[serde(default)]
+    author: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Revert {
+    event_id: String,
+    #[serde(default)]
+    author: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -52,35 +88,85 @@ pub struct ReviewState {
     ignored: Vec<(usize, usize)>,
 }
 
+#[derive(Serialize)]
+pub struct ReviewerCoverage {
+    by_reviewer: HashMap<String, Vec<(usize, usize)>>,
+    // Lines reviewed by at least two reviewers
+    multiple_reviewers: Vec<(usize, usize)>,
+    // Lines reviewed by one reviewer only
+    single_reviewer: Vec<(usize, usize)>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CreateComment {
     file_name: String,
     line_number: usize,
     body: String,
-    author: String,
+    #[serde(default)]
+    author: Option<String>,
 }
 
-// #[derive(Deserialize)]
-// pub struct UpdateComment {
-//     line_number: usize,
-//     comment_id: String,
-//     body: String,
-//     author: String,
-// }
+#[derive(Deserialize, Debug)]
+pub struct ReplyToComment {
+    file_name: String,
+    line_number: usize,
+    // Id of the comment that started the thread
+    thread_id: String,
+    body: String,
+    #[serde(default)]
+    author: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateThread {
+    file_name: String,
+    line_number: usize,
+    thread_id: String,
+    // Only used when resolving, either Resolved (the default) or WontFix
+    #[serde(default)]
+    state: Option<ThreadState>,
+    #[serde(default)]
+    author: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateComment {
+    file_name: String,
+    line_number: usize,
+    body: String,
+    // The editor, who has to be the author of the comment unless the edit is forced
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    force: bool,
+}
 
 #[derive(Deserialize, Debug)]
 pub struct DeleteComment {
     file_name: String,
     line_number: usize,
     comment_id: String,
+    #[serde(default)]
+    author: Option<String>,
+}
+
+fn to_pairs(ranges: &[RangeInclusive<usize>]) -> Vec<(usize, usize)> {
+    ranges
+        .iter()
+        .map(|range| (*range.start(), *range.end()))
+        .collect()
 }
 
-impl ReviewState {
-    fn default() -> Self {
+impl From<StoredReviewForFile> for ReviewerCoverage {
+    fn from(state: StoredReviewForFile) -> Self {
         Self {
-            ignored: vec![],
-            modified: vec![],
-            reviewed: vec![],
+            by_reviewer: state
+                .reviewers
+                .iter()
+                .map(|(reviewer, ranges)| (reviewer.clone(), to_pairs(ranges)))
+                .collect(),
+            multiple_reviewers: to_pairs(&state.reviewed_by_at_least(2)),
+            single_reviewer: to_pairs(&state.reviewed_by_only_one()),
         }
     }
 }
@@ -122,8 +208,64 @@ async fn main() {
 
     let mut config_path = "./config.toml";
     let args: Vec<String> = env::args().collect();
-    if args.len() == 2 {
-        config_path = &args[1];
+    let migrate = args.get(1).is_some_and(|arg| arg == "migrate-to-sqlite");
+    // `sarif` prints the comments and findings as a SARIF log
+    let export_sarif = args.get(1).is_some_and(|arg| arg == "sarif");
+    // `check` fails when high or medium priority code changed since it was last reviewed, for CI
+    let check = args.get(1).is_some_and(|arg| arg == "check");
+    // `report <markdown|html>` prints the audit report instead of serving requests
+    let report_format: Option<ReportFormat> = match args.get(1).map(String::as_str) {
+        Some("report") => Some(
+            args.get(2)
+                .expect("usage: auditor report <markdown|html> [config]")
+                .parse()
+                .unwrap(),
+        ),
+        _ => None,
+    };
+    // `import-sarif <file> [--raise-priority]` attaches the results of static analyzers as comments
+    let sarif_import: Option<String> = match args.get(1).map(String::as_str) {
+        Some("import-sarif") => Some(
+            args.get(2)
+                .expect("usage: auditor import-sarif <file> [--raise-priority] [config]")
+                .clone(),
+        ),
+        _ => None,
+    };
+    // `add-user <name> [role]` and `create-token <name>` print a new API token of the user,
+    // `set-role <name> <role>` changes the role and `revoke-token <id>` deletes a token
+    let account_command: Option<(&str, String)> = match args.get(1).map(String::as_str) {
+        Some(command @ ("add-user" | "create-token" | "set-role" | "revoke-token")) => Some((
+            command,
+            args.get(2)
+                .expect("usage: auditor <add-user|create-token|set-role> <name> [role] [config], auditor revoke-token <id> [config]")
+                .clone(),
+        )),
+        _ => None,
+    };
+    let role: Option<Role> = match account_command {
+        Some(("set-role", _)) => Some(
+            args.get(3)
+                .expect("usage: auditor set-role <name> <lead|auditor|viewer> [config]")
+                .parse()
+                .unwrap(),
+        ),
+        Some(("add-user", _)) => args.get(3).and_then(|arg| arg.parse().ok()),
+        _ => None,
+    };
+    let raise_priority =
+        sarif_import.is_some() && args.get(3).is_some_and(|arg| arg == "--raise-priority");
+    let args = if migrate || export_sarif || check {
+        &args[2..]
+    } else if report_format.is_some() || account_command.is_some() {
+        &args[3 + usize::from(role.is_some())..]
+    } else if sarif_import.is_some() {
+        &args[3 + usize::from(raise_priority)..]
+    } else {
+        &args[1..]
+    };
+    if args.len() == 1 {
+        config_path = &args[0];
     }
 
     let mut builder = ConfigBuilder::default();
@@ -135,35 +277,145 @@ async fn main() {
             .unwrap()
             .build()
             .unwrap(),
+        db_lock: Arc::new(RwLock::new(())),
+        changes: Changes::new(256),
     };
 
+    if app_state.config.db_backend == "json" {
+        let migrated = DB::migrate(&app_state.config.db_path).unwrap();
+        if migrated > 0 {
+            println!("Upgraded {migrated} db files to the latest schema");
+        }
+    }
+
+    if migrate {
+        migrate_to_sqlite(&app_state.config);
+        return;
+    }
+
+    if let Some((command, argument)) = account_command {
+        manage_accounts(&app_state.config, command, &argument, role);
+        return;
+    }
+
+    if let Some(format) = report_format {
+        let db = open_storage(&app_state.config, None).unwrap();
+        let git = Git::new(&app_state.config.repository_path).unwrap();
+        let report = Report::generate(&app_state.config, db.as_ref(), &git).unwrap();
+        print!("{}", report.render(format));
+        return;
+    }
+
+    if check {
+        let db = open_storage(&app_state.config, None).unwrap();
+        let git = Git::new(&app_state.config.repository_path).unwrap();
+        let gate = match Gate::check(&app_state.config, db.as_ref(), &git) {
+            Ok(gate) => gate,
+            Err(err) => {
+                eprintln!("{err:#}");
+                std::process::exit(2);
+            }
+        };
+        print!("{gate}");
+        if !gate.passed() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if export_sarif {
+        let db = open_storage(&app_state.config, None).unwrap();
+        let log = sarif::export(&app_state.config, db.as_ref()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&log).unwrap());
+        return;
+    }
+
+    if let Some(sarif_path) = sarif_import {
+        let content = std::fs::read_to_string(sarif_path).unwrap();
+        let log: SarifLog = serde_json::from_str(&content).unwrap();
+        let mut db = open_storage(&app_state.config, None).unwrap();
+        let summary =
+            sarif::import(&log, &app_state.config, raise_priority, None, db.as_mut()).unwrap();
+        db.save().unwrap();
+        println!(
+            "Added {} comments, {} were already imported and {} results were skipped",
+            summary.added, summary.duplicates, summary.skipped
+        );
+        for file_name in summary.raised {
+            println!("Raised the priority of {file_name}");
+        }
+        return;
+    }
+
     println!("{app_state:?}");
     let port: u16 = app_state.config.port.clone().parse().unwrap();
+    let ip: IpAddr = app_state.config.bind_address.parse().unwrap();
 
     let cors = CorsLayer::new()
-        .allow_headers(vec![http::header::CONTENT_TYPE])
-        // allow `GET` and `POST` when accessing the resource
-        .allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS])
-        // allow requests from any origin
-        .allow_origin(Any);
+        .allow_headers(vec![
+            http::header::CONTENT_TYPE,
+            http::header::AUTHORIZATION,
+        ])
+        // allow `GET`, `POST`, `PUT` and `DELETE` when accessing the resource
+        .allow_methods(vec![
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        // only allow requests from the dashboard
+        .allow_origin(
+            app_state
+                .config
+                .dashboard_origin
+                .parse::<http::HeaderValue>()
+                .expect("dashboard_origin is not a valid origin"),
+        );
 
     let app = Router::new()
         .route("/", get(root))
         .route("/reviews", post(handle_update_review_state))
         .route("/reviews", get(handle_get_review_state))
+        .route("/reviewers", get(handle_get_reviewer_coverage))
         .route("/info", get(handle_get_all_info))
+        .route("/changes", get(handle_subscribe_to_changes))
         .route("/transform", post(handle_transform_review_state))
         .route("/comments", post(handle_create_comment))
         .route("/comments", get(handle_get_comments))
         .route("/comments", delete(handle_delete_comment))
+        .route("/comments/reply", post(handle_reply_to_comment))
+        .route("/comments/resolve", post(handle_resolve_thread))
+        .route("/comments/reopen", post(handle_reopen_thread))
         .route("/metadata", post(handle_update_metadata))
-        //.route("/comments/:comment_id", put(handle_update_comment))
+        .route("/history", get(handle_get_history))
+        .route("/history/revert", post(handle_revert_event))
+        .route("/report", get(handle_get_report))
+        .route("/sarif", get(handle_get_sarif))
+        .route("/sarif", post(handle_import_sarif))
+        .route("/findings", get(handle_get_findings))
+        .route("/findings", post(handle_create_finding))
+        .route("/findings/:finding_id", get(handle_get_finding))
+        .route("/findings/:finding_id", put(handle_update_finding))
+        .route("/findings/:finding_id", delete(handle_delete_finding))
+        .route("/comments/:comment_id", put(handle_update_comment))
+        .route("/sessions", get(handle_get_sessions))
+        .route("/sessions", post(handle_create_session))
+        .route("/sessions/:session_id", get(handle_get_session))
+        .route("/me", get(handle_get_me))
+        // Layers run from the bottom up, the user is known before the route is checked
+        .layer(middleware::from_fn(authorize_request))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            authenticate_request,
+        ))
         .with_state(app_state)
         .layer(cors)
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &Request<_>| {
-                    let path = request.uri().to_string();
+                    // The query may hold an API token and is never logged
+                    let path = request.uri().path().to_string();
 
                     info_span!(
                         "http_request",
@@ -178,7 +430,7 @@ async fn main() {
                 ),
         );
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let addr = SocketAddr::from((ip, port));
     tracing::debug!("listening on {}", addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
@@ -186,222 +438,803 @@ async fn main() {
         .unwrap();
 }
 
+/// Copies the JSON database in `db_path` into a SQLite database next to it.
+fn migrate_to_sqlite(config: &Config) {
+    let json_db = DB::new(config.db_path.clone()).unwrap();
+    let mut sqlite_db = SqliteDB::new(&config.db_path).unwrap();
+    let count = sqlite_db.migrate_from_json(&json_db).unwrap();
+    println!("Migrated {count} files into {}", config.db_path);
+}
+
+/// Runs one of the account commands against the configured backend.
+fn manage_accounts(config: &Config, command: &str, argument: &str, role: Option<Role>) {
+    let mut db = open_findings_storage(config).unwrap();
+    match command {
+        "add-user" => {
+            let user = auth::create_user(argument, role.unwrap_or_default(), db.as_mut()).unwrap();
+            println!("Added {} as {}", user.name, user.role.as_str());
+            print_new_token(argument, db.as_mut());
+        }
+        "create-token" => print_new_token(argument, db.as_mut()),
+        "set-role" => {
+            let user = auth::set_role(argument, role.unwrap_or_default(), db.as_mut()).unwrap();
+            println!("{} is now {}", user.name, user.role.as_str());
+        }
+        _ => {
+            db.delete_token(argument).unwrap();
+            println!("Revoked token {argument}");
+        }
+    }
+    db.save().unwrap();
+    if !config.authentication {
+        println!("Tokens are only checked once `authentication` is enabled in the config");
+    }
+}
+
+fn print_new_token(user_name: &str, db: &mut dyn Storage) {
+    let (id, token) = auth::create_token(user_name, db).unwrap();
+    println!("Created token {id} for {user_name}, it is only shown once:\n{token}");
+}
+
 async fn root() -> &'static str {
-    "Send requests to /reviews, /transform, and /comments endpoints"
+    "Send requests to /reviews, /reviewers, /transform, /comments, and /findings endpoints"
 }
 
-async fn handle_get_review_state(
+/// Rejects requests without a valid API token when authentication is enabled, otherwise passes
+/// the user of the token on to the handlers.
+async fn authenticate_request<B>(
     State(state): State<AppState>,
-    Query(query): Query<HashMap<String, String>>,
-) -> (StatusCode, Json<ReviewState>) {
-    let file_name = query.get(&"file_name".to_string());
-    if file_name.is_none() {
-        return (StatusCode::BAD_REQUEST, Json(ReviewState::default()));
-    }
-    let file_name = file_name
-        .unwrap()
-        .replace(&state.config.repository_path, "");
-    let file_name = file_name.replace(&state.config.repository_path, "");
-    let db = DB::new_single_file(state.config.db_path, &file_name).unwrap();
-    let git = Git::new(&state.config.repository_path).unwrap();
-    match get_review_state(&file_name, &db, &git) {
-        Ok(state) => (StatusCode::CREATED, Json(state.into())),
-        Err(err) => {
-            tracing::error!("{}", err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ReviewState::default()),
-            )
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    if !state.config.authentication {
+        return Ok(next.run(request).await);
+    }
+    let token = token_of(&request).ok_or(AuditorError::Unauthorized)?;
+    let user = {
+        let _guard = state.db_lock.read().await;
+        let db = open_findings_storage(&state.config)?;
+        auth::authenticate(&token, db.as_ref())?
+    };
+    request.extensions_mut().insert(Identity(user));
+    Ok(next.run(request).await)
+}
+
+/// Reads the API token from the `Authorization` header. Browsers can't set headers on an
+/// EventSource, so the token of the `/changes` stream may be in the query instead.
+fn token_of<B>(request: &Request<B>) -> Option<String> {
+    let header_token = request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(auth::bearer_token)
+        .map(str::to_string);
+    header_token.or_else(|| {
+        if request.uri().path() != "/changes" {
+            return None;
         }
+        // Tokens only consist of url safe characters
+        request
+            .uri()
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("access_token=").map(str::to_string))
+    })
+}
+
+/// The author of a change, which is the authenticated user when authentication is enabled and
+/// the name sent by the client otherwise.
+fn author_of(identity: &Option<Extension<Identity>>, claimed: Option<String>) -> Option<String> {
+    match identity {
+        Some(Extension(Identity(user))) => Some(user.name.clone()),
+        None => claimed,
     }
 }
 
-async fn handle_get_all_info(State(state): State<AppState>) -> (StatusCode, Json<LatestFileInfos>) {
-    let db = DB::new(state.config.db_path).unwrap();
-    let mut latest = vec![];
-    for (_, file_data) in db.file_dbs {
-        let (file_name, line_reviews, comments, metadata) = file_data.get_latest_info().unwrap();
+/// Like `author_of`, for changes that must have an author.
+fn required_author_of(
+    identity: &Option<Extension<Identity>>,
+    claimed: Option<String>,
+) -> Result<String, ApiError> {
+    author_of(identity, claimed).ok_or_else(|| ApiError::missing_parameter("author"))
+}
 
-        let mut extension_allowed = false;
-        for ext in &state.config.allowed_file_extensions {
-            if file_name.ends_with(ext) {
-                extension_allowed = true;
-            }
-        }
-        if !extension_allowed {
-            continue;
-        }
+/// Checks a permission that depends on the request, `authorize_request` already checked the
+/// route. Everything is allowed when authentication is disabled.
+fn authorize(
+    identity: &Option<Extension<Identity>>,
+    permission: Permission,
+) -> Result<(), ApiError> {
+    match identity {
+        Some(Extension(Identity(user))) => Ok(user.authorize(permission)?),
+        None => Ok(()),
+    }
+}
 
-        let mut prefix_allowed = false;
-        for prefix in &state.config.included_prefixes {
-            if file_name.starts_with(prefix) {
-                prefix_allowed = true;
-            }
-        }
-        if !prefix_allowed {
-            continue;
-        }
+/// Reads the file name from the query, relative to the repository.
+fn file_name_from_query(
+    state: &AppState,
+    query: &HashMap<String, String>,
+) -> Result<String, ApiError> {
+    query
+        .get("file_name")
+        .map(|file_name| file_key(file_name, &state.config.repository_path))
+        .ok_or_else(|| ApiError::missing_parameter("file_name"))
+}
 
-        let mut prefix_allowed = true;
-        for prefix in &state.config.excluded_prefixes {
-            if file_name.starts_with(prefix) {
-                prefix_allowed = false;
-            }
-        }
-        if !prefix_allowed {
-            continue;
-        }
+/// Rejects requests to routes that the role of the authenticated user does not allow.
+async fn authorize_request<B>(request: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    if let Some(Identity(user)) = request.extensions().get::<Identity>() {
+        let permission = Permission::of_route(request.method().as_str(), request.uri().path());
+        user.authorize(permission)?;
+    }
+    Ok(next.run(request).await)
+}
 
-        latest.push(LatestFileInfo {
-            file_name,
-            line_reviews,
-            comments: comments.0,
-            metadata,
-        });
+/// Returns the authenticated user, which clients show as the author of their changes.
+async fn handle_get_me(
+    identity: Option<Extension<Identity>>,
+) -> Result<(StatusCode, Json<User>), ApiError> {
+    match identity {
+        Some(Extension(Identity(user))) => Ok((StatusCode::OK, Json(user))),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "authentication_disabled",
+            "Requests are not authenticated",
+        )),
     }
-    (StatusCode::CREATED, Json(LatestFileInfos(latest)))
+}
+
+async fn handle_get_review_state(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<ReviewState>), ApiError> {
+    let file_name = file_name_from_query(&state, &query)?;
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, Some(&file_name))?;
+    let git = Git::new(&state.config.repository_path)?;
+    let review_state = get_review_state(&file_name, db.as_ref(), &git)?;
+    Ok((StatusCode::CREATED, Json(review_state.into())))
+}
+
+async fn handle_get_reviewer_coverage(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<ReviewerCoverage>), ApiError> {
+    let file_name = file_name_from_query(&state, &query)?;
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, Some(&file_name))?;
+    let git = Git::new(&state.config.repository_path)?;
+    let mut coverage: ReviewerCoverage = get_review_state(&file_name, db.as_ref(), &git)?.into();
+    // Only keep the requested reviewer
+    if let Some(reviewer) = query.get("reviewer") {
+        coverage.by_reviewer.retain(|name, _| name == reviewer);
+    }
+    Ok((StatusCode::OK, Json(coverage)))
+}
+
+/// Lists the files in scope, or only `file_name` if given, which clients fetch when it changed.
+async fn handle_get_all_info(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<LatestFileInfos>), ApiError> {
+    let file_name = query
+        .get("file_name")
+        .map(|file_name| file_key(file_name, &state.config.repository_path));
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, file_name.as_ref())?;
+    // In a diff audit only the changed lines of the session are listed
+    let latest = match query.get("session") {
+        Some(session_id) => {
+            let session = db
+                .get_session(session_id)?
+                .ok_or(AuditorError::UnknownSessionId(session_id.clone()))?;
+            let git = Git::new(&state.config.repository_path)?;
+            session.infos(&state.config, db.as_ref(), &git, file_name.as_deref())?
+        }
+        None => infos_in_scope(&state.config, db.as_ref(), file_name.as_deref())?,
+    };
+    Ok((StatusCode::CREATED, Json(LatestFileInfos(latest))))
+}
+
+async fn handle_get_sessions(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<Session>>), ApiError> {
+    let _guard = state.db_lock.read().await;
+    let db = open_findings_storage(&state.config)?;
+    Ok((StatusCode::OK, Json(db.get_sessions()?)))
+}
+
+async fn handle_get_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<(StatusCode, Json<Session>), ApiError> {
+    let _guard = state.db_lock.read().await;
+    let db = open_findings_storage(&state.config)?;
+    let session = db
+        .get_session(&session_id)?
+        .ok_or(AuditorError::UnknownSessionId(session_id))?;
+    Ok((StatusCode::OK, Json(session)))
+}
+
+/// Starts a diff audit of the changes between the base and the head commit.
+async fn handle_create_session(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Json(mut payload): Json<SessionRequest>,
+) -> Result<(StatusCode, Json<Session>), ApiError> {
+    payload.author = author_of(&identity, payload.author);
+    let git = Git::new(&state.config.repository_path)?;
+    let session = Session::new(payload, &state.config, &git)?;
+    let _guard = state.db_lock.write().await;
+    let mut db = open_findings_storage(&state.config)?;
+    db.store_session(session.clone())?;
+    db.save()?;
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+/// Streams every change as a server-sent event with the change as JSON data.
+async fn handle_subscribe_to_changes(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<sse::Event, serde_json::Error>>> {
+    let changes = stream::unfold(state.changes.subscribe(), |mut subscription| async move {
+        let change = subscription.next().await?;
+        Some((sse::Event::default().json_data(change), subscription))
+    });
+    Sse::new(changes).keep_alive(KeepAlive::default())
 }
 
 async fn handle_transform_review_state(
     State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
     Json(payload): Json<Transform>,
-) -> (StatusCode, Json<ReviewState>) {
-    let git = Git::new(&state.config.repository_path).unwrap();
-    let mut db = DB::new(state.config.db_path).unwrap();
-    let file_name = payload.file_name;
-    let file_name = file_name.replace(&state.config.repository_path, "");
-    match transform_review_state(&file_name, &mut db, &git) {
-        Ok(state) => {
-            db.save().unwrap();
-            (StatusCode::CREATED, Json(state.into()))
-        }
-        Err(err) => {
-            tracing::error!("{}", err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ReviewState::default()),
-            )
-        }
-    }
+) -> Result<(StatusCode, Json<ReviewState>), ApiError> {
+    let git = Git::new(&state.config.repository_path)?;
+    let author = author_of(&identity, payload.author);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, None)?;
+    let file_name = file_key(&payload.file_name, &state.config.repository_path);
+    let review_state = transform_review_state(&file_name, author, db.as_mut(), &git)?;
+    db.save()?;
+    // Transforming moves the comments along with the reviewed lines
+    state.changes.publish(Change::Reviews {
+        file_name: file_name.clone(),
+    });
+    state.changes.publish(Change::Comments { file_name });
+    Ok((StatusCode::CREATED, Json(review_state.into())))
 }
 
 async fn handle_update_review_state(
     State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
     Json(payload): Json<UpdateReviewState>,
-) -> StatusCode {
-    let git = Git::new(&state.config.repository_path).unwrap();
+) -> Result<StatusCode, ApiError> {
+    let git = Git::new(&state.config.repository_path)?;
     let mut payload = payload;
-    payload.file_name = payload.file_name.replace(&state.config.repository_path, "");
+    payload.file_name = file_key(&payload.file_name, &state.config.repository_path);
+    payload.reviewer = author_of(&identity, payload.reviewer);
     let file_name = payload.file_name.clone();
-    let mut db = DB::new_single_file(state.config.db_path, &file_name).unwrap();
-    match update_review_state(payload, &mut db, &git) {
-        Ok(_) => {
-            print!("Saving");
-            db.save_file(&file_name).unwrap();
-            StatusCode::CREATED
-        }
-        Err(err) => {
-            tracing::error!("{}", err);
-            StatusCode::INTERNAL_SERVER_ERROR
-        }
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    // Marking reviewed lines as modified or ignored clears them as well
+    if payload.unreviews(db.as_ref(), &git)? {
+        authorize(&identity, Permission::ClearReviews)?;
     }
+    update_review_state(payload, db.as_mut(), &git)?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Reviews { file_name });
+    Ok(StatusCode::CREATED)
 }
 
 async fn handle_create_comment(
     State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
     Json(payload): Json<CreateComment>,
-) -> (StatusCode, Json<String>) {
-    let file_name = payload.file_name.replace(&state.config.repository_path, "");
-    let mut db = DB::new_single_file(state.config.db_path, &file_name).unwrap();
-    match db.add_new_comment(
+) -> Result<(StatusCode, Json<String>), ApiError> {
+    let author = required_author_of(&identity, payload.author)?;
+    let file_name = file_key(&payload.file_name, &state.config.repository_path);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    let new_comment_id = add_comment(
         file_name.clone(),
         payload.line_number,
         payload.body,
-        payload.author,
-    ) {
-        Ok(new_comment_id) => {
-            db.save_file(&file_name).unwrap();
-            (StatusCode::CREATED, Json(new_comment_id))
-        }
-        Err(err) => {
-            tracing::error!("{}", err);
-            (StatusCode::BAD_REQUEST, Json("".to_string()))
-        }
-    }
+        author,
+        db.as_mut(),
+    )?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Comments { file_name });
+    Ok((StatusCode::CREATED, Json(new_comment_id)))
 }
 
-// async fn handle_update_comment(
-//     State(state): State<AppState>,
-//     Path(file_name): Path<String>,
-//     Json(payload): Json<UpdateComment>,
-// ) -> StatusCode {
-//     let mut db = DB::new(state.db_path).unwrap();
-//     match db.update_comment(
-//         file_name,
-//         payload.comment_id,
-//         payload.line_number,
-//         payload.body,
-//         payload.author,
-//     ) {
-//         Ok(_) => StatusCode::CREATED,
-//         Err(err) => {
-//             StatusCode::BAD_REQUEST
-//         }
-//     }
-// }
+async fn handle_update_comment(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Path(comment_id): Path<String>,
+    Json(payload): Json<UpdateComment>,
+) -> Result<StatusCode, ApiError> {
+    let author = required_author_of(&identity, payload.author)?;
+    // Forcing edits the comments of others
+    if payload.force {
+        authorize(&identity, Permission::ModerateComments)?;
+    }
+    let file_name = file_key(&payload.file_name, &state.config.repository_path);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    update_comment(
+        file_name.clone(),
+        comment_id,
+        payload.line_number,
+        payload.body,
+        author,
+        payload.force,
+        db.as_mut(),
+    )?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Comments { file_name });
+    Ok(StatusCode::CREATED)
+}
 
 async fn handle_delete_comment(
     State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
     Json(payload): Json<DeleteComment>,
-) -> StatusCode {
-    let file_name = payload.file_name.replace(&state.config.repository_path, "");
-    let mut db = DB::new_single_file(state.config.db_path, &file_name).unwrap();
-    match db.delete_comment(file_name.clone(), payload.comment_id, payload.line_number) {
-        Ok(_) => {
-            db.save_file(&file_name).unwrap();
-            StatusCode::CREATED
-        }
-        Err(err) => {
-            tracing::error!("{}", err);
-            StatusCode::BAD_REQUEST
-        }
+) -> Result<StatusCode, ApiError> {
+    let author = author_of(&identity, payload.author);
+    let file_name = file_key(&payload.file_name, &state.config.repository_path);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    let comments = db.get_file_comments(&file_name)?;
+    let comment_author = comments
+        .as_ref()
+        .and_then(|comments| comments.author_of(payload.line_number, &payload.comment_id));
+    if comment_author.is_some_and(|comment_author| Some(comment_author) != author.as_deref()) {
+        authorize(&identity, Permission::ModerateComments)?;
     }
+    delete_comment(
+        file_name.clone(),
+        payload.comment_id,
+        payload.line_number,
+        author,
+        db.as_mut(),
+    )?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Comments { file_name });
+    Ok(StatusCode::CREATED)
+}
+
+async fn handle_reply_to_comment(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Json(payload): Json<ReplyToComment>,
+) -> Result<(StatusCode, Json<String>), ApiError> {
+    let author = required_author_of(&identity, payload.author)?;
+    let file_name = file_key(&payload.file_name, &state.config.repository_path);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    let new_reply_id = reply_to_comment(
+        file_name.clone(),
+        payload.line_number,
+        payload.thread_id,
+        payload.body,
+        author,
+        db.as_mut(),
+    )?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Comments { file_name });
+    Ok((StatusCode::CREATED, Json(new_reply_id)))
+}
+
+async fn handle_resolve_thread(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Json(payload): Json<UpdateThread>,
+) -> Result<StatusCode, ApiError> {
+    let thread_state = match payload.state {
+        None => ThreadState::Resolved,
+        Some(ThreadState::Open) => {
+            return Err(ApiError::bad_request(
+                "invalid_thread_state",
+                "A thread is resolved as either Resolved or WontFix",
+            ))
+        }
+        Some(thread_state) => thread_state,
+    };
+    update_thread_state(state, identity, payload, thread_state).await
+}
+
+async fn handle_reopen_thread(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Json(payload): Json<UpdateThread>,
+) -> Result<StatusCode, ApiError> {
+    update_thread_state(state, identity, payload, ThreadState::Open).await
+}
+
+async fn update_thread_state(
+    state: AppState,
+    identity: Option<Extension<Identity>>,
+    payload: UpdateThread,
+    thread_state: ThreadState,
+) -> Result<StatusCode, ApiError> {
+    let author = author_of(&identity, payload.author);
+    let file_name = file_key(&payload.file_name, &state.config.repository_path);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    set_thread_state(
+        file_name.clone(),
+        payload.line_number,
+        payload.thread_id,
+        thread_state,
+        author,
+        db.as_mut(),
+    )?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Comments { file_name });
+    Ok(StatusCode::CREATED)
 }
 
 async fn handle_get_comments(
     State(state): State<AppState>,
     Query(query): Query<HashMap<String, String>>,
-) -> (StatusCode, Json<FileComments>) {
-    let file_name = query.get(&"file_name".to_string()).unwrap();
-    let file_name = file_name.replace(&state.config.repository_path, "");
-    let db = DB::new_single_file(state.config.db_path, &file_name).unwrap();
-
-    match db.get_file_comments(&file_name) {
-        Some(comments) => (StatusCode::CREATED, Json(comments)),
-        None => (
-            StatusCode::BAD_REQUEST,
-            Json(FileComments(HashMap::default())),
-        ),
-    }
+) -> Result<(StatusCode, Json<FileComments>), ApiError> {
+    let file_name = file_name_from_query(&state, &query)?;
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, Some(&file_name))?;
+    // A file without comments yet is not an error
+    let comments = db
+        .get_file_comments(&file_name)?
+        .unwrap_or_else(|| FileComments(HashMap::default()));
+    Ok((StatusCode::CREATED, Json(comments)))
 }
 
 async fn handle_update_metadata(
     State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
     Json(payload): Json<UpdateMetadataRequest>,
-) -> StatusCode {
+) -> Result<StatusCode, ApiError> {
     let mut payload = payload;
-    payload.file_name = payload.file_name.replace(&state.config.repository_path, "");
+    payload.file_name = file_key(&payload.file_name, &state.config.repository_path);
+    payload.author = author_of(&identity, payload.author);
     let file_name = payload.file_name.clone();
-    let mut db = DB::new_single_file(state.config.db_path, &file_name).unwrap();
-    match update_metadata(payload, &mut db) {
-        Ok(_) => {
-            print!("Saving");
-            db.save_file(&file_name).unwrap();
-            StatusCode::CREATED
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, Some(&file_name))?;
+    // Anyone may change the note, only leads assign files
+    if payload.assigns(db.get_metadata(&file_name)?.as_ref()) {
+        authorize(&identity, Permission::AssignFiles)?;
+    }
+    update_metadata(payload, db.as_mut())?;
+    db.save_file(&file_name)?;
+    state.changes.publish(Change::Metadata { file_name });
+    Ok(StatusCode::CREATED)
+}
+
+async fn handle_get_history(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<Vec<Event>>), ApiError> {
+    let file_name = file_name_from_query(&state, &query)?;
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, Some(&file_name))?;
+    Ok((StatusCode::OK, Json(db.events_of_file(&file_name)?)))
+}
+
+async fn handle_revert_event(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Json(payload): Json<Revert>,
+) -> Result<StatusCode, ApiError> {
+    let author = author_of(&identity, payload.author);
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, None)?;
+    // Reverting needs the permissions of the change that undoes the event
+    if let Some(Extension(Identity(user))) = &identity {
+        authorize_revert(&payload.event_id, user, db.as_ref())?;
+    }
+    revert_event(&payload.event_id, author, db.as_mut())?;
+    db.save()?;
+    if let Some(event) = db.find_event(&payload.event_id)? {
+        for change in Change::of_event(&event) {
+            state.changes.publish(change);
+        }
+    }
+    Ok(StatusCode::CREATED)
+}
+
+async fn handle_get_report(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, [(http::HeaderName, &'static str); 1], String), ApiError> {
+    let format = match query.get("format") {
+        Some(format) => format.parse()?,
+        None => ReportFormat::Markdown,
+    };
+    let content_type = match format {
+        ReportFormat::Markdown => "text/markdown; charset=utf-8",
+        ReportFormat::Html => "text/html; charset=utf-8",
+    };
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, None)?;
+    let git = Git::new(&state.config.repository_path)?;
+    let report = Report::generate(&state.config, db.as_ref(), &git)?;
+    Ok((
+        StatusCode::OK,
+        [(http::header::CONTENT_TYPE, content_type)],
+        report.render(format),
+    ))
+}
+
+async fn handle_get_sarif(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<SarifLog>), ApiError> {
+    let _guard = state.db_lock.read().await;
+    let db = open_storage(&state.config, None)?;
+    let log = sarif::export(&state.config, db.as_ref())?;
+    Ok((StatusCode::OK, Json(log)))
+}
+
+async fn handle_import_sarif(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Query(query): Query<HashMap<String, String>>,
+    Json(payload): Json<SarifLog>,
+) -> Result<(StatusCode, Json<ImportSummary>), ApiError> {
+    let raise_priority = query
+        .get("raise_priority")
+        .is_some_and(|raise_priority| raise_priority == "true");
+    if raise_priority {
+        authorize(&identity, Permission::AssignFiles)?;
+    }
+    // The comments are written by whoever imports the log, not by the tool it names
+    let author = required_author_of(&identity, query.get("author").cloned())?;
+    let _guard = state.db_lock.write().await;
+    let mut db = open_storage(&state.config, None)?;
+    let summary = sarif::import(
+        &payload,
+        &state.config,
+        raise_priority,
+        Some(author),
+        db.as_mut(),
+    )?;
+    db.save()?;
+    // Comments may have been added to many files
+    state.changes.publish(Change::Resync);
+    Ok((StatusCode::OK, Json(summary)))
+}
+
+async fn handle_get_findings(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<Vec<Finding>>), ApiError> {
+    let _guard = state.db_lock.read().await;
+    let db = open_findings_storage(&state.config)?;
+    let findings = db.get_findings()?;
+    // Optionally only list the findings that have a location in the file
+    let findings = match query.get("file_name") {
+        Some(file_name) => {
+            let file_name = file_key(file_name, &state.config.repository_path);
+            findings
+                .into_iter()
+                .filter(|finding| finding.touches(&file_name))
+                .collect()
+        }
+        None => findings,
+    };
+    Ok((StatusCode::OK, Json(findings)))
+}
+
+async fn handle_get_finding(
+    State(state): State<AppState>,
+    Path(finding_id): Path<String>,
+) -> Result<(StatusCode, Json<Finding>), ApiError> {
+    let _guard = state.db_lock.read().await;
+    let db = open_findings_storage(&state.config)?;
+    let finding = db
+        .get_finding(&finding_id)?
+        .ok_or(AuditorError::UnknownFindingId(finding_id))?;
+    Ok((StatusCode::OK, Json(finding)))
+}
+
+/// Makes the locations relative to the repository and attributes the finding to the
+/// authenticated user.
+fn prepare_finding_request(
+    state: &AppState,
+    identity: &Option<Extension<Identity>>,
+    payload: FindingRequest,
+) -> Result<FindingRequest, ApiError> {
+    let mut payload = payload;
+    let claimed = Some(payload.author).filter(|author| !author.is_empty());
+    payload.author = required_author_of(identity, claimed)?;
+    for location in payload.locations.iter_mut() {
+        location.file_name = file_key(&location.file_name, &state.config.repository_path);
+    }
+    Ok(payload)
+}
+
+async fn handle_create_finding(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Json(payload): Json<FindingRequest>,
+) -> Result<(StatusCode, Json<String>), ApiError> {
+    let payload = prepare_finding_request(&state, &identity, payload)?;
+    let _guard = state.db_lock.write().await;
+    let mut db = open_findings_storage(&state.config)?;
+    let id = create_finding(payload, db.as_mut())?;
+    db.save()?;
+    state.changes.publish(Change::Findings);
+    Ok((StatusCode::CREATED, Json(id)))
+}
+
+async fn handle_update_finding(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Path(finding_id): Path<String>,
+    Json(payload): Json<FindingRequest>,
+) -> Result<StatusCode, ApiError> {
+    let payload = prepare_finding_request(&state, &identity, payload)?;
+    let _guard = state.db_lock.write().await;
+    let mut db = open_findings_storage(&state.config)?;
+    update_finding(&finding_id, payload, db.as_mut())?;
+    db.save()?;
+    state.changes.publish(Change::Findings);
+    Ok(StatusCode::CREATED)
+}
+
+async fn handle_delete_finding(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Path(finding_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let _guard = state.db_lock.write().await;
+    let mut db = open_findings_storage(&state.config)?;
+    let finding_author = db.get_finding(&finding_id)?.map(|finding| finding.author);
+    if finding_author.is_some() && finding_author != author_of(&identity, None) {
+        authorize(&identity, Permission::ModerateFindings)?;
+    }
+    db.delete_finding(&finding_id)?;
+    db.save()?;
+    state.changes.publish(Change::Findings);
+    Ok(StatusCode::CREATED)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use auditor::State as LineState;
+    use git2::{Repository, Signature};
+    use std::path::Path as FilePath;
+
+    /// A service with authentication over a repository with `file_name` committed and an empty
+    /// JSON db.
+    fn app_state(file_name: &str, content: &str) -> AppState {
+        let repository_path =
+            std::env::temp_dir().join(format!("auditor-main-{}", uuid::Uuid::new_v4()));
+        let repo = Repository::init(&repository_path).unwrap();
+        std::fs::write(repository_path.join(file_name), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(FilePath::new(file_name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("auditor", "auditor@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, file_name, &tree, &[])
+            .unwrap();
+        let repository_path = repository_path.to_str().unwrap().to_string();
+        let db_path = repository_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        AppState {
+            config: Config {
+                repository_path,
+                db_path,
+                db_backend: "json".to_string(),
+                included_prefixes: vec!["".to_string()],
+                authentication: true,
+                ..Config::default()
+            },
+            db_lock: Arc::new(RwLock::new(())),
+            changes: Changes::new(16),
         }
-        Err(err) => {
-            tracing::error!("{}", err);
-            StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn identity(name: &str, role: Role) -> Option<Extension<Identity>> {
+        Some(Extension(Identity(User {
+            name: name.to_string(),
+            created_at: 0,
+            role,
+        })))
+    }
+
+    #[tokio::test]
+    async fn test_unreviewing_needs_permission() {
+        let state = app_state("a.c", "1\n2\n3\n4\n");
+        let mark = |lines, review_state| {
+            Json(UpdateReviewState::new(
+                "a.c".to_string(),
+                lines,
+                review_state,
+                5,
+                None,
+            ))
+        };
+        let update = |user, lines, review_state| {
+            handle_update_review_state(
+                State(state.clone()),
+                identity(user, Role::Auditor),
+                mark(lines, review_state),
+            )
+        };
+        assert!(update("alice", 0..=1, LineState::Reviewed).await.is_ok());
+
+        // Marking reviewed lines as modified or ignored removes them from the reviewed lines
+        for review_state in [LineState::Modified, LineState::Ignored, LineState::Cleared] {
+            let err = update("bob", 1..=2, review_state).await.unwrap_err();
+            assert_eq!(err.status, StatusCode::FORBIDDEN);
         }
+        assert!(update("bob", 2..=3, LineState::Modified).await.is_ok());
+        let lead = identity("carol", Role::Lead);
+        let result =
+            handle_update_review_state(State(state.clone()), lead, mark(0..=0, LineState::Ignored));
+        assert!(result.await.is_ok());
+
+        let db = open_storage(&state.config, None).unwrap();
+        let git = Git::new(&state.config.repository_path).unwrap();
+        let review = get_review_state(&"a.c".to_string(), db.as_ref(), &git).unwrap();
+        assert_eq!(review.reviewed, vec![1..=1]);
+        assert_eq!(review.modified, vec![2..=3]);
+        assert_eq!(review.ignored, vec![0..=0]);
+    }
+
+    #[tokio::test]
+    async fn test_imported_comments_are_written_by_the_importer() {
+        let state = app_state("a.c", "1\n2\n");
+        // A tool named like a lead does not make the comments the lead's
+        let log: SarifLog = serde_json::from_value(serde_json::json!({
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {"driver": {"name": "carol"}},
+                "results": [{
+                    "message": {"text": "Looks fine"},
+                    "locations": [{"physicalLocation": {
+                        "artifactLocation": {"uri": "a.c"},
+                        "region": {"startLine": 2},
+                    }}],
+                }],
+            }],
+        }))
+        .unwrap();
+        let result = handle_import_sarif(
+            State(state.clone()),
+            identity("bob", Role::Auditor),
+            Query(HashMap::from([("author".to_string(), "carol".to_string())])),
+            Json(log),
+        );
+        assert_eq!(result.await.unwrap().1.added, 1);
+
+        let db = open_storage(&state.config, None).unwrap();
+        let comment = &db.get_file_comments("a.c").unwrap().unwrap().0[&1][0];
+        assert_eq!(comment.author, "bob");
+        assert_eq!(comment.body, "[carol] Looks fine");
+    }
+
+    #[test]
+    fn test_token_of() {
+        let request = |uri: &str, header: Option<&str>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(header) = header {
+                request = request.header(http::header::AUTHORIZATION, header);
+            }
+            request.body(()).unwrap()
+        };
+        assert_eq!(
+            token_of(&request("/info", Some("Bearer aud_1"))),
+            Some("aud_1".to_string())
+        );
+        assert_eq!(
+            token_of(&request("/changes?access_token=aud_2", None)),
+            Some("aud_2".to_string())
+        );
+        // Tokens in the query of other routes would end up in logs and browser histories
+        assert_eq!(token_of(&request("/info?access_token=aud_2", None)), None);
+        assert_eq!(token_of(&request("/changes", None)), None);
     }
 }
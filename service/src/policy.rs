@@ -0,0 +1,153 @@
+use crate::{config::Config, LatestFileInfo, Priority, StoredReviewForFile};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PolicyStatus {
+    // Every line that is not ignored has enough reviewers
+    Satisfied,
+    // Some lines have enough reviewers
+    PartiallySatisfied,
+    // No line has enough reviewers
+    Violated,
+    // The file was never marked, so it is not known which lines need a sign-off
+    Pending,
+}
+
+/// High priority files only count as reviewed once enough distinct reviewers marked the same lines.
+#[derive(Clone, Debug)]
+pub struct SignOffPolicy {
+    pub required_reviewers: usize,
+}
+
+impl SignOffPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            required_reviewers: config.high_priority_reviewers,
+        }
+    }
+
+    pub fn applies_to(&self, info: &LatestFileInfo) -> bool {
+        matches!(
+            info.metadata.as_ref().map(|metadata| &metadata.priority),
+            Some(Priority::High)
+        )
+    }
+
+    /// Only the lines in `scope` need a sign-off if it is given, like in a diff audit. The
+    /// reviews are expected to be limited to the scope already.
+    pub fn evaluate(
+        &self,
+        line_reviews: &StoredReviewForFile,
+        scope: Option<&[RangeInclusive<usize>]>,
+    ) -> PolicyStatus {
+        let count = |ranges: &[RangeInclusive<usize>]| -> usize {
+            ranges
+                .iter()
+                .map(|range| range.end() - range.start() + 1)
+                .sum()
+        };
+        if line_reviews.total_lines == 0 {
+            return PolicyStatus::Pending;
+        }
+        let lines = scope.map_or(line_reviews.total_lines, count);
+        let to_review = lines.saturating_sub(count(&line_reviews.ignored));
+        let signed_off = count(&line_reviews.reviewed_by_at_least(self.required_reviewers));
+        if signed_off >= to_review {
+            PolicyStatus::Satisfied
+        } else if signed_off > 0 {
+            PolicyStatus::PartiallySatisfied
+        } else {
+            PolicyStatus::Violated
+        }
+    }
+
+    /// Sets the policy status of the file and only keeps the lines that were signed off as
+    /// reviewed. Files that the policy does not apply to are left untouched.
+    pub fn apply(&self, info: &mut LatestFileInfo) {
+        if !self.applies_to(info) {
+            return;
+        }
+        info.policy_status = Some(self.evaluate(&info.line_reviews, info.scope.as_deref()));
+        info.line_reviews.reviewed = info
+            .line_reviews
+            .reviewed_by_at_least(self.required_reviewers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Metadata;
+    use std::collections::HashMap;
+
+    fn info(priority: Priority, reviewers: Vec<(&str, RangeInclusive<usize>)>) -> LatestFileInfo {
+        let mut line_reviews = StoredReviewForFile::default();
+        line_reviews.total_lines = 10;
+        line_reviews.ignored = vec![RangeInclusive::new(8, 9)];
+        for (reviewer, range) in reviewers {
+            line_reviews.mark_reviewed_by(reviewer, &range);
+        }
+        LatestFileInfo {
+            file_name: "file1".to_string(),
+            reviewer_coverage: line_reviews.reviewer_coverage(),
+            line_reviews,
+            comments: HashMap::default(),
+            metadata: Some(Metadata {
+                priority,
+                reviewer: "alice".to_string(),
+                note: "".to_string(),
+            }),
+            aliases: vec![],
+            open_threads: 0,
+            resolved_threads: 0,
+            policy_status: None,
+            scope: None,
+        }
+    }
+
+    #[test]
+    fn test_sign_off_policy() {
+        let policy = SignOffPolicy {
+            required_reviewers: 2,
+        };
+
+        // Only high priority files are checked
+        let mut low = info(Priority::Low, vec![("alice", 0..=7)]);
+        policy.apply(&mut low);
+        assert_eq!(low.policy_status, None);
+        assert_eq!(low.line_reviews.reviewed, vec![0..=7]);
+
+        let mut violated = info(Priority::High, vec![("alice", 0..=7)]);
+        policy.apply(&mut violated);
+        assert_eq!(violated.policy_status, Some(PolicyStatus::Violated));
+        assert_eq!(violated.line_reviews.reviewed, vec![]);
+
+        let mut partial = info(Priority::High, vec![("alice", 0..=7), ("bob", 2..=4)]);
+        policy.apply(&mut partial);
+        assert_eq!(
+            partial.policy_status,
+            Some(PolicyStatus::PartiallySatisfied)
+        );
+        assert_eq!(partial.line_reviews.reviewed, vec![2..=4]);
+
+        // Ignored lines do not need a sign-off
+        let mut satisfied = info(Priority::High, vec![("alice", 0..=7), ("bob", 0..=7)]);
+        policy.apply(&mut satisfied);
+        assert_eq!(satisfied.policy_status, Some(PolicyStatus::Satisfied));
+
+        // A file that only has metadata or comments was not signed off
+        let mut unmarked = info(Priority::High, vec![]);
+        unmarked.line_reviews = StoredReviewForFile::default();
+        policy.apply(&mut unmarked);
+        assert_eq!(unmarked.policy_status, Some(PolicyStatus::Pending));
+
+        // Only the lines in scope need a sign-off
+        let mut scoped = info(Priority::High, vec![("alice", 2..=3), ("bob", 2..=3)]);
+        scoped.scope = Some(vec![2..=3]);
+        scoped.line_reviews.ignored = vec![];
+        policy.apply(&mut scoped);
+        assert_eq!(scoped.policy_status, Some(PolicyStatus::Satisfied));
+    }
+}
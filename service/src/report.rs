@@ -0,0 +1,566 @@
+use crate::{
+    config::Config,
+    errors::AuditorError,
+    finding::{Finding, FindingStatus, Severity},
+    git::Git,
+    policy::{PolicyStatus, SignOffPolicy},
+    storage::Storage,
+    LatestFileInfo, ThreadState,
+};
+use anyhow::Result;
+use std::{collections::HashMap, ops::RangeInclusive, str::FromStr};
+
+/// Lines shown above and below a commented line.
+const CONTEXT_LINES: usize = 2;
+
+/// Locations of findings that are longer are cut after this many lines.
+const MAX_SNIPPET_LINES: usize = 20;
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+body { font-family: sans-serif; max-width: 60rem; margin: 2rem auto; color: #1f2937; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #d1d5db; padding: 0.25rem 0.5rem; text-align: left; }
+pre { background: #f3f4f6; padding: 0.5rem; overflow-x: auto; }
+</style>
+</head>
+<body>
+{{body}}
+</body>
+</html>
+"#;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = AuditorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            format => Err(AuditorError::UnknownReportFormat(format.to_string())),
+        }
+    }
+}
+
+/// Building blocks of a report, which are rendered either as Markdown or as HTML.
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    List(Vec<String>),
+    // Header and rows
+    Table(Vec<String>, Vec<Vec<String>>),
+    // Number of the first line, starting at 0, and the lines of code
+    Code(usize, Vec<String>),
+}
+
+/// The audit report covering the scope, the coverage, the findings and the comments.
+pub struct Report {
+    title: String,
+    blocks: Vec<Block>,
+}
+
+/// Reads files at the commit they were last reviewed at, or at HEAD if they were never reviewed.
+struct Sources<'a> {
+    db: &'a dyn Storage,
+    git: &'a Git,
+    files: HashMap<String, Option<Vec<String>>>,
+}
+
+impl<'a> Sources<'a> {
+    fn lines(&mut self, file_name: &str) -> Option<&Vec<String>> {
+        if !self.files.contains_key(file_name) {
+            let commit = match self.db.latest_reviewed_commit(file_name) {
+                Ok(Some(commit)) => Some(commit),
+                _ => self.git.current_commit().ok(),
+            };
+            // A missing commit or file only means that the report has no snippet for it
+            let lines = commit
+                .and_then(|commit| self.git.file_content_at(&commit, file_name).ok())
+                .flatten()
+                .map(|content| content.lines().map(|line| line.to_string()).collect());
+            self.files.insert(file_name.to_string(), lines);
+        }
+        self.files[file_name].as_ref()
+    }
+
+    fn snippet(&mut self, file_name: &str, lines: RangeInclusive<usize>) -> Option<Block> {
+        let content = self.lines(file_name)?;
+        let start = *lines.start();
+        if start >= content.len() {
+            return None;
+        }
+        let end = (*lines.end())
+            .min(content.len() - 1)
+            .min(start + MAX_SNIPPET_LINES - 1);
+        Some(Block::Code(start, content[start..=end].to_vec()))
+    }
+}
+
+pub(crate) fn count(ranges: &[RangeInclusive<usize>]) -> usize {
+    ranges
+        .iter()
+        .map(|range| range.end() - range.start() + 1)
+        .sum()
+}
+
+pub(crate) fn percent(lines: usize, total_lines: usize) -> usize {
+    if total_lines == 0 {
+        return 0;
+    }
+    (100 * lines / total_lines).min(100)
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "Critical",
+        Severity::High => "High",
+        Severity::Medium => "Medium",
+        Severity::Low => "Low",
+        Severity::Informational => "Informational",
+    }
+}
+
+fn status_name(status: FindingStatus) -> &'static str {
+    match status {
+        FindingStatus::Open => "open",
+        FindingStatus::Confirmed => "confirmed",
+        FindingStatus::Fixed => "fixed",
+        FindingStatus::FalsePositive => "false positive",
+    }
+}
+
+fn lines_name(lines: &RangeInclusive<usize>) -> String {
+    if lines.start() == lines.end() {
+        format!("line {}", lines.start() + 1)
+    } else {
+        format!("lines {}-{}", lines.start() + 1, lines.end() + 1)
+    }
+}
+
+impl Report {
+    /// Collects the files that are in scope of the config along with every finding.
+    pub fn generate(config: &Config, db: &dyn Storage, git: &Git) -> Result<Self> {
+        let policy = SignOffPolicy::from_config(config);
+        let mut infos: Vec<LatestFileInfo> = db
+            .latest_infos()?
+            .into_iter()
+            .filter(|info| config.in_scope(&info.file_name))
+            .collect();
+        infos.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        for info in infos.iter_mut() {
+            policy.apply(info);
+        }
+        let mut findings = db.get_findings()?;
+        findings.sort_by_key(|finding| finding.severity);
+
+        let mut sources = Sources {
+            db,
+            git,
+            files: HashMap::default(),
+        };
+        let title = "Audit report".to_string();
+        let mut blocks = vec![Block::Heading(1, title.clone())];
+        if let Ok(head) = git.current_commit() {
+            blocks.push(Block::Paragraph(format!(
+                "Repository {} at commit {head}.",
+                config.repository_path
+            )));
+        }
+        Self::summary(&infos, &findings, &mut blocks);
+        Self::scope(&infos, db, &mut blocks)?;
+        Self::findings(&findings, &mut sources, &mut blocks);
+        Self::comments(&infos, &mut sources, &mut blocks);
+        Ok(Self { title, blocks })
+    }
+
+    fn summary(infos: &[LatestFileInfo], findings: &[Finding], blocks: &mut Vec<Block>) {
+        let total_lines: usize = infos.iter().map(|info| info.line_reviews.total_lines).sum();
+        let reviewed_lines: usize = infos
+            .iter()
+            .map(|info| count(&info.line_reviews.reviewed))
+            .sum();
+        let mut by_severity: Vec<String> = vec![];
+        for severity in [
+            Severity::Critical,
+            Severity::High,
+            Severity::Medium,
+            Severity::Low,
+            Severity::Informational,
+        ] {
+            let found = findings
+                .iter()
+                .filter(|finding| finding.severity == severity)
+                .count();
+            if found > 0 {
+                by_severity.push(format!(
+                    "{found} {}",
+                    severity_name(severity).to_lowercase()
+                ));
+            }
+        }
+        let open_threads: usize = infos.iter().map(|info| info.open_threads).sum();
+        let resolved_threads: usize = infos.iter().map(|info| info.resolved_threads).sum();
+
+        blocks.push(Block::Heading(2, "Summary".to_string()));
+        blocks.push(Block::List(vec![
+            format!("{} files in scope", infos.len()),
+            format!(
+                "{}% of {total_lines} lines reviewed",
+                percent(reviewed_lines, total_lines)
+            ),
+            if by_severity.is_empty() {
+                "No findings".to_string()
+            } else {
+                format!("Findings: {}", by_severity.join(", "))
+            },
+            format!("{open_threads} open and {resolved_threads} resolved comment threads"),
+        ]));
+    }
+
+    fn scope(infos: &[LatestFileInfo], db: &dyn Storage, blocks: &mut Vec<Block>) -> Result<()> {
+        let header = [
+            "File",
+            "Priority",
+            "Reviewer",
+            "Reviewed",
+            "Modified",
+            "Ignored",
+            "Reviewed by",
+            "Sign-off",
+            "Reviewed at",
+        ];
+        let mut rows = vec![];
+        for info in infos {
+            let reviews = &info.line_reviews;
+            let (priority, reviewer) = match &info.metadata {
+                Some(metadata) => (
+                    format!("{:?}", metadata.priority),
+                    metadata.reviewer.clone(),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let mut coverage: Vec<(&String, &usize)> = info.reviewer_coverage.iter().collect();
+            coverage.sort();
+            let coverage: Vec<String> = coverage
+                .into_iter()
+                .map(|(reviewer, percent)| format!("{reviewer} {percent}%"))
+                .collect();
+            let sign_off = match &info.policy_status {
+                Some(PolicyStatus::Satisfied) => "satisfied",
+                Some(PolicyStatus::PartiallySatisfied) => "partially satisfied",
+                Some(PolicyStatus::Violated) => "violated",
+                Some(PolicyStatus::Pending) => "pending",
+                None => "-",
+            };
+            let commit = db
+                .latest_reviewed_commit(&info.file_name)?
+                .map(|commit| commit.chars().take(8).collect())
+                .unwrap_or("-".to_string());
+            rows.push(vec![
+                info.file_name.clone(),
+                priority,
+                reviewer,
+                format!(
+                    "{}%",
+                    percent(count(&reviews.reviewed), reviews.total_lines)
+                ),
+                format!(
+                    "{}%",
+                    percent(count(&reviews.modified), reviews.total_lines)
+                ),
+                format!("{}%", percent(count(&reviews.ignored), reviews.total_lines)),
+                coverage.join(", "),
+                sign_off.to_string(),
+                commit,
+            ]);
+        }
+        blocks.push(Block::Heading(2, "Scope".to_string()));
+        blocks.push(Block::Table(
+            header.iter().map(|column| column.to_string()).collect(),
+            rows,
+        ));
+        Ok(())
+    }
+
+    fn findings(findings: &[Finding], sources: &mut Sources, blocks: &mut Vec<Block>) {
+        blocks.push(Block::Heading(2, "Findings".to_string()));
+        if findings.is_empty() {
+            blocks.push(Block::Paragraph("No findings.".to_string()));
+        }
+        for finding in findings {
+            blocks.push(Block::Heading(
+                3,
+                format!("{}: {}", severity_name(finding.severity), finding.title),
+            ));
+            blocks.push(Block::List(vec![
+                format!("Status: {}", status_name(finding.status)),
+                format!(
+                    "Category: {}",
+                    finding.category.clone().unwrap_or("-".to_string())
+                ),
+                format!("Reported by: {}", finding.author),
+            ]));
+            blocks.push(Block::Paragraph(finding.description.clone()));
+            if !finding.recommendation.is_empty() {
+                blocks.push(Block::Paragraph(format!(
+                    "Recommendation: {}",
+                    finding.recommendation
+                )));
+            }
+            for location in &finding.locations {
+                blocks.push(Block::Paragraph(format!(
+                    "{}, {}",
+                    location.file_name,
+                    lines_name(&location.lines)
+                )));
+                if let Some(snippet) = sources.snippet(&location.file_name, location.lines.clone())
+                {
+                    blocks.push(snippet);
+                }
+            }
+        }
+    }
+
+    fn comments(infos: &[LatestFileInfo], sources: &mut Sources, blocks: &mut Vec<Block>) {
+        blocks.push(Block::Heading(2, "Comments".to_string()));
+        if infos.iter().all(|info| info.comments.is_empty()) {
+            blocks.push(Block::Paragraph("No comments.".to_string()));
+        }
+        for info in infos.iter().filter(|info| !info.comments.is_empty()) {
+            blocks.push(Block::Heading(3, info.file_name.clone()));
+            let mut line_numbers: Vec<&usize> = info.comments.keys().collect();
+            line_numbers.sort();
+            for line_number in line_numbers {
+                for thread in &info.comments[line_number] {
+                    let state = match thread.state {
+                        ThreadState::Open => "open",
+                        ThreadState::Resolved => "resolved",
+                        ThreadState::WontFix => "won't fix",
+                    };
+                    blocks.push(Block::Paragraph(format!(
+                        "Line {} ({state})",
+                        line_number + 1
+                    )));
+                    let mut messages = vec![format!("{}: {}", thread.author, thread.body)];
+                    for reply in &thread.replies {
+                        messages.push(format!("{}: {}", reply.author, reply.body));
+                    }
+                    blocks.push(Block::List(messages));
+                    let context = RangeInclusive::new(
+                        line_number.saturating_sub(CONTEXT_LINES),
+                        line_number + CONTEXT_LINES,
+                    );
+                    if let Some(snippet) = sources.snippet(&info.file_name, context) {
+                        blocks.push(snippet);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.render_markdown(),
+            ReportFormat::Html => self.render_html(),
+        }
+    }
+
+    fn render_markdown(&self) -> String {
+        let cell = |text: &String| text.replace('|', "\\|").replace('\n', " ");
+        let mut out = String::new();
+        for block in &self.blocks {
+            match block {
+                Block::Heading(level, text) => {
+                    out.push_str(&format!("{} {text}\n\n", "#".repeat(*level)))
+                }
+                Block::Paragraph(text) => out.push_str(&format!("{text}\n\n")),
+                Block::List(items) => {
+                    for item in items {
+                        out.push_str(&format!("- {}\n", item.replace('\n', "\n  ")));
+                    }
+                    out.push('\n');
+                }
+                Block::Table(header, rows) => {
+                    let header: Vec<String> = header.iter().map(cell).collect();
+                    out.push_str(&format!("| {} |\n", header.join(" | ")));
+                    out.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+                    for row in rows {
+                        let row: Vec<String> = row.iter().map(cell).collect();
+                        out.push_str(&format!("| {} |\n", row.join(" | ")));
+                    }
+                    out.push('\n');
+                }
+                Block::Code(first_line, lines) => {
+                    out.push_str("```\n");
+                    for (i, line) in lines.iter().enumerate() {
+                        out.push_str(&format!("{:>5} | {line}\n", first_line + i + 1));
+                    }
+                    out.push_str("```\n\n");
+                }
+            }
+        }
+        out
+    }
+
+    fn render_html(&self) -> String {
+        let mut body = String::new();
+        for block in &self.blocks {
+            match block {
+                Block::Heading(level, text) => {
+                    body.push_str(&format!("<h{level}>{}</h{level}>\n", escape_html(text)))
+                }
+                Block::Paragraph(text) => body.push_str(&format!("<p>{}</p>\n", escape_html(text))),
+                Block::List(items) => {
+                    body.push_str("<ul>\n");
+                    for item in items {
+                        body.push_str(&format!("<li>{}</li>\n", escape_html(item)));
+                    }
+                    body.push_str("</ul>\n");
+                }
+                Block::Table(header, rows) => {
+                    body.push_str("<table>\n<tr>");
+                    for column in header {
+                        body.push_str(&format!("<th>{}</th>", escape_html(column)));
+                    }
+                    body.push_str("</tr>\n");
+                    for row in rows {
+                        body.push_str("<tr>");
+                        for cell in row {
+                            body.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                        }
+                        body.push_str("</tr>\n");
+                    }
+                    body.push_str("</table>\n");
+                }
+                Block::Code(first_line, lines) => {
+                    body.push_str("<pre><code>");
+                    for (i, line) in lines.iter().enumerate() {
+                        body.push_str(&format!(
+                            "{:>5} | {}\n",
+                            first_line + i + 1,
+                            escape_html(line)
+                        ));
+                    }
+                    body.push_str("</code></pre>\n");
+                }
+            }
+        }
+        HTML_TEMPLATE
+            .replace("{{title}}", &escape_html(&self.title))
+            .replace("{{body}}", &body)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        add_comment,
+        db::DB,
+        finding::{create_finding, FindingRequest, Location},
+        git, update_review_state, State, UpdateReviewState,
+    };
+
+    #[test]
+    fn test_report() {
+        let repo_path = git::tests::init_repo();
+        let content = "fn main() {\n    let a = b + 1;\n    if a < 3 {}\n}\n";
+        git::tests::commit_file(&repo_path, "src/main.rs", content);
+        git::tests::commit_file(&repo_path, "src/lib.go", "package lib\n");
+        let git = git::Git::new(&repo_path).unwrap();
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = DB::new(db_path).unwrap();
+        let config = Config {
+            repository_path: repo_path,
+            allowed_file_extensions: vec![".rs".to_string()],
+            included_prefixes: vec!["".to_string()],
+            ..Config::default()
+        };
+
+        update_review_state(
+            UpdateReviewState {
+                file_name: "src/main.rs".to_string(),
+                start_line: 0,
+                end_line: 1,
+                review_state: State::Reviewed,
+                total_lines: 4,
+                reviewer: Some("alice".to_string()),
+            },
+            &mut db,
+            &git,
+        )
+        .unwrap();
+        add_comment(
+            "src/main.rs".to_string(),
+            2,
+            "Is the <3 bound right?".to_string(),
+            "bob".to_string(),
+            &mut db,
+        )
+        .unwrap();
+        add_comment(
+            "src/lib.go".to_string(),
+            0,
+            "Out of scope".to_string(),
+            "bob".to_string(),
+            &mut db,
+        )
+        .unwrap();
+        create_finding(
+            FindingRequest {
+                title: "Addition overflows".to_string(),
+                severity: Severity::High,
+                status: FindingStatus::Confirmed,
+                category: Some("CWE-190".to_string()),
+                description: "b + 1 overflows".to_string(),
+                recommendation: "Use checked_add".to_string(),
+                locations: vec![Location {
+                    file_name: "src/main.rs".to_string(),
+                    lines: 1..=1,
+                }],
+                author: "alice".to_string(),
+            },
+            &mut db,
+        )
+        .unwrap();
+
+        let report = Report::generate(&config, &db, &git).unwrap();
+        let markdown = report.render(ReportFormat::Markdown);
+        assert!(markdown.contains("- 1 files in scope\n"));
+        assert!(markdown.contains("- Findings: 1 high\n"));
+        assert!(markdown.contains("| src/main.rs | - | - | 50% | 0% | 0% | alice 50% | - |"));
+        assert!(markdown.contains("### High: Addition overflows\n"));
+        assert!(markdown.contains("```\n    2 |     let a = b + 1;\n```"));
+        assert!(markdown.contains("- bob: Is the <3 bound right?\n"));
+        assert!(markdown.contains("    1 | fn main() {\n"));
+        assert!(!markdown.contains("Out of scope"));
+
+        let html = report.render(ReportFormat::Html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<li>bob: Is the &lt;3 bound right?</li>"));
+        assert!(html.contains("<td>src/main.rs</td>"));
+
+        assert_eq!("html".parse::<ReportFormat>().unwrap(), ReportFormat::Html);
+        assert!("pdf".parse::<ReportFormat>().is_err());
+    }
+}
@@ -0,0 +1,758 @@
+use crate::{
+    add_comment,
+    config::Config,
+    file_key,
+    finding::{Finding, FindingStatus, Severity},
+    storage::Storage,
+    update_metadata, Metadata, Priority, ThreadState, UpdateMetadataRequest,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub const SARIF_VERSION: &str = "2.1.0";
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Locations are relative to the root of the repository, which the uri base id refers to.
+pub const SOURCE_ROOT: &str = "SRCROOT";
+
+/// Rule of the results that are exported from comment threads.
+pub const COMMENT_RULE_ID: &str = "auditor/comment";
+
+/// Rule of the findings that have no category.
+pub const FINDING_RULE_ID: &str = "auditor/finding";
+
+// The subset of SARIF 2.1.0 that is exported and imported, unknown properties are ignored.
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifLog {
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    pub version: String,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    pub tool: Tool,
+    #[serde(default)]
+    pub results: Vec<SarifResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub version_control_provenance: Vec<VersionControlDetails>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub original_uri_base_ids: HashMap<String, ArtifactLocation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Tool {
+    pub driver: ToolComponent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolComponent {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingDescriptor {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_description: Option<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_configuration: Option<ReportingConfiguration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReportingConfiguration {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub message: Message,
+    #[serde(default)]
+    pub locations: Vec<SarifLocation>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial_fingerprints: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suppressions: Vec<Suppression>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Message {
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifLocation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_location: Option<PhysicalLocation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_location: Option<ArtifactLocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactLocation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri_base_id: Option<String>,
+}
+
+/// Lines and columns start at 1.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Suppression {
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub justification: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionControlDetails {
+    pub repository_uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapped_to: Option<ArtifactLocation>,
+}
+
+impl SarifLocation {
+    fn new(file_name: &str, start_line: usize, end_line: usize) -> Self {
+        Self {
+            physical_location: Some(PhysicalLocation {
+                artifact_location: Some(ArtifactLocation {
+                    uri: Some(file_name.trim_start_matches('/').to_string()),
+                    uri_base_id: Some(SOURCE_ROOT.to_string()),
+                }),
+                region: Some(Region {
+                    start_line: Some(start_line + 1),
+                    end_line: Some(end_line + 1),
+                }),
+            }),
+        }
+    }
+}
+
+/// SARIF only knows three levels, critical and high findings are errors.
+fn level_of(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low | Severity::Informational => "note",
+    }
+}
+
+/// Score that code scanning tools such as GitHub's use to rank security results.
+fn security_severity_of(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "9.5",
+        Severity::High => "8.0",
+        Severity::Medium => "5.5",
+        Severity::Low => "2.0",
+        Severity::Informational => "0.0",
+    }
+}
+
+fn rule_id_of(finding: &Finding) -> String {
+    finding
+        .category
+        .clone()
+        .filter(|category| !category.is_empty())
+        .unwrap_or(FINDING_RULE_ID.to_string())
+}
+
+fn finding_result(finding: &Finding) -> SarifResult {
+    let mut text = finding.title.clone();
+    if !finding.description.is_empty() {
+        text = format!("{text}\n\n{}", finding.description);
+    }
+    if !finding.recommendation.is_empty() {
+        text = format!("{text}\n\nRecommendation: {}", finding.recommendation);
+    }
+    let (kind, suppressions) = match finding.status {
+        FindingStatus::Open | FindingStatus::Confirmed => ("fail", vec![]),
+        FindingStatus::Fixed => ("pass", vec![]),
+        FindingStatus::FalsePositive => (
+            "fail",
+            vec![Suppression {
+                kind: "external".to_string(),
+                justification: Some("false positive".to_string()),
+            }],
+        ),
+    };
+    SarifResult {
+        rule_id: Some(rule_id_of(finding)),
+        level: Some(level_of(finding.severity).to_string()),
+        kind: Some(kind.to_string()),
+        message: Message { text },
+        locations: finding
+            .locations
+            .iter()
+            .map(|location| {
+                SarifLocation::new(
+                    &location.file_name,
+                    *location.lines.start(),
+                    *location.lines.end(),
+                )
+            })
+            .collect(),
+        partial_fingerprints: BTreeMap::from([("auditorId/v1".to_string(), finding.id.clone())]),
+        suppressions,
+        properties: Some(json!({
+            "severity": finding.severity,
+            "status": finding.status,
+            "security-severity": security_severity_of(finding.severity),
+            "author": finding.author,
+        })),
+    }
+}
+
+/// Converts the comment threads and findings of the files in scope into a single SARIF run.
+pub fn export(config: &Config, db: &dyn Storage) -> Result<SarifLog> {
+    let mut infos = db.latest_infos()?;
+    infos.retain(|info| config.in_scope(&info.file_name));
+    infos.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    let mut results = vec![];
+    let mut commits = BTreeSet::new();
+    for info in &infos {
+        if let Some(commit) = db.latest_reviewed_commit(&info.file_name)? {
+            commits.insert(commit);
+        }
+        let mut line_numbers: Vec<&usize> = info.comments.keys().collect();
+        line_numbers.sort();
+        for line_number in line_numbers {
+            for thread in &info.comments[line_number] {
+                let mut text = format!("{}: {}", thread.author, thread.body);
+                for reply in &thread.replies {
+                    text = format!("{text}\n{}: {}", reply.author, reply.body);
+                }
+                results.push(SarifResult {
+                    rule_id: Some(COMMENT_RULE_ID.to_string()),
+                    level: Some("note".to_string()),
+                    // Open threads still need a look, closed ones are only informational
+                    kind: Some(
+                        match thread.state {
+                            ThreadState::Open => "review",
+                            ThreadState::Resolved | ThreadState::WontFix => "informational",
+                        }
+                        .to_string(),
+                    ),
+                    message: Message { text },
+                    locations: vec![SarifLocation::new(
+                        &info.file_name,
+                        *line_number,
+                        *line_number,
+                    )],
+                    partial_fingerprints: BTreeMap::from([(
+                        "auditorId/v1".to_string(),
+                        thread.id.clone(),
+                    )]),
+                    suppressions: vec![],
+                    properties: Some(json!({
+                        "author": thread.author,
+                        "state": thread.state,
+                    })),
+                });
+            }
+        }
+    }
+
+    let mut findings = db.get_findings()?;
+    findings.sort_by_key(|finding| finding.severity);
+    let mut rules: BTreeMap<String, ReportingDescriptor> = BTreeMap::new();
+    if !results.is_empty() {
+        rules.insert(
+            COMMENT_RULE_ID.to_string(),
+            ReportingDescriptor {
+                id: COMMENT_RULE_ID.to_string(),
+                short_description: Some(Message {
+                    text: "Comment left during the audit".to_string(),
+                }),
+                default_configuration: Some(ReportingConfiguration {
+                    level: Some("note".to_string()),
+                }),
+                properties: None,
+            },
+        );
+    }
+    for finding in &findings {
+        let rule_id = rule_id_of(finding);
+        // Findings are sorted by severity, so each rule gets the level of its most severe finding
+        rules
+            .entry(rule_id.clone())
+            .or_insert_with(|| ReportingDescriptor {
+                id: rule_id.clone(),
+                short_description: Some(Message {
+                    text: finding.title.clone(),
+                }),
+                default_configuration: Some(ReportingConfiguration {
+                    level: Some(level_of(finding.severity).to_string()),
+                }),
+                properties: Some(json!({
+                    "tags": ["security"],
+                    "security-severity": security_severity_of(finding.severity),
+                })),
+            });
+        results.push(finding_result(finding));
+    }
+
+    let repository_uri = format!("file://{}", config.repository_path);
+    Ok(SarifLog {
+        schema: Some(SARIF_SCHEMA.to_string()),
+        version: SARIF_VERSION.to_string(),
+        runs: vec![Run {
+            tool: Tool {
+                driver: ToolComponent {
+                    name: "auditor".to_string(),
+                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                    rules: rules.into_values().collect(),
+                },
+            },
+            results,
+            version_control_provenance: commits
+                .into_iter()
+                .map(|commit| VersionControlDetails {
+                    repository_uri: repository_uri.clone(),
+                    revision_id: Some(commit),
+                    mapped_to: Some(ArtifactLocation {
+                        uri: None,
+                        uri_base_id: Some(SOURCE_ROOT.to_string()),
+                    }),
+                })
+                .collect(),
+            original_uri_base_ids: HashMap::from([(
+                SOURCE_ROOT.to_string(),
+                ArtifactLocation {
+                    uri: Some(format!("{}/", repository_uri.trim_end_matches('/'))),
+                    uri_base_id: None,
+                },
+            )]),
+        }],
+    })
+}
+
+/// What an import changed. Results that were imported before are counted as duplicates.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub duplicates: usize,
+    // Suppressed results and results without a location in the scope
+    pub skipped: usize,
+    // Files whose priority was raised to high
+    pub raised: Vec<String>,
+}
+
+/// Turns the uri of a location into a file name relative to the repository.
+fn file_name_of(location: &SarifLocation, run: &Run, repository_path: &str) -> Option<String> {
+    let artifact = location
+        .physical_location
+        .as_ref()?
+        .artifact_location
+        .as_ref()?;
+    let uri = artifact.uri.as_ref()?;
+    // Relative uris are resolved against their base, unknown bases are the repository itself
+    let uri = match artifact
+        .uri_base_id
+        .as_ref()
+        .and_then(|base_id| run.original_uri_base_ids.get(base_id))
+        .and_then(|base| base.uri.as_ref())
+    {
+        Some(base) if !uri.contains("://") => {
+            format!(
+                "{}/{}",
+                base.trim_end_matches('/'),
+                uri.trim_start_matches('/')
+            )
+        }
+        _ => uri.clone(),
+    };
+    let path = uri.strip_prefix("file://").unwrap_or(&uri);
+    let path = if path.starts_with('/') {
+        let relative = path.strip_prefix(repository_path.trim_end_matches('/'))?;
+        if !relative.is_empty() && !relative.starts_with('/') {
+            return None;
+        }
+        relative
+    } else {
+        path
+    };
+    let file_name = file_key(path, "");
+    (!file_name.is_empty()).then_some(file_name)
+}
+
+/// A result is high severity if its security score is at least 7, as GitHub ranks them, or else
+/// if its level is error. Both are taken from the rule when the result doesn't set them.
+fn is_high_severity(result: &SarifResult, rules: &[ReportingDescriptor]) -> bool {
+    let rule = result
+        .rule_id
+        .as_ref()
+        .and_then(|rule_id| rules.iter().find(|rule| &rule.id == rule_id));
+    let security_severity = [
+        result.properties.as_ref(),
+        rule.and_then(|rule| rule.properties.as_ref()),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|properties| {
+        properties
+            .get("security-severity")?
+            .as_str()?
+            .parse::<f64>()
+            .ok()
+    });
+    if let Some(score) = security_severity {
+        return score >= 7.0;
+    }
+    let level = result
+        .level
+        .clone()
+        .or_else(|| rule.and_then(|rule| rule.default_configuration.as_ref()?.level.clone()));
+    level.as_deref() == Some("error")
+}
+
+/// Attaches every result of the log as a comment at the first line of its first location. The
+/// comments are written by `author`, the user importing the log, and name the tool that reported
+/// the result in their body. Only without an author, when the log is imported locally, the tool is
+/// the author. A result that already has a comment with the same body at that line, or had it
+/// before the comment was edited, is not added again.
+///
+/// With `raise_priority`, files with high severity results get a high priority unless they are
+/// already high or ignored.
+pub fn import(
+    log: &SarifLog,
+    config: &Config,
+    raise_priority: bool,
+    author: Option<String>,
+    db: &mut dyn Storage,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    for run in &log.runs {
+        let tool = &run.tool.driver.name;
+        let author = author.clone().unwrap_or_else(|| tool.clone());
+        let mut high_severity_files = BTreeSet::new();
+        for result in &run.results {
+            let location = result.locations.first();
+            let file_name = location
+                .and_then(|location| file_name_of(location, run, &config.repository_path))
+                .filter(|file_name| config.in_scope(file_name));
+            let file_name = match file_name {
+                Some(file_name)
+                    if result.suppressions.is_empty() && result.kind.as_deref() != Some("pass") =>
+                {
+                    file_name
+                }
+                _ => {
+                    summary.skipped += 1;
+                    continue;
+                }
+            };
+            let line_number = location
+                .and_then(|location| location.physical_location.as_ref()?.region.as_ref())
+                .and_then(|region| region.start_line)
+                .unwrap_or(1)
+                .saturating_sub(1);
+            let body = match &result.rule_id {
+                Some(rule_id) => format!("[{tool}] {rule_id}: {}", result.message.text),
+                None => format!("[{tool}] {}", result.message.text),
+            };
+            if is_high_severity(result, &run.tool.driver.rules) {
+                high_severity_files.insert(file_name.clone());
+            }
+
+            let is_duplicate = db
+                .get_file_comments(&file_name)?
+                .and_then(|comments| comments.0.get(&line_number).cloned())
+                .unwrap_or_default()
+                .iter()
+                .any(|comment| {
+                    comment.body == body || comment.edits.iter().any(|edit| edit.body == body)
+                });
+            if is_duplicate {
+                summary.duplicates += 1;
+                continue;
+            }
+            add_comment(file_name, line_number, body, author.clone(), db)?;
+            summary.added += 1;
+        }
+
+        if !raise_priority {
+            continue;
+        }
+        for file_name in high_severity_files {
+            let metadata = match db.get_metadata(&file_name)? {
+                Some(Metadata {
+                    priority: Priority::High | Priority::Ignore,
+                    ..
+                }) => continue,
+                Some(metadata) => Metadata {
+                    priority: Priority::High,
+                    ..metadata
+                },
+                None => Metadata {
+                    priority: Priority::High,
+                    reviewer: "".to_string(),
+                    note: "".to_string(),
+                },
+            };
+            update_metadata(
+                UpdateMetadataRequest {
+                    file_name: file_name.clone(),
+                    metadata,
+                    author: Some(author.clone()),
+                },
+                db,
+            )?;
+            summary.raised.push(file_name);
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        db::DB,
+        finding::{create_finding, FindingRequest, Location},
+        StoredReviewForCommit, StoredReviewForFile,
+    };
+    use uuid::Uuid;
+
+    #[test]
+    fn test_export() {
+        let path = std::env::temp_dir().join(format!("auditor-sarif-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut db = DB::new(path.clone()).unwrap();
+        let config = Config {
+            repository_path: "/repo".to_string(),
+            included_prefixes: vec!["src".to_string()],
+            ..Config::default()
+        };
+
+        let mut state = StoredReviewForCommit::new(vec![]);
+        let mut review = StoredReviewForFile::default();
+        review.total_lines = 10;
+        state.files.insert("src/a.rs".to_string(), review);
+        db.store_review_status("commit1", &state).unwrap();
+        let thread = db
+            .add_new_comment(
+                "src/a.rs".to_string(),
+                4,
+                "unchecked".to_string(),
+                "alice".to_string(),
+            )
+            .unwrap();
+        db.add_new_comment(
+            "vendor/b.rs".to_string(),
+            0,
+            "out of scope".to_string(),
+            "alice".to_string(),
+        )
+        .unwrap();
+        db.add_reply(
+            "src/a.rs",
+            4,
+            &thread,
+            "agreed".to_string(),
+            "bob".to_string(),
+        )
+        .unwrap();
+        let request = |severity, category: Option<&str>| FindingRequest {
+            title: "Overflow".to_string(),
+            severity,
+            status: FindingStatus::FalsePositive,
+            category: category.map(|category| category.to_string()),
+            description: "".to_string(),
+            recommendation: "".to_string(),
+            locations: vec![Location {
+                file_name: "src/a.rs".to_string(),
+                lines: 2..=3,
+            }],
+            author: "alice".to_string(),
+        };
+        create_finding(request(Severity::Medium, Some("CWE-190")), &mut db).unwrap();
+        create_finding(request(Severity::Critical, None), &mut db).unwrap();
+
+        let log = export(&config, &db).unwrap();
+        let run = &log.runs[0];
+        assert_eq!(log.version, "2.1.0");
+        assert_eq!(
+            run.version_control_provenance[0].revision_id,
+            Some("commit1".to_string())
+        );
+        assert_eq!(run.results.len(), 3);
+        assert_eq!(run.results[0].rule_id, Some(COMMENT_RULE_ID.to_string()));
+        assert_eq!(run.results[0].message.text, "alice: unchecked\nbob: agreed");
+        assert_eq!(
+            run.results[0].locations[0],
+            SarifLocation::new("src/a.rs", 4, 4)
+        );
+        assert_eq!(run.results[1].level, Some("error".to_string()));
+        assert_eq!(run.results[1].rule_id, Some(FINDING_RULE_ID.to_string()));
+        assert_eq!(run.results[2].level, Some("warning".to_string()));
+        assert_eq!(run.results[2].suppressions.len(), 1);
+        let rule_ids: Vec<&str> = run
+            .tool
+            .driver
+            .rules
+            .iter()
+            .map(|rule| rule.id.as_str())
+            .collect();
+        assert_eq!(rule_ids, vec!["CWE-190", COMMENT_RULE_ID, FINDING_RULE_ID]);
+
+        // Serialized with the SARIF property names and read back unchanged
+        let serialized = serde_json::to_string(&log).unwrap();
+        assert!(serialized.contains("\"$schema\""));
+        assert!(serialized.contains("\"versionControlProvenance\""));
+        assert!(serialized.contains("\"startLine\":5"));
+        assert_eq!(serde_json::from_str::<SarifLog>(&serialized).unwrap(), log);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_import() {
+        let path = std::env::temp_dir().join(format!("auditor-sarif-import-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let config = Config {
+            repository_path: "/repo/".to_string(),
+            included_prefixes: vec!["src".to_string()],
+            ..Config::default()
+        };
+        let log: SarifLog = serde_json::from_value(json!({
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {"driver": {"name": "semgrep", "rules": [
+                    {"id": "overflow", "defaultConfiguration": {"level": "error"}},
+                    {"id": "style", "properties": {"security-severity": "2.0"}},
+                ]}},
+                "originalUriBaseIds": {"ROOT": {"uri": "file:///repo/"}},
+                "results": [
+                    {
+                        "ruleId": "overflow",
+                        "message": {"text": "Length can overflow"},
+                        "locations": [{"physicalLocation": {
+                            "artifactLocation": {"uri": "file:///repo/src/a.rs"},
+                            "region": {"startLine": 3, "startColumn": 5},
+                        }}],
+                    },
+                    {
+                        "ruleId": "style",
+                        "level": "error",
+                        "message": {"text": "Long line"},
+                        "locations": [{"physicalLocation": {
+                            "artifactLocation": {"uri": "src/b.rs", "uriBaseId": "ROOT"},
+                            "region": {"startLine": 1},
+                        }}],
+                    },
+                    {
+                        "ruleId": "overflow",
+                        "message": {"text": "Suppressed"},
+                        "suppressions": [{"kind": "inSource"}],
+                        "locations": [{"physicalLocation": {
+                            "artifactLocation": {"uri": "src/b.rs"},
+                        }}],
+                    },
+                    {
+                        "message": {"text": "Out of scope"},
+                        "locations": [{"physicalLocation": {
+                            "artifactLocation": {"uri": "file:///repository/src/c.rs"},
+                        }}],
+                    },
+                ],
+            }],
+        }))
+        .unwrap();
+
+        let mut db = DB::new(path.clone()).unwrap();
+        let summary = import(&log, &config, true, Some("alice".to_string()), &mut db).unwrap();
+        assert_eq!(summary.added, 2);
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(summary.raised, vec!["src/a.rs".to_string()]);
+        db.save().unwrap();
+
+        let comments = db.get_file_comments("src/a.rs").unwrap().unwrap();
+        let comment = &comments.0[&2][0];
+        assert_eq!(comment.author, "alice");
+        assert_eq!(comment.body, "[semgrep] overflow: Length can overflow");
+        assert_eq!(
+            db.get_file_comments("src/b.rs").unwrap().unwrap().0[&0].len(),
+            1
+        );
+        assert!(matches!(
+            db.get_metadata("src/a.rs").unwrap().unwrap().priority,
+            Priority::High
+        ));
+        assert!(db.get_metadata("src/b.rs").unwrap().is_none());
+
+        // Importing again, also by someone else, only finds duplicates, also of comments that
+        // were edited since
+        let mut db = DB::new(path.clone()).unwrap();
+        db.update_comment(
+            "src/a.rs".to_string(),
+            comment.id.clone(),
+            2,
+            "Checked, the length is bounded".to_string(),
+            "alice".to_string(),
+            true,
+        )
+        .unwrap();
+        let summary = import(&log, &config, true, None, &mut db).unwrap();
+        assert_eq!(summary.added, 0);
+        assert_eq!(summary.duplicates, 2);
+        assert!(summary.raised.is_empty());
+        assert_eq!(
+            db.get_file_comments("src/a.rs").unwrap().unwrap().0[&2].len(),
+            1
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
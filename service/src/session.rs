@@ -0,0 +1,317 @@
+use crate::{
+    config::Config,
+    git::Git,
+    now,
+    policy::SignOffPolicy,
+    report::{count, percent},
+    storage::Storage,
+    LatestFileInfo, StoredReviewForFile,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ops::RangeInclusive};
+
+/// A diff audit of the changes between two commits, e.g. of a pull request. Only the lines that
+/// were added or changed are in scope and coverage is relative to them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub base: String,
+    pub head: String,
+    pub author: Option<String>,
+    // Seconds since the unix epoch
+    pub created_at: u64,
+    // 0-based lines of each file at `head` that changed since `base`
+    pub files: HashMap<String, Vec<RangeInclusive<usize>>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionRequest {
+    // Commit ids, branches or any other revisions
+    pub base: String,
+    pub head: String,
+    // Replaced by the authenticated user when authentication is enabled
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+impl Session {
+    /// Diffs the commits and keeps the changed lines of the files in scope of the config.
+    pub fn new(request: SessionRequest, config: &Config, git: &Git) -> Result<Self> {
+        let base = git.resolve_commit(&request.base)?;
+        let head = git.resolve_commit(&request.head)?;
+        let mut files = HashMap::default();
+        if let Some(diff) = git.diff_commits(&base, &head, &vec![])? {
+            for (file_name, file_diff) in diff.files {
+                let lines = file_diff.line_map().added_ranges();
+                if config.in_scope(&file_name) && !lines.is_empty() {
+                    files.insert(file_name, lines);
+                }
+            }
+        }
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            base,
+            head,
+            author: request.author,
+            created_at: now(),
+            files,
+        })
+    }
+
+    /// The changed lines at HEAD. Changes made after `head` move them along like they move the
+    /// reviews, and the lines they add to the files of the session are in scope as well. Other
+    /// files are not, even if they changed since.
+    pub fn scope_at_head(
+        &self,
+        config: &Config,
+        git: &Git,
+    ) -> Result<HashMap<String, Vec<RangeInclusive<usize>>>> {
+        let current_commit = git.current_commit()?;
+        let diff = match current_commit == self.head {
+            true => None,
+            false => git.diff_commits(&self.head, &current_commit, &vec![])?,
+        };
+        let Some(diff) = diff else {
+            return Ok(self.files.clone());
+        };
+        let mut scope = self.files.clone();
+        for (file_name, file_diff) in &diff.files {
+            if let Some(old_file_name) = file_diff.moved_from(file_name) {
+                if !file_diff.is_copy {
+                    scope.remove(old_file_name);
+                }
+            }
+        }
+        for (file_name, file_diff) in diff.files {
+            let Some(ranges) = file_diff
+                .old_file_name
+                .as_ref()
+                .and_then(|old_file_name| self.files.get(old_file_name))
+            else {
+                continue;
+            };
+            if !config.in_scope(&file_name) {
+                scope.remove(&file_name);
+                continue;
+            }
+            let line_map = file_diff.line_map();
+            let mut lines: Vec<RangeInclusive<usize>> = ranges
+                .iter()
+                .flat_map(|range| line_map.map_range(range))
+                .collect();
+            lines.extend(line_map.added_ranges());
+            let lines = lines.into_iter().fold(vec![], |acc, range| {
+                StoredReviewForFile::add_range_to_list(range, acc)
+            });
+            match lines.is_empty() {
+                true => scope.remove(&file_name),
+                false => scope.insert(file_name, lines),
+            };
+        }
+        Ok(scope)
+    }
+
+    /// Lists the files of the session with their reviews limited to the changed lines, or only
+    /// `file_name` if given. Changed files without reviews are listed as well.
+    pub fn infos(
+        &self,
+        config: &Config,
+        db: &dyn Storage,
+        git: &Git,
+        file_name: Option<&str>,
+    ) -> Result<Vec<LatestFileInfo>> {
+        let mut scope = self.scope_at_head(config, git)?;
+        scope.retain(|scoped_file_name, _| {
+            file_name.is_none_or(|file_name| file_name == scoped_file_name)
+        });
+        let policy = SignOffPolicy::from_config(config);
+        let mut infos = vec![];
+        for mut info in db.latest_infos()? {
+            let Some(lines) = scope.remove(&info.file_name) else {
+                continue;
+            };
+            limit_to(&mut info, lines);
+            policy.apply(&mut info);
+            infos.push(info);
+        }
+        let current_commit = git.current_commit()?;
+        for (file_name, lines) in scope {
+            let total_lines = git
+                .file_content_at(&current_commit, &file_name)?
+                .map_or(0, |content| content.split('\n').count());
+            let mut info = LatestFileInfo {
+                file_name,
+                line_reviews: StoredReviewForFile {
+                    total_lines,
+                    ..StoredReviewForFile::default()
+                },
+                comments: HashMap::default(),
+                metadata: None,
+                aliases: vec![],
+                reviewer_coverage: HashMap::default(),
+                policy_status: None,
+                open_threads: 0,
+                resolved_threads: 0,
+                scope: None,
+            };
+            limit_to(&mut info, lines);
+            infos.push(info);
+        }
+        infos.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        Ok(infos)
+    }
+}
+
+/// Keeps the reviews of the lines in scope and makes the coverage of each reviewer relative to
+/// them.
+fn limit_to(info: &mut LatestFileInfo, scope: Vec<RangeInclusive<usize>>) {
+    let line_reviews = &mut info.line_reviews;
+    line_reviews.reviewed = intersect(&line_reviews.reviewed, &scope);
+    line_reviews.modified = intersect(&line_reviews.modified, &scope);
+    line_reviews.ignored = intersect(&line_reviews.ignored, &scope);
+    for ranges in line_reviews.reviewers.values_mut() {
+        *ranges = intersect(ranges, &scope);
+    }
+    line_reviews
+        .reviewers
+        .retain(|_, ranges| !ranges.is_empty());
+    info.reviewer_coverage = line_reviews
+        .reviewers
+        .iter()
+        .map(|(reviewer, ranges)| (reviewer.clone(), percent(count(ranges), count(&scope))))
+        .collect();
+    info.scope = Some(scope);
+}
+
+/// The parts of the sorted ranges that overlap with the sorted scope.
+fn intersect(
+    ranges: &[RangeInclusive<usize>],
+    scope: &[RangeInclusive<usize>],
+) -> Vec<RangeInclusive<usize>> {
+    let mut overlaps = vec![];
+    for range in ranges {
+        for scoped in scope {
+            let start = *range.start().max(scoped.start());
+            let end = *range.end().min(scoped.end());
+            if start <= end {
+                overlaps.push(start..=end);
+            }
+        }
+    }
+    overlaps
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        db::DB, git, policy::PolicyStatus, update_review_state, Metadata, Priority, State,
+        UpdateReviewState,
+    };
+
+    #[test]
+    fn test_session() {
+        let repo_path = git::tests::init_repo();
+        git::tests::commit_file(&repo_path, "src/lib.rs", "a\nb\nc\nd\n");
+        let git = git::Git::new(&repo_path).unwrap();
+        let base = git.current_commit().unwrap();
+        git::tests::commit_file(&repo_path, "src/lib.rs", "a\nx\ny\nc\nd\n");
+        git::tests::commit_file(&repo_path, "src/new.rs", "n\n");
+        git::tests::commit_file(&repo_path, "notes.txt", "out of scope\n");
+        let config = Config {
+            repository_path: repo_path.clone(),
+            allowed_file_extensions: vec![".rs".to_string()],
+            included_prefixes: vec!["".to_string()],
+            high_priority_reviewers: 1,
+            ..Config::default()
+        };
+        let request = SessionRequest {
+            base: base.clone(),
+            head: "HEAD".to_string(),
+            author: None,
+        };
+        let session = Session::new(request, &config, &git).unwrap();
+        assert_eq!(session.base, base);
+        assert_eq!(session.head, git.current_commit().unwrap());
+        assert_eq!(
+            session.files,
+            HashMap::from([
+                ("src/lib.rs".to_string(), vec![1..=2]),
+                ("src/new.rs".to_string(), vec![0..=0]),
+            ])
+        );
+
+        let db_path = repo_path.clone() + "-db";
+        std::fs::create_dir(&db_path).unwrap();
+        let mut db = DB::new(db_path).unwrap();
+        update_review_state(
+            UpdateReviewState::new(
+                "src/lib.rs".to_string(),
+                0..=1,
+                State::Reviewed,
+                6,
+                Some("alice".to_string()),
+            ),
+            &mut db,
+            &git,
+        )
+        .unwrap();
+        let infos = session.infos(&config, &db, &git, None).unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].line_reviews.reviewed, vec![1..=1]);
+        assert_eq!(infos[0].scope, Some(vec![1..=2]));
+        assert_eq!(infos[0].reviewer_coverage["alice"], 50);
+        assert_eq!(infos[1].file_name, "src/new.rs");
+        assert_eq!(infos[1].line_reviews.total_lines, 2);
+
+        // The sign-off policy only asks for the changed lines
+        db.set_metadata(
+            "src/lib.rs",
+            Metadata {
+                priority: Priority::High,
+                reviewer: "alice".to_string(),
+                note: String::new(),
+            },
+        )
+        .unwrap();
+        let infos = session.infos(&config, &db, &git, None).unwrap();
+        assert_eq!(
+            infos[0].policy_status,
+            Some(PolicyStatus::PartiallySatisfied)
+        );
+        update_review_state(
+            UpdateReviewState::new(
+                "src/lib.rs".to_string(),
+                2..=2,
+                State::Reviewed,
+                6,
+                Some("alice".to_string()),
+            ),
+            &mut db,
+            &git,
+        )
+        .unwrap();
+        let infos = session.infos(&config, &db, &git, None).unwrap();
+        assert_eq!(infos[0].policy_status, Some(PolicyStatus::Satisfied));
+        assert_eq!(infos[0].line_reviews.reviewed, vec![1..=2]);
+
+        // Lines added after the head of the session are in scope too
+        git::tests::commit_file(&repo_path, "src/lib.rs", "z\na\nx\ny\nc\nd\n");
+        let scope = session.scope_at_head(&config, &git).unwrap();
+        assert_eq!(scope["src/lib.rs"], vec![0..=0, 2..=3]);
+
+        // Files that were not part of the session stay out of it when they change later, renamed
+        // files of the session stay in it
+        git::tests::commit_file(&repo_path, "src/other.rs", "o\n");
+        git::tests::commit_file(&repo_path, "notes.txt", "still out of scope\n");
+        git::tests::commit_move(&repo_path, "src/new.rs", "src/renamed.rs");
+        let scope = session.scope_at_head(&config, &git).unwrap();
+        let mut files: Vec<&String> = scope.keys().collect();
+        files.sort();
+        assert_eq!(files, vec!["src/lib.rs", "src/renamed.rs"]);
+        let infos = session.infos(&config, &db, &git, None).unwrap();
+        assert_eq!(infos.len(), 2);
+    }
+}
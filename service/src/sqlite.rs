@@ -0,0 +1,1521 @@
+use crate::{
+    auth::{ApiToken, User},
+    db::DB,
+    finding::{Finding, FindingStatus, Location, Severity},
+    history::Event,
+    line_map::LineMap,
+    session::Session,
+    storage::Storage,
+    AuditorError, Comment, CommentEdit, FileComments, LatestFileInfo, Metadata, Priority, Reply,
+    StoredReviewForCommit, StoredReviewForFile, ThreadState,
+};
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{collections::HashMap, ops::RangeInclusive, time::Duration};
+use uuid::Uuid;
+
+const DB_FILE_NAME: &str = "auditor.sqlite3";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    file_name TEXT NOT NULL UNIQUE,
+    latest_reviewed_commit TEXT NOT NULL DEFAULT '',
+    total_lines INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS commits (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    commit_hash TEXT NOT NULL,
+    total_lines INTEGER NOT NULL,
+    UNIQUE(file_id, commit_hash)
+);
+CREATE TABLE IF NOT EXISTS ranges (
+    commit_id INTEGER NOT NULL REFERENCES commits(id) ON DELETE CASCADE,
+    state TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS ranges_commit_id ON ranges(commit_id);
+CREATE TABLE IF NOT EXISTS reviewer_ranges (
+    commit_id INTEGER NOT NULL REFERENCES commits(id) ON DELETE CASCADE,
+    reviewer TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS reviewer_ranges_commit_id ON reviewer_ranges(commit_id);
+CREATE TABLE IF NOT EXISTS comments (
+    id TEXT PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    line_number INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    author TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS comments_file_id ON comments(file_id);
+CREATE TABLE IF NOT EXISTS threads (
+    comment_id TEXT PRIMARY KEY REFERENCES comments(id) ON DELETE CASCADE,
+    state TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    resolved_by TEXT
+);
+CREATE TABLE IF NOT EXISTS replies (
+    id TEXT PRIMARY KEY,
+    comment_id TEXT NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS replies_comment_id ON replies(comment_id);
+CREATE TABLE IF NOT EXISTS comment_edits (
+    comment_id TEXT NOT NULL,
+    body TEXT NOT NULL,
+    editor TEXT NOT NULL,
+    edited_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS comment_edits_comment_id ON comment_edits(comment_id);
+CREATE TABLE IF NOT EXISTS metadata (
+    file_id INTEGER PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+    priority TEXT NOT NULL,
+    reviewer TEXT NOT NULL,
+    note TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS aliases (
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    alias TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    id TEXT PRIMARY KEY,
+    file_name TEXT NOT NULL,
+    event TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_file_name ON events(file_name);
+CREATE TABLE IF NOT EXISTS findings (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    status TEXT NOT NULL,
+    category TEXT,
+    description TEXT NOT NULL,
+    recommendation TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS finding_locations (
+    finding_id TEXT NOT NULL REFERENCES findings(id) ON DELETE CASCADE,
+    file_name TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS finding_locations_finding_id ON finding_locations(finding_id);
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    session TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS users (
+    name TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    role TEXT NOT NULL DEFAULT 'auditor'
+);
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    user_name TEXT NOT NULL REFERENCES users(name) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL
+);
+";
+
+// Selects the edits of the comments and replies of the file ?1
+const EDITS_OF_FILE: &str = "comment_id IN (SELECT id FROM comments WHERE file_id = ?1)
+    OR comment_id IN (SELECT replies.id FROM replies
+        JOIN comments ON comments.id = replies.comment_id WHERE comments.file_id = ?1)";
+
+const REVIEWED: &str = "reviewed";
+const MODIFIED: &str = "modified";
+const IGNORED: &str = "ignored";
+
+/// Stores everything in a single SQLite database. The changes since opening it are written in one
+/// transaction by `save` and `save_file`, and are rolled back if it is dropped before.
+pub struct SqliteDB {
+    conn: Connection,
+}
+
+impl SqliteDB {
+    pub fn new(db_dir: &str) -> Result<Self> {
+        Self::open(&format!("{db_dir}/{DB_FILE_NAME}"))
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        // Wait for writers of other processes instead of failing right away
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        // Databases created before roles existed lack the column
+        if !has_column(&conn, "users", "role")? {
+            conn.execute_batch(
+                "ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'auditor'",
+            )?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Starts the transaction that `save` commits, so that the writes of one operation are stored
+    /// together or not at all.
+    fn begin(&self) -> Result<()> {
+        if self.conn.is_autocommit() {
+            self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        }
+        Ok(())
+    }
+
+    /// Copies every file of the JSON database into this one. Returns the number of copied files.
+    pub fn migrate_from_json(&mut self, db: &DB) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        for db_content in db.file_dbs.values() {
+            let file_id = ensure_file(&tx, &db_content.file_name)?;
+            tx.execute(
+                "UPDATE files SET latest_reviewed_commit = ?1, total_lines = ?2 WHERE id = ?3",
+                params![
+                    db_content.latest_reviewed_commit,
+                    db_content.total_lines,
+                    file_id
+                ],
+            )?;
+            for (commit, review) in &db_content.commit_reviews {
+                store_review(&tx, file_id, commit, review)?;
+            }
+            store_comments(&tx, file_id, &db_content.comments)?;
+            if let Some(metadata) = &db_content.metadata {
+                store_metadata(&tx, file_id, metadata)?;
+            }
+            for alias in &db_content.aliases {
+                store_alias(&tx, file_id, alias)?;
+            }
+        }
+        for event in db.events()? {
+            store_event(&tx, &event)?;
+        }
+        for finding in db.get_findings()? {
+            store_finding(&tx, &finding)?;
+        }
+        for session in db.get_sessions()? {
+            store_session(&tx, &session)?;
+        }
+        for user in db.get_users()? {
+            store_user(&tx, &user)?;
+        }
+        for token in db.get_tokens()? {
+            store_token(&tx, &token)?;
+        }
+        tx.commit()?;
+        Ok(db.file_dbs.len())
+    }
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut statement = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = statement
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(columns.iter().any(|name| name == column))
+}
+
+fn user_from_row(row: &rusqlite::Row) -> rusqlite::Result<User> {
+    let role: String = row.get(2)?;
+    Ok(User {
+        name: row.get(0)?,
+        created_at: row.get(1)?,
+        role: role.parse().map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(err))
+        })?,
+    })
+}
+
+fn file_id(conn: &Connection, file_name: &str) -> Result<Option<i64>> {
+    Ok(conn
+        .query_row(
+            "SELECT id FROM files WHERE file_name = ?1",
+            params![file_name],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+fn ensure_file(conn: &Connection, file_name: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT OR IGNORE INTO files (file_name) VALUES (?1)",
+        params![file_name],
+    )?;
+    Ok(file_id(conn, file_name)?.expect("Just inserted"))
+}
+
+fn load_review(
+    conn: &Connection,
+    commit_id: i64,
+    total_lines: usize,
+) -> Result<StoredReviewForFile> {
+    let mut review = StoredReviewForFile::default();
+    review.total_lines = total_lines;
+    let mut statement = conn.prepare(
+        "SELECT state, start_line, end_line FROM ranges WHERE commit_id = ?1 ORDER BY start_line",
+    )?;
+    let rows = statement.query_map(params![commit_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, usize>(1)?,
+            row.get::<_, usize>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (state, start, end) = row?;
+        let range = RangeInclusive::new(start, end);
+        match state.as_str() {
+            REVIEWED => review.reviewed.push(range),
+            MODIFIED => review.modified.push(range),
+            IGNORED => review.ignored.push(range),
+            _ => (),
+        }
+    }
+
+    let mut statement = conn.prepare(
+        "SELECT reviewer, start_line, end_line FROM reviewer_ranges
+         WHERE commit_id = ?1 ORDER BY start_line",
+    )?;
+    let rows = statement.query_map(params![commit_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, usize>(1)?,
+            row.get::<_, usize>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (reviewer, start, end) = row?;
+        review
+            .reviewers
+            .entry(reviewer)
+            .or_default()
+            .push(RangeInclusive::new(start, end));
+    }
+    Ok(review)
+}
+
+fn store_review(
+    conn: &Connection,
+    file_id: i64,
+    commit: &str,
+    review: &StoredReviewForFile,
+) -> Result<()> {
+    let commit_id: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM commits WHERE file_id = ?1 AND commit_hash = ?2",
+            params![file_id, commit],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let commit_id = match commit_id {
+        Some(commit_id) => {
+            conn.execute(
+                "UPDATE commits SET total_lines = ?1 WHERE id = ?2",
+                params![review.total_lines, commit_id],
+            )?;
+            conn.execute(
+                "DELETE FROM ranges WHERE commit_id = ?1",
+                params![commit_id],
+            )?;
+            conn.execute(
+                "DELETE FROM reviewer_ranges WHERE commit_id = ?1",
+                params![commit_id],
+            )?;
+            commit_id
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO commits (file_id, commit_hash, total_lines) VALUES (?1, ?2, ?3)",
+                params![file_id, commit, review.total_lines],
+            )?;
+            conn.last_insert_rowid()
+        }
+    };
+    let mut statement = conn.prepare(
+        "INSERT INTO ranges (commit_id, state, start_line, end_line) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (state, ranges) in [
+        (REVIEWED, &review.reviewed),
+        (MODIFIED, &review.modified),
+        (IGNORED, &review.ignored),
+    ] {
+        for range in ranges {
+            statement.execute(params![commit_id, state, range.start(), range.end()])?;
+        }
+    }
+    let mut statement = conn.prepare(
+        "INSERT INTO reviewer_ranges (commit_id, reviewer, start_line, end_line)
+         VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (reviewer, ranges) in &review.reviewers {
+        for range in ranges {
+            statement.execute(params![commit_id, reviewer, range.start(), range.end()])?;
+        }
+    }
+    Ok(())
+}
+
+fn store_metadata(conn: &Connection, file_id: i64, metadata: &Metadata) -> Result<()> {
+    let priority = serde_json::to_value(&metadata.priority)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (file_id, priority, reviewer, note)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            file_id,
+            priority.as_str().unwrap_or_default(),
+            metadata.reviewer,
+            metadata.note
+        ],
+    )?;
+    Ok(())
+}
+
+fn store_comments(conn: &Connection, file_id: i64, comments: &FileComments) -> Result<()> {
+    let mut line_numbers: Vec<&usize> = comments.0.keys().collect();
+    line_numbers.sort();
+    for line_number in line_numbers {
+        for comment in &comments.0[line_number] {
+            conn.execute(
+                "INSERT OR REPLACE INTO comments (id, file_id, line_number, body, author)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    comment.id,
+                    file_id,
+                    line_number,
+                    comment.body,
+                    comment.author
+                ],
+            )?;
+            store_thread(conn, comment)?;
+        }
+    }
+    Ok(())
+}
+
+/// Stores the body, the state, the replies and the edits of the thread that `comment` starts.
+fn store_thread(conn: &Connection, comment: &Comment) -> Result<()> {
+    conn.execute(
+        "UPDATE comments SET body = ?1 WHERE id = ?2",
+        params![comment.body, comment.id],
+    )?;
+    let state = serde_json::to_value(comment.state)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO threads (comment_id, state, created_at, updated_at, resolved_by)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            comment.id,
+            state.as_str().unwrap_or_default(),
+            comment.created_at,
+            comment.updated_at,
+            comment.resolved_by
+        ],
+    )?;
+    delete_edits(conn, &comment.id)?;
+    store_edits(conn, &comment.id, &comment.edits)?;
+    conn.execute(
+        "DELETE FROM replies WHERE comment_id = ?1",
+        params![comment.id],
+    )?;
+    for reply in &comment.replies {
+        store_edits(conn, &reply.id, &reply.edits)?;
+        conn.execute(
+            "INSERT INTO replies (id, comment_id, body, author, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                reply.id,
+                comment.id,
+                reply.body,
+                reply.author,
+                reply.created_at
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn store_edits(conn: &Connection, comment_id: &str, edits: &[CommentEdit]) -> Result<()> {
+    for edit in edits {
+        conn.execute(
+            "INSERT INTO comment_edits (comment_id, body, editor, edited_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![comment_id, edit.body, edit.editor, edit.edited_at],
+        )?;
+    }
+    Ok(())
+}
+
+/// Deletes the edits of a comment and of its replies. Edits also belong to replies, so they
+/// are not removed along with the comment by a cascade.
+fn delete_edits(conn: &Connection, comment_id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM comment_edits
+         WHERE comment_id = ?1 OR comment_id IN (SELECT id FROM replies WHERE comment_id = ?1)",
+        params![comment_id],
+    )?;
+    Ok(())
+}
+
+fn store_user(conn: &Connection, user: &User) -> Result<()> {
+    conn.execute(
+        "INSERT INTO users (name, created_at, role) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name) DO UPDATE SET created_at = excluded.created_at, role = excluded.role",
+        params![user.name, user.created_at, user.role.as_str()],
+    )?;
+    Ok(())
+}
+
+fn store_token(conn: &Connection, token: &ApiToken) -> Result<()> {
+    conn.execute(
+        "INSERT INTO api_tokens (id, user_name, token_hash, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![token.id, token.user, token.token_hash, token.created_at],
+    )?;
+    Ok(())
+}
+
+fn store_finding(conn: &Connection, finding: &Finding) -> Result<()> {
+    let severity = serde_json::to_value(finding.severity)?;
+    let status = serde_json::to_value(finding.status)?;
+    // Replacing the row deletes its locations through the cascade
+    conn.execute(
+        "INSERT OR REPLACE INTO findings
+         (id, title, severity, status, category, description, recommendation, author,
+          created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            finding.id,
+            finding.title,
+            severity.as_str().unwrap_or_default(),
+            status.as_str().unwrap_or_default(),
+            finding.category,
+            finding.description,
+            finding.recommendation,
+            finding.author,
+            finding.created_at,
+            finding.updated_at
+        ],
+    )?;
+    for location in &finding.locations {
+        conn.execute(
+            "INSERT INTO finding_locations (finding_id, file_name, start_line, end_line)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                finding.id,
+                location.file_name,
+                location.lines.start(),
+                location.lines.end()
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn store_event(conn: &Connection, event: &Event) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO events (id, file_name, event) VALUES (?1, ?2, ?3)",
+        params![event.id, event.file_name, serde_json::to_string(event)?],
+    )?;
+    Ok(())
+}
+
+fn store_session(conn: &Connection, session: &Session) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sessions (id, session, created_at) VALUES (?1, ?2, ?3)",
+        params![
+            session.id,
+            serde_json::to_string(session)?,
+            session.created_at
+        ],
+    )?;
+    Ok(())
+}
+
+fn store_alias(conn: &Connection, file_id: i64, alias: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO aliases (file_id, alias) SELECT ?1, ?2
+         WHERE NOT EXISTS (SELECT 1 FROM aliases WHERE file_id = ?1 AND alias = ?2)",
+        params![file_id, alias],
+    )?;
+    Ok(())
+}
+
+impl SqliteDB {
+    fn existing_file_id(&self, file_name: &str) -> Result<i64> {
+        Ok(file_id(&self.conn, file_name)?
+            .ok_or(AuditorError::UnknownFileName(file_name.to_string()))?)
+    }
+
+    fn comments_of(&self, file_id: i64) -> Result<FileComments> {
+        let mut edits: HashMap<String, Vec<CommentEdit>> = HashMap::default();
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT comment_id, body, editor, edited_at FROM comment_edits
+             WHERE {EDITS_OF_FILE} ORDER BY rowid"
+        ))?;
+        let rows = statement.query_map(params![file_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                CommentEdit {
+                    body: row.get(1)?,
+                    editor: row.get(2)?,
+                    edited_at: row.get(3)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (comment_id, edit) = row?;
+            edits.entry(comment_id).or_default().push(edit);
+        }
+
+        let mut replies: HashMap<String, Vec<Reply>> = HashMap::default();
+        let mut statement = self.conn.prepare(
+            "SELECT replies.comment_id, replies.id, replies.body, replies.author, replies.created_at
+             FROM replies JOIN comments ON comments.id = replies.comment_id
+             WHERE comments.file_id = ?1 ORDER BY replies.rowid",
+        )?;
+        let rows = statement.query_map(params![file_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Reply {
+                    id: row.get(1)?,
+                    body: row.get(2)?,
+                    author: row.get(3)?,
+                    created_at: row.get(4)?,
+                    edits: vec![],
+                },
+            ))
+        })?;
+        for row in rows {
+            let (comment_id, mut reply) = row?;
+            reply.edits = edits.remove(&reply.id).unwrap_or_default();
+            replies.entry(comment_id).or_default().push(reply);
+        }
+
+        // Comments stored before threads existed have no thread row and are open
+        let mut statement = self.conn.prepare(
+            "SELECT comments.id, line_number, body, author, state, created_at, updated_at, resolved_by
+             FROM comments LEFT JOIN threads ON threads.comment_id = comments.id
+             WHERE file_id = ?1 ORDER BY comments.rowid",
+        )?;
+        let rows = statement.query_map(params![file_id], |row| {
+            Ok((
+                row.get::<_, usize>(1)?,
+                row.get::<_, Option<String>>(4)?,
+                Comment {
+                    id: row.get(0)?,
+                    body: row.get(2)?,
+                    author: row.get(3)?,
+                    replies: vec![],
+                    state: ThreadState::Open,
+                    created_at: row.get::<_, Option<u64>>(5)?.unwrap_or_default(),
+                    updated_at: row.get::<_, Option<u64>>(6)?.unwrap_or_default(),
+                    resolved_by: row.get(7)?,
+                    edits: vec![],
+                },
+            ))
+        })?;
+        let mut comments: HashMap<usize, Vec<Comment>> = HashMap::default();
+        for row in rows {
+            let (line_number, state, mut comment) = row?;
+            if let Some(state) = state {
+                comment.state = serde_json::from_value(serde_json::Value::String(state))?;
+            }
+            comment.replies = replies.remove(&comment.id).unwrap_or_default();
+            comment.edits = edits.remove(&comment.id).unwrap_or_default();
+            comments.entry(line_number).or_insert(vec![]).push(comment);
+        }
+        Ok(FileComments(comments))
+    }
+
+    /// Loads a thread, lets `update` change it and stores it again.
+    fn update_thread<T>(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        update: impl FnOnce(&mut Comment) -> T,
+    ) -> Result<T> {
+        let file_id = self.check_comment_line(file_name, line_number)?;
+        let mut thread = self
+            .comments_of(file_id)?
+            .0
+            .remove(&line_number)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|comment| comment.id == thread_id)
+            .ok_or(AuditorError::UnknownCommentId(thread_id.to_string()))?;
+        let result = update(&mut thread);
+        let tx = self.conn.savepoint()?;
+        store_thread(&tx, &thread)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// Loads the finding with the given id, or every finding if no id is given.
+    fn findings_of(&self, finding_id: Option<&str>) -> Result<Vec<Finding>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, title, severity, status, category, description, recommendation, author,
+                    created_at, updated_at
+             FROM findings WHERE ?1 IS NULL OR id = ?1 ORDER BY created_at, rowid",
+        )?;
+        let rows = statement.query_map(params![finding_id], |row| {
+            Ok((
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                Finding {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    severity: Severity::Informational,
+                    status: FindingStatus::Open,
+                    category: row.get(4)?,
+                    description: row.get(5)?,
+                    recommendation: row.get(6)?,
+                    locations: vec![],
+                    author: row.get(7)?,
+                    created_at: row.get(8)?,
+                    updated_at: row.get(9)?,
+                },
+            ))
+        })?;
+        let mut findings = vec![];
+        for row in rows {
+            let (severity, status, mut finding) = row?;
+            finding.severity = serde_json::from_value(serde_json::Value::String(severity))?;
+            finding.status = serde_json::from_value(serde_json::Value::String(status))?;
+            findings.push(finding);
+        }
+
+        let mut statement = self.conn.prepare(
+            "SELECT file_name, start_line, end_line FROM finding_locations
+             WHERE finding_id = ?1 ORDER BY rowid",
+        )?;
+        for finding in findings.iter_mut() {
+            let rows = statement.query_map(params![finding.id], |row| {
+                Ok(Location {
+                    file_name: row.get(0)?,
+                    lines: RangeInclusive::new(row.get(1)?, row.get(2)?),
+                })
+            })?;
+            finding.locations = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        }
+        Ok(findings)
+    }
+
+    fn metadata_of(&self, file_id: i64) -> Result<Option<Metadata>> {
+        let metadata = self
+            .conn
+            .query_row(
+                "SELECT priority, reviewer, note FROM metadata WHERE file_id = ?1",
+                params![file_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        match metadata {
+            Some((priority, reviewer, note)) => Ok(Some(Metadata {
+                priority: serde_json::from_value::<Priority>(serde_json::Value::String(priority))?,
+                reviewer,
+                note,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn aliases_of(&self, file_id: i64) -> Result<Vec<String>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT alias FROM aliases WHERE file_id = ?1 ORDER BY rowid")?;
+        let aliases = statement
+            .query_map(params![file_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(aliases)
+    }
+
+    fn check_comment_line(&self, file_name: &str, line_number: usize) -> Result<i64> {
+        let file_id = self.existing_file_id(file_name)?;
+        let count: usize = self.conn.query_row(
+            "SELECT COUNT(*) FROM comments WHERE file_id = ?1 AND line_number = ?2",
+            params![file_id, line_number],
+            |row| row.get(0),
+        )?;
+        if count == 0 {
+            return Err(
+                AuditorError::UnknownLinenumberInFile(line_number, file_name.to_string()).into(),
+            );
+        }
+        Ok(file_id)
+    }
+}
+
+impl Storage for SqliteDB {
+    fn latest_reviewed_commit(&self, file_name: &str) -> Result<Option<String>> {
+        let commit: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT latest_reviewed_commit FROM files WHERE file_name = ?1",
+                params![file_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(commit.filter(|commit| !commit.is_empty()))
+    }
+
+    fn reviewed_commits(&self) -> Result<Vec<String>> {
+        let mut statement = self.conn.prepare(
+            "SELECT DISTINCT latest_reviewed_commit FROM files
+             WHERE latest_reviewed_commit != '' ORDER BY latest_reviewed_commit",
+        )?;
+        let commits = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(commits)
+    }
+
+    fn reviewed_commits_of_file(&self, file_name: &str) -> Result<Vec<String>> {
+        let mut statement = self.conn.prepare(
+            "SELECT commits.commit_hash FROM commits
+             JOIN files ON commits.file_id = files.id WHERE files.file_name = ?1",
+        )?;
+        let commits = statement
+            .query_map(params![file_name], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(commits)
+    }
+
+    fn review_status_of_file_at(
+        &self,
+        file_name: &str,
+        commit: &str,
+    ) -> Result<Option<StoredReviewForFile>> {
+        let found: Option<(i64, usize)> = self
+            .conn
+            .query_row(
+                "SELECT commits.id, commits.total_lines FROM commits
+                 JOIN files ON commits.file_id = files.id
+                 WHERE files.file_name = ?1 AND commits.commit_hash = ?2",
+                params![file_name, commit],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        match found {
+            Some((commit_id, total_lines)) => {
+                Ok(Some(load_review(&self.conn, commit_id, total_lines)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn review_status_of_commit(&self, commit: &Option<String>) -> Result<StoredReviewForCommit> {
+        let mut commit_reviews = StoredReviewForCommit::new(vec![]);
+        if let Some(commit) = commit {
+            let mut statement = self.conn.prepare(
+                "SELECT files.file_name, commits.id, commits.total_lines FROM files
+                 JOIN commits ON commits.file_id = files.id
+                 AND commits.commit_hash = files.latest_reviewed_commit
+                 WHERE files.latest_reviewed_commit = ?1",
+            )?;
+            let rows = statement.query_map(params![commit], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, usize>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (file_name, commit_id, total_lines) = row?;
+                commit_reviews
+                    .files
+                    .insert(file_name, load_review(&self.conn, commit_id, total_lines)?);
+            }
+        }
+        Ok(commit_reviews)
+    }
+
+    fn store_review_status(&mut self, commit: &str, state: &StoredReviewForCommit) -> Result<()> {
+        self.begin()?;
+        let tx = self.conn.savepoint()?;
+        let commit = commit.to_string();
+        for (file_name, review) in &state.files {
+            let file_id = ensure_file(&tx, file_name)?;
+            tx.execute(
+                "UPDATE files SET latest_reviewed_commit = ?1, total_lines = ?2 WHERE id = ?3",
+                params![commit, review.total_lines, file_id],
+            )?;
+            store_review(&tx, file_id, &commit, review)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn rename_file(&mut self, old_file_name: &str, new_file_name: &str) -> Result<()> {
+        self.begin()?;
+        let tx = self.conn.savepoint()?;
+        if let Some(old_id) = file_id(&tx, old_file_name)? {
+            tx.execute(
+                "DELETE FROM files WHERE file_name = ?1",
+                params![new_file_name],
+            )?;
+            tx.execute(
+                "UPDATE files SET file_name = ?1 WHERE id = ?2",
+                params![new_file_name, old_id],
+            )?;
+            tx.execute(
+                "DELETE FROM aliases WHERE file_id = ?1 AND alias = ?2",
+                params![old_id, new_file_name],
+            )?;
+            store_alias(&tx, old_id, old_file_name)?;
+            // The file name is part of the stored event as well
+            let events = {
+                let mut statement = tx.prepare("SELECT event FROM events WHERE file_name = ?1")?;
+                let rows = statement.query_map(params![old_file_name], |row| row.get(0))?;
+                rows.collect::<rusqlite::Result<Vec<String>>>()?
+            };
+            for event in events {
+                let mut event: Event = serde_json::from_str(&event)?;
+                event.file_name = new_file_name.to_string();
+                tx.execute(
+                    "UPDATE events SET file_name = ?1, event = ?2 WHERE id = ?3",
+                    params![event.file_name, serde_json::to_string(&event)?, event.id],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn add_alias(&mut self, file_name: &str, alias: &str) -> Result<()> {
+        self.begin()?;
+        if let Some(file_id) = file_id(&self.conn, file_name)? {
+            store_alias(&self.conn, file_id, alias)?;
+        }
+        Ok(())
+    }
+
+    fn add_new_comment(
+        &mut self,
+        file_name: String,
+        line_number: usize,
+        body: String,
+        author: String,
+    ) -> Result<String> {
+        self.begin()?;
+        let id = Uuid::new_v4().to_string();
+        let file_id = ensure_file(&self.conn, &file_name)?;
+        let tx = self.conn.savepoint()?;
+        tx.execute(
+            "INSERT INTO comments (id, file_id, line_number, body, author)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, file_id, line_number, body, author],
+        )?;
+        store_thread(&tx, &Comment::new(id.clone(), body, author))?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn delete_comment(
+        &mut self,
+        file_name: String,
+        comment_id: String,
+        line_number: usize,
+    ) -> Result<()> {
+        self.begin()?;
+        let file_id = self.check_comment_line(&file_name, line_number)?;
+        delete_edits(&self.conn, &comment_id)?;
+        let deleted = self.conn.execute(
+            "DELETE FROM comments WHERE id = ?1 AND file_id = ?2 AND line_number = ?3",
+            params![comment_id, file_id, line_number],
+        )?;
+        // Replies are deleted on their own, deleting a thread deletes its replies
+        let deleted = deleted
+            + self.conn.execute(
+                "DELETE FROM replies WHERE id = ?1 AND comment_id IN
+                 (SELECT id FROM comments WHERE file_id = ?2 AND line_number = ?3)",
+                params![comment_id, file_id, line_number],
+            )?;
+        if deleted == 0 {
+            return Err(AuditorError::UnknownCommentId(comment_id).into());
+        }
+        Ok(())
+    }
+
+    fn add_reply(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        body: String,
+        author: String,
+    ) -> Result<String> {
+        self.begin()?;
+        self.update_thread(file_name, line_number, thread_id, |thread| {
+            let reply = Reply::new(Uuid::new_v4().to_string(), body, author);
+            let id = reply.id.clone();
+            thread.updated_at = reply.created_at;
+            thread.replies.push(reply);
+            id
+        })
+    }
+
+    fn set_thread_state(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        state: ThreadState,
+        user: Option<String>,
+    ) -> Result<()> {
+        self.begin()?;
+        self.update_thread(file_name, line_number, thread_id, |thread| {
+            thread.set_state(state, user)
+        })
+    }
+
+    fn update_comment(
+        &mut self,
+        file_name: String,
+        comment_id: String,
+        line_number: usize,
+        body: String,
+        editor: String,
+        force: bool,
+    ) -> Result<()> {
+        self.begin()?;
+        let file_id = self.check_comment_line(&file_name, line_number)?;
+        let threads = self
+            .comments_of(file_id)?
+            .0
+            .remove(&line_number)
+            .unwrap_or_default();
+        for mut thread in threads {
+            if thread.edit(&comment_id, body.clone(), editor.clone(), force)? {
+                let tx = self.conn.savepoint()?;
+                store_thread(&tx, &thread)?;
+                tx.commit()?;
+                return Ok(());
+            }
+        }
+        Err(AuditorError::UnknownCommentId(comment_id).into())
+    }
+
+    fn transform_comments(
+        &mut self,
+        file_name: &str,
+        line_map: &LineMap,
+        total_lines: Option<usize>,
+    ) -> Result<()> {
+        self.begin()?;
+        let Some(file_id) = file_id(&self.conn, file_name)? else {
+            return Ok(());
+        };
+        let tx = self.conn.savepoint()?;
+        let moves: Vec<(String, usize)> = {
+            let mut statement =
+                tx.prepare("SELECT id, line_number FROM comments WHERE file_id = ?1")?;
+            let rows = statement.query_map(params![file_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        for (comment_id, line_number) in moves {
+            let mut line_number = line_map.map_line_nearest(line_number);
+            if let Some(total_lines) = total_lines {
+                line_number = std::cmp::min(line_number, total_lines.saturating_sub(1));
+            }
+            tx.execute(
+                "UPDATE comments SET line_number = ?1 WHERE id = ?2",
+                params![line_number, comment_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_file_comments(&self, file_name: &str) -> Result<Option<FileComments>> {
+        match file_id(&self.conn, file_name)? {
+            Some(file_id) => Ok(Some(self.comments_of(file_id)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_comments(&mut self, file_name: &str, comments: FileComments) -> Result<()> {
+        self.begin()?;
+        let tx = self.conn.savepoint()?;
+        let file_id = ensure_file(&tx, file_name)?;
+        tx.execute(
+            &format!("DELETE FROM comment_edits WHERE {EDITS_OF_FILE}"),
+            params![file_id],
+        )?;
+        tx.execute("DELETE FROM comments WHERE file_id = ?1", params![file_id])?;
+        store_comments(&tx, file_id, &comments)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_metadata(&self, file_name: &str) -> Result<Option<Metadata>> {
+        match file_id(&self.conn, file_name)? {
+            Some(file_id) => self.metadata_of(file_id),
+            None => Ok(None),
+        }
+    }
+
+    fn set_metadata(&mut self, file_name: &str, metadata: Metadata) -> Result<()> {
+        self.begin()?;
+        let file_id = self.existing_file_id(file_name)?;
+        store_metadata(&self.conn, file_id, &metadata)
+    }
+
+    fn clear_metadata(&mut self, file_name: &str) -> Result<()> {
+        self.begin()?;
+        if let Some(file_id) = file_id(&self.conn, file_name)? {
+            self.conn
+                .execute("DELETE FROM metadata WHERE file_id = ?1", params![file_id])?;
+        }
+        Ok(())
+    }
+
+    fn record_event(&mut self, event: Event) -> Result<()> {
+        self.begin()?;
+        store_event(&self.conn, &event)
+    }
+
+    fn events_of_file(&self, file_name: &str) -> Result<Vec<Event>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT event FROM events WHERE file_name = ?1 ORDER BY rowid")?;
+        let rows = statement.query_map(params![file_name], |row| row.get::<_, String>(0))?;
+        let mut events = vec![];
+        for row in rows {
+            events.push(serde_json::from_str(&row?)?);
+        }
+        Ok(events)
+    }
+
+    fn find_event(&self, event_id: &str) -> Result<Option<Event>> {
+        let event: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT event FROM events WHERE id = ?1",
+                params![event_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(match event {
+            Some(event) => Some(serde_json::from_str(&event)?),
+            None => None,
+        })
+    }
+
+    fn latest_infos(&self) -> Result<Vec<LatestFileInfo>> {
+        let files: Vec<(i64, String, String)> = {
+            let mut statement = self
+                .conn
+                .prepare("SELECT id, file_name, latest_reviewed_commit FROM files")?;
+            let rows =
+                statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let mut infos = vec![];
+        for (file_id, file_name, latest_reviewed_commit) in files {
+            let line_reviews = self
+                .review_status_of_file_at(&file_name, &latest_reviewed_commit)?
+                .unwrap_or(StoredReviewForFile::default());
+            let comments = self.comments_of(file_id)?;
+            let (open_threads, resolved_threads) = comments.thread_counts();
+            infos.push(LatestFileInfo {
+                reviewer_coverage: line_reviews.reviewer_coverage(),
+                line_reviews,
+                comments: comments.0,
+                open_threads,
+                resolved_threads,
+                metadata: self.metadata_of(file_id)?,
+                aliases: self.aliases_of(file_id)?,
+                policy_status: None,
+                scope: None,
+                file_name,
+            });
+        }
+        Ok(infos)
+    }
+
+    fn get_findings(&self) -> Result<Vec<Finding>> {
+        self.findings_of(None)
+    }
+
+    fn get_finding(&self, finding_id: &str) -> Result<Option<Finding>> {
+        Ok(self.findings_of(Some(finding_id))?.pop())
+    }
+
+    fn store_finding(&mut self, finding: Finding) -> Result<()> {
+        self.begin()?;
+        let tx = self.conn.savepoint()?;
+        store_finding(&tx, &finding)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_finding(&mut self, finding_id: &str) -> Result<()> {
+        self.begin()?;
+        let deleted = self
+            .conn
+            .execute("DELETE FROM findings WHERE id = ?1", params![finding_id])?;
+        if deleted == 0 {
+            return Err(AuditorError::UnknownFindingId(finding_id.to_string()).into());
+        }
+        Ok(())
+    }
+
+    fn get_sessions(&self) -> Result<Vec<Session>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT session FROM sessions ORDER BY created_at, rowid")?;
+        let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+        let mut sessions = vec![];
+        for session in rows {
+            sessions.push(serde_json::from_str(&session?)?);
+        }
+        Ok(sessions)
+    }
+
+    fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
+        let session: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT session FROM sessions WHERE id = ?1",
+                params![session_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(match session {
+            Some(session) => Some(serde_json::from_str(&session)?),
+            None => None,
+        })
+    }
+
+    fn store_session(&mut self, session: Session) -> Result<()> {
+        self.begin()?;
+        store_session(&self.conn, &session)
+    }
+
+    fn get_users(&self) -> Result<Vec<User>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT name, created_at, role FROM users ORDER BY created_at, rowid")?;
+        let users = statement
+            .query_map([], user_from_row)?
+            .collect::<rusqlite::Result<Vec<User>>>()?;
+        Ok(users)
+    }
+
+    fn store_user(&mut self, user: User) -> Result<()> {
+        self.begin()?;
+        store_user(&self.conn, &user)
+    }
+
+    fn get_tokens(&self) -> Result<Vec<ApiToken>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, user_name, token_hash, created_at FROM api_tokens
+             ORDER BY created_at, rowid",
+        )?;
+        let tokens = statement
+            .query_map([], |row| {
+                Ok(ApiToken {
+                    id: row.get(0)?,
+                    user: row.get(1)?,
+                    token_hash: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<ApiToken>>>()?;
+        Ok(tokens)
+    }
+
+    fn store_token(&mut self, token: ApiToken) -> Result<()> {
+        self.begin()?;
+        store_token(&self.conn, &token)
+    }
+
+    fn user_of_token(&self, token_hash: &str) -> Result<Option<User>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT users.name, users.created_at, users.role FROM api_tokens
+                 JOIN users ON users.name = api_tokens.user_name
+                 WHERE api_tokens.token_hash = ?1",
+                params![token_hash],
+                user_from_row,
+            )
+            .optional()?)
+    }
+
+    fn delete_token(&mut self, token_id: &str) -> Result<()> {
+        self.begin()?;
+        let deleted = self
+            .conn
+            .execute("DELETE FROM api_tokens WHERE id = ?1", params![token_id])?;
+        if deleted == 0 {
+            return Err(AuditorError::UnknownTokenId(token_id.to_string()).into());
+        }
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<()> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+
+    fn save_file(&mut self, _file_name: &str) -> Result<()> {
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::*;
+
+    fn temp_dir() -> String {
+        let path = std::env::temp_dir().join(format!("auditor-sqlite-{}", Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_read_write_scenarios() {
+        let commit = "commit1".to_string();
+        let file1 = "file1.cpp".to_string();
+        let file2 = "file2.cpp".to_string();
+        let mut file_reviews = HashMap::default();
+        file_reviews.insert(
+            file1.clone(),
+            StoredReviewForFile {
+                reviewed: vec![RangeInclusive::new(0, 0), RangeInclusive::new(4, 6)],
+                modified: vec![RangeInclusive::new(1, 1)],
+                ignored: vec![RangeInclusive::new(2, 3)],
+                total_lines: 7,
+                reviewers: HashMap::from([
+                    ("alice".to_string(), vec![RangeInclusive::new(4, 6)]),
+                    ("bob".to_string(), vec![RangeInclusive::new(0, 0)]),
+                ]),
+            },
+        );
+        let state = &StoredReviewForCommit {
+            files: file_reviews,
+            exclusions: vec![],
+        };
+
+        let path = temp_dir();
+        let mut db = SqliteDB::new(&path).unwrap();
+        db.store_review_status(&commit, state).unwrap();
+        db.save().unwrap();
+        let mut db = SqliteDB::new(&path).unwrap();
+
+        assert_eq!(
+            db.latest_reviewed_commit(&file1).unwrap(),
+            Some(commit.clone())
+        );
+        assert_eq!(db.latest_reviewed_commit(&file2).unwrap(), None);
+        let retrieved_state = db.review_status_of_commit(&Some(commit.clone())).unwrap();
+        assert!(!retrieved_state.files.contains_key(&file2));
+        assert_eq!(
+            state.files.get(&file1).unwrap(),
+            retrieved_state.files.get(&file1).unwrap()
+        );
+
+        let id = db
+            .add_new_comment(file1.clone(), 4, "body".to_string(), "alice".to_string())
+            .unwrap();
+        db.update_comment(
+            file1.clone(),
+            id.clone(),
+            4,
+            "new body".to_string(),
+            "alice".to_string(),
+            false,
+        )
+        .unwrap();
+        assert!(db
+            .update_comment(
+                file1.clone(),
+                id.clone(),
+                4,
+                "bob's body".to_string(),
+                "bob".to_string(),
+                false,
+            )
+            .is_err());
+        let comment = &db.get_file_comments(&file1).unwrap().unwrap().0[&4][0];
+        assert_eq!(comment.body, "new body");
+        assert_eq!(comment.edits.len(), 1);
+        assert_eq!(comment.edits[0].body, "body");
+        assert_eq!(comment.edits[0].editor, "alice");
+        let reply = db
+            .add_reply(&file1, 4, &id, "reply".to_string(), "bob".to_string())
+            .unwrap();
+        db.set_thread_state(
+            &file1,
+            4,
+            &id,
+            ThreadState::WontFix,
+            Some("bob".to_string()),
+        )
+        .unwrap();
+        let thread = &db.get_file_comments(&file1).unwrap().unwrap().0[&4][0];
+        assert_eq!(thread.replies[0].body, "reply");
+        assert_eq!(thread.state, ThreadState::WontFix);
+        assert_eq!(thread.resolved_by, Some("bob".to_string()));
+        db.update_comment(
+            file1.clone(),
+            reply.clone(),
+            4,
+            "edited reply".to_string(),
+            "alice".to_string(),
+            true,
+        )
+        .unwrap();
+        let thread = &db.get_file_comments(&file1).unwrap().unwrap().0[&4][0];
+        assert_eq!(thread.replies[0].body, "edited reply");
+        assert_eq!(thread.replies[0].edits[0].body, "reply");
+        assert_eq!(thread.edits.len(), 1);
+        db.delete_comment(file1.clone(), reply, 4).unwrap();
+        assert!(db.get_file_comments(&file1).unwrap().unwrap().0[&4][0]
+            .replies
+            .is_empty());
+        assert!(db
+            .delete_comment(file1.clone(), "unknown".to_string(), 4)
+            .is_err());
+
+        let event = Event::new(&file1, None, crate::history::Action::AddComment, vec![]);
+        db.record_event(event.clone()).unwrap();
+        db.rename_file(&file1, &file2).unwrap();
+        assert_eq!(db.latest_reviewed_commit(&file1).unwrap(), None);
+        assert!(db.events_of_file(&file1).unwrap().is_empty());
+        assert_eq!(db.events_of_file(&file2).unwrap().len(), 1);
+        let renamed = db.find_event(&event.id).unwrap().unwrap();
+        assert_eq!(renamed.file_name, file2);
+        let infos = db.latest_infos().unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].file_name, file2);
+        assert_eq!(infos[0].aliases, vec![file1.clone()]);
+        assert_eq!(&infos[0].line_reviews, state.files.get(&file1).unwrap());
+        assert_eq!((infos[0].open_threads, infos[0].resolved_threads), (0, 1));
+
+        db.delete_comment(file2.clone(), id, 4).unwrap();
+        assert!(db.get_file_comments(&file2).unwrap().unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_unsaved_changes_are_rolled_back() {
+        let file_name = "file.cpp".to_string();
+        let state = StoredReviewForCommit {
+            files: HashMap::from([(
+                file_name.clone(),
+                StoredReviewForFile {
+                    reviewed: vec![RangeInclusive::new(0, 1)],
+                    modified: vec![],
+                    ignored: vec![],
+                    total_lines: 2,
+                    reviewers: HashMap::default(),
+                },
+            )]),
+            exclusions: vec![],
+        };
+        let path = temp_dir();
+
+        // Dropped before the event was recorded
+        let mut db = SqliteDB::new(&path).unwrap();
+        db.store_review_status("commit1", &state).unwrap();
+        assert!(db.latest_reviewed_commit(&file_name).unwrap().is_some());
+        drop(db);
+        let mut db = SqliteDB::new(&path).unwrap();
+        assert_eq!(db.latest_reviewed_commit(&file_name).unwrap(), None);
+
+        db.store_review_status("commit1", &state).unwrap();
+        db.add_new_comment(file_name.clone(), 1, "body".to_string(), "bob".to_string())
+            .unwrap();
+        db.save_file(&file_name).unwrap();
+        let db = SqliteDB::new(&path).unwrap();
+        assert_eq!(
+            db.latest_reviewed_commit(&file_name).unwrap(),
+            Some("commit1".to_string())
+        );
+        assert_eq!(
+            db.get_file_comments(&file_name).unwrap().unwrap().0.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_migrate_from_json() {
+        let path = temp_dir();
+        let file_name = "src/file.go".to_string();
+        let mut json_db = DB::new(path.clone()).unwrap();
+        let mut files = HashMap::default();
+        files.insert(
+            file_name.clone(),
+            StoredReviewForFile {
+                reviewed: vec![RangeInclusive::new(0, 3)],
+                modified: vec![],
+                ignored: vec![],
+                total_lines: 4,
+                reviewers: HashMap::default(),
+            },
+        );
+        let state = StoredReviewForCommit {
+            files,
+            exclusions: vec![],
+        };
+        json_db.store_review_status("commit1", &state).unwrap();
+        json_db
+            .add_new_comment(file_name.clone(), 2, "body".to_string(), "bob".to_string())
+            .unwrap();
+        json_db
+            .set_metadata(
+                &file_name,
+                Metadata {
+                    priority: Priority::High,
+                    reviewer: "bob".to_string(),
+                    note: "entry point".to_string(),
+                },
+            )
+            .unwrap();
+
+        json_db
+            .store_session(Session {
+                id: "session1".to_string(),
+                base: "commit0".to_string(),
+                head: "commit1".to_string(),
+                author: Some("bob".to_string()),
+                created_at: 1,
+                files: HashMap::from([(file_name.clone(), vec![RangeInclusive::new(1, 2)])]),
+            })
+            .unwrap();
+
+        let mut db = SqliteDB::new(&path).unwrap();
+        assert_eq!(db.migrate_from_json(&json_db).unwrap(), 1);
+        assert_eq!(db.get_sessions().unwrap(), json_db.get_sessions().unwrap());
+        assert_eq!(
+            db.get_session("session1").unwrap(),
+            json_db.get_session("session1").unwrap()
+        );
+        let infos = db.latest_infos().unwrap();
+        let expected = json_db.latest_infos().unwrap();
+        assert_eq!(
+            serde_json::to_value(&infos).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    auth::{ApiToken, User},
+    config::Config,
+    db::DB,
+    errors::AuditorError,
+    finding::Finding,
+    history::Event,
+    line_map::LineMap,
+    session::Session,
+    sqlite::SqliteDB,
+    FileComments, LatestFileInfo, Metadata, StoredReviewForCommit, StoredReviewForFile,
+    ThreadState,
+};
+use anyhow::Result;
+
+/// Operations that every backend storing reviews, comments and metadata supports.
+///
+/// Changes may be kept in memory until `save` or `save_file` is called.
+pub trait Storage: Send {
+    fn latest_reviewed_commit(&self, file_name: &str) -> Result<Option<String>>;
+
+    /// Lists the distinct commits at which files were last reviewed.
+    fn reviewed_commits(&self) -> Result<Vec<String>>;
+
+    /// Lists all commits at which reviews of the file were stored.
+    fn reviewed_commits_of_file(&self, file_name: &str) -> Result<Vec<String>>;
+
+    fn review_status_of_file_at(
+        &self,
+        file_name: &str,
+        commit: &str,
+    ) -> Result<Option<StoredReviewForFile>>;
+
+    /// Returns the reviews of all files that were last reviewed at `commit`.
+    fn review_status_of_commit(&self, commit: &Option<String>) -> Result<StoredReviewForCommit>;
+
+    fn store_review_status(&mut self, commit: &str, state: &StoredReviewForCommit) -> Result<()>;
+
+    /// Moves the reviews, comments, metadata and events of a file to its new path.
+    fn rename_file(&mut self, old_file_name: &str, new_file_name: &str) -> Result<()>;
+
+    fn add_alias(&mut self, file_name: &str, alias: &str) -> Result<()>;
+
+    fn add_new_comment(
+        &mut self,
+        file_name: String,
+        line_number: usize,
+        body: String,
+        author: String,
+    ) -> Result<String>;
+
+    fn delete_comment(
+        &mut self,
+        file_name: String,
+        comment_id: String,
+        line_number: usize,
+    ) -> Result<()>;
+
+    /// Edits a comment or a reply and keeps its previous body. Edits of comments written by
+    /// someone else than `editor` are rejected unless `force` is set.
+    fn update_comment(
+        &mut self,
+        file_name: String,
+        comment_id: String,
+        line_number: usize,
+        body: String,
+        editor: String,
+        force: bool,
+    ) -> Result<()>;
+
+    /// Adds a reply to the thread started by the comment `thread_id`.
+    fn add_reply(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        body: String,
+        author: String,
+    ) -> Result<String>;
+
+    fn set_thread_state(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        thread_id: &str,
+        state: ThreadState,
+        user: Option<String>,
+    ) -> Result<()>;
+
+    /// Moves the comments of a file along with the lines they are attached to.
+    /// Comments on deleted lines are attached to the line where the deletion happened.
+    fn transform_comments(
+        &mut self,
+        file_name: &str,
+        line_map: &LineMap,
+        total_lines: Option<usize>,
+    ) -> Result<()>;
+
+    fn get_file_comments(&self, file_name: &str) -> Result<Option<FileComments>>;
+
+    /// Replaces all comments of the file.
+    fn set_comments(&mut self, file_name: &str, comments: FileComments) -> Result<()>;
+
+    fn get_metadata(&self, file_name: &str) -> Result<Option<Metadata>>;
+
+    fn set_metadata(&mut self, file_name: &str, metadata: Metadata) -> Result<()>;
+
+    fn clear_metadata(&mut self, file_name: &str) -> Result<()>;
+
+    /// Appends the event to the history. Events are never changed or removed afterwards, only
+    /// renaming their file moves them to its new path.
+    fn record_event(&mut self, event: Event) -> Result<()>;
+
+    /// Lists the events of the file, oldest first.
+    fn events_of_file(&self, file_name: &str) -> Result<Vec<Event>>;
+
+    fn find_event(&self, event_id: &str) -> Result<Option<Event>>;
+
+    /// Returns the latest reviews, comments and metadata of every file.
+    fn latest_infos(&self) -> Result<Vec<LatestFileInfo>>;
+
+    /// Lists every finding, oldest first.
+    fn get_findings(&self) -> Result<Vec<Finding>>;
+
+    fn get_finding(&self, finding_id: &str) -> Result<Option<Finding>>;
+
+    /// Adds the finding or replaces the one with the same id.
+    fn store_finding(&mut self, finding: Finding) -> Result<()>;
+
+    fn delete_finding(&mut self, finding_id: &str) -> Result<()>;
+
+    /// Lists every diff audit session, oldest first.
+    fn get_sessions(&self) -> Result<Vec<Session>>;
+
+    fn get_session(&self, session_id: &str) -> Result<Option<Session>>;
+
+    /// Adds the session or replaces the one with the same id.
+    fn store_session(&mut self, session: Session) -> Result<()>;
+
+    /// Lists every user, oldest first.
+    fn get_users(&self) -> Result<Vec<User>>;
+
+    /// Adds the user or replaces the one with the same name.
+    fn store_user(&mut self, user: User) -> Result<()>;
+
+    fn get_tokens(&self) -> Result<Vec<ApiToken>>;
+
+    fn store_token(&mut self, token: ApiToken) -> Result<()>;
+
+    /// Returns the user that the token with the hash belongs to.
+    fn user_of_token(&self, token_hash: &str) -> Result<Option<User>>;
+
+    fn delete_token(&mut self, token_id: &str) -> Result<()>;
+
+    fn save(&mut self) -> Result<()>;
+
+    fn save_file(&mut self, file_name: &str) -> Result<()>;
+}
+
+/// Opens the backend selected in the config. The JSON backend only loads `file_name` if given.
+pub fn open_storage(config: &Config, file_name: Option<&String>) -> Result<Box<dyn Storage>> {
+    match config.db_backend.as_str() {
+        "json" => Ok(match file_name {
+            Some(file_name) => Box::new(DB::new_single_file(config.db_path.clone(), file_name)?),
+            None => Box::new(DB::new(config.db_path.clone())?),
+        }),
+        "sqlite" => Ok(Box::new(SqliteDB::new(&config.db_path)?)),
+        backend => Err(AuditorError::UnknownBackend(backend.to_string()).into()),
+    }
+}
+
+/// Opens the backend selected in the config without loading the files of the JSON backend, for
+/// requests that only read or change findings or accounts.
+pub fn open_findings_storage(config: &Config) -> Result<Box<dyn Storage>> {
+    match config.db_backend.as_str() {
+        "json" => Ok(Box::new(DB::new_without_files(config.db_path.clone())?)),
+        _ => open_storage(config, None),
+    }
+}
@@ -1,9 +1,12 @@
 use std::collections::HashSet;
 
 use crate::{
-    Filters, LatestFileInfo, LatestFileInfos, Metadata, Priority, PriorityBF, StoredReviewForFile,
-    UpdateMetadataRequest,
+    ApiError, Change, Filters, Finding, FindingStatus, LatestFileInfo, LatestFileInfos, Metadata,
+    PolicyStatus, Priority, PriorityBF, Severity, StoredReviewForFile, ThreadState,
+    UpdateCommentRequest, UpdateMetadataRequest,
 };
+use futures::StreamExt;
+use gloo_net::eventsource::futures::EventSource;
 use leptos::html::{Input, Select};
 use leptos::{
     ev::{MouseEvent, SubmitEvent},
@@ -55,6 +58,8 @@ fn AccordionButton<F1, F2>(
     full_file_name: String,
     line_info: StoredReviewForFile,
     comments_count: usize,
+    open_threads: usize,
+    resolved_threads: usize,
     metadata: Option<Metadata>,
     is_first: bool,
     expanded: F1,
@@ -90,6 +95,7 @@ where
                 <div class="flex-grow text-black">{note}</div>
 
                 <div class="text-blue-500 min-w-[40px]">{format!("({comments_count})")}</div>
+                <div class="text-blue-500 min-w-[80px]">{format!("{open_threads} open / {resolved_threads} resolved")}</div>
                 <div class="text-green-500 min-w-[40px]">{line_info.percent_reviewed()}<span class="font-thin text-xs">" %"</span></div>
                 <div class="text-red-600 min-w-[40px]">{line_info.percent_modified()}<span class="font-thin text-xs">" %"</span></div>
                 <div class="text-gray-400 min-w-[40px]">{line_info.percent_ignored()}<span class="font-thin text-xs">" %"</span></div>
@@ -129,15 +135,46 @@ fn SearchBar(cx: Scope, search: RwSignal<String>) -> impl IntoView {
     }
 }
 
+// Token sent to the backend when it requires authentication, set with `AUDITOR_API_TOKEN` when
+// building the dashboard
+const API_TOKEN: Option<&str> = option_env!("AUDITOR_API_TOKEN");
+
+/// Adds the API token to the request, if there is one.
+fn authorized(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match API_TOKEN {
+        Some(token) => request.header("Authorization", format!("Bearer {token}")),
+        None => request,
+    }
+}
+
+/// Requests the files, or only those of the diff audit session in the `session` query parameter
+/// of the page.
+fn info_request() -> reqwest::RequestBuilder {
+    let request = authorized(reqwest::Client::new().get("http://localhost:3000/info"));
+    let session = window().location().search().ok().and_then(|search| {
+        search
+            .trim_start_matches('?')
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("session=").map(str::to_string))
+    });
+    match session {
+        Some(session) => request.query(&[("session", session)]),
+        None => request,
+    }
+}
+
 async fn update_metadata(update_metadata_request: &UpdateMetadataRequest) -> String {
     let client = reqwest::Client::new();
-    match client
-        .post("http://localhost:3000/metadata")
+    match authorized(client.post("http://localhost:3000/metadata"))
         .json(update_metadata_request)
         .send()
         .await
     {
-        Ok(_response) => "Saved!".to_string(),
+        Ok(response) if response.status().is_success() => "Saved!".to_string(),
+        Ok(response) => match response.json::<ApiError>().await {
+            Ok(error) => format!("Failed: {}", error.message),
+            Err(e) => e.to_string(),
+        },
         Err(e) => e.to_string(),
     }
 }
@@ -209,6 +246,104 @@ fn FileDetails(
     }
 }
 
+async fn update_comment(comment_id: &str, update_comment_request: &UpdateCommentRequest) -> String {
+    let client = reqwest::Client::new();
+    match authorized(client.put(format!("http://localhost:3000/comments/{comment_id}")))
+        .json(update_comment_request)
+        .send()
+        .await
+    {
+        Ok(response) => match response.status().as_u16() {
+            201 => "Saved!".to_string(),
+            403 => "Only the author can edit this comment".to_string(),
+            status => match response.json::<ApiError>().await {
+                Ok(error) => format!("Failed: {}", error.message),
+                Err(_) => format!("Failed: {status}"),
+            },
+        },
+        Err(e) => e.to_string(),
+    }
+}
+
+/// The body of a comment or a reply, which can be edited inline.
+#[component]
+fn EditableComment(
+    cx: Scope,
+    reviewers: Vec<String>,
+    file_name: String,
+    line_number: usize,
+    comment_id: String,
+    author: String,
+    body: String,
+    edits: usize,
+) -> impl IntoView {
+    let (body, set_body) = create_signal(cx, body);
+    let (editing, set_editing) = create_signal(cx, false);
+    let body_element: NodeRef<Input> = create_node_ref(cx);
+    let editor_element: NodeRef<Select> = create_node_ref(cx);
+    let force_element: NodeRef<Input> = create_node_ref(cx);
+
+    let update_comment_action = create_action(cx, move |request: &UpdateCommentRequest| {
+        let request = request.to_owned();
+        let comment_id = comment_id.clone();
+        async move { update_comment(&comment_id, &request).await }
+    });
+
+    // Shown once the backend accepted the edit
+    let (pending_body, set_pending_body) = create_signal(cx, String::new());
+    create_effect(cx, move |_| {
+        if update_comment_action.value().get() == Some("Saved!".to_string()) {
+            set_body(pending_body.get_untracked());
+            set_editing(false);
+        }
+    });
+
+    let on_submit = move |ev: SubmitEvent| {
+        ev.prevent_default(); // stop the page from reloading!
+
+        let request = UpdateCommentRequest {
+            file_name: file_name.clone(),
+            line_number,
+            body: body_element().expect("<input> to exist").value(),
+            author: editor_element().expect("<select> to exist").value(),
+            force: force_element().expect("<input> to exist").checked(),
+        };
+        set_pending_body(request.body.clone());
+        update_comment_action.dispatch(request);
+    };
+    let edited = if edits > 0 { "(edited)" } else { "" };
+
+    view! { cx,
+        <div class="flex flex-row gap-5">
+            <div class="min-w-[100px]">{format!("-{}:", author.clone())}</div>
+            <div class=("hidden", move || editing())>{body}</div>
+            <div class="text-gray-400" class=("hidden", move || editing())>{edited}</div>
+            <button type="button" class="text-blue-500" class=("hidden", move || editing()) on:click=move |_| set_editing(true)>"Edit"</button>
+            <form class=("hidden", move || !editing()) on:submit=on_submit>
+                <input type="text"
+                    node_ref=body_element
+                    value={body.get_untracked()}
+                    class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 p-2.5 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white dark:focus:ring-blue-500 dark:focus:border-blue-500"
+                />
+                <b>"　Editor: "</b>
+                <select name="editor" node_ref=editor_element>
+                    <option value={&author}>{author.clone()}</option>
+                    {reviewers.into_iter()
+                        .filter(|reviewer| *reviewer != author)
+                        .map(|reviewer| view! { cx, <option value={&reviewer}>{reviewer}</option>})
+                        .collect::<Vec<_>>()}
+                </select>
+                <b>"　Force: "</b>
+                <input type="checkbox" node_ref=force_element/>
+                <b>"　　"</b>
+                <input type="submit" value="Save" class="font-medium focus:ring-4 focus:ring-gray-200 dark:focus:ring-gray-800 dark:text-gray-400 hover:bg-gray-100 dark:hover:bg-gray-800"/>
+                <button type="button" on:click=move |_| set_editing(false)>"　Cancel"</button>
+            </form>
+            "　"{update_comment_action.value()}
+        </div>
+    }
+}
+
 #[component]
 fn ExpandableComment<F>(
     cx: Scope,
@@ -228,7 +363,48 @@ where
     let expanded = Signal::derive(cx, move || expanded().contains(&file_name_clone));
 
     let comments_count = file_info.comments.len();
+    let comment_file_name = file_name.clone();
+    let comment_reviewers = reviewers.clone();
+    let open_threads = file_info.open_threads;
+    let resolved_threads = file_info.resolved_threads;
     let metadata: Option<crate::Metadata> = file_info.metadata;
+    let aliases = file_info.aliases.clone();
+    let previous_paths = move || {
+        if aliases.is_empty() {
+            ().into_view(cx)
+        } else {
+            view! { cx, <p class="text-left">{format!("previously: {}", aliases.join(", "))}</p> }
+                .into_view(cx)
+        }
+    };
+    let mut reviewer_coverage: Vec<(String, usize)> =
+        file_info.reviewer_coverage.clone().into_iter().collect();
+    reviewer_coverage.sort();
+    let coverage = move || {
+        if reviewer_coverage.is_empty() {
+            ().into_view(cx)
+        } else {
+            let coverage: Vec<String> = reviewer_coverage
+                .iter()
+                .map(|(reviewer, percent)| format!("{reviewer} {percent}%"))
+                .collect();
+            view! { cx, <p class="text-left">{format!("reviewed by: {}", coverage.join(", "))}</p> }
+                .into_view(cx)
+        }
+    };
+    let policy_status = file_info.policy_status.clone();
+    let sign_off = move || match &policy_status {
+        None => ().into_view(cx),
+        Some(status) => {
+            let status = match status {
+                PolicyStatus::Satisfied => "satisfied",
+                PolicyStatus::PartiallySatisfied => "partially satisfied",
+                PolicyStatus::Violated => "violated",
+                PolicyStatus::Pending => "pending",
+            };
+            view! { cx, <p class="text-left">{format!("sign-off: {status}")}</p> }.into_view(cx)
+        }
+    };
     let display = move || {
         if file_info.comments.is_empty() {
             view! {
@@ -238,6 +414,9 @@ where
                     "No comments!"
                     </p>
                     <p class="text-left">{format!("full path: {}", &file_name_clone_2)}</p>
+                    {previous_paths.clone()}
+                    {coverage.clone()}
+                    {sign_off.clone()}
                 </div>
             }
             .into_view(cx)
@@ -253,12 +432,41 @@ where
                             <div class="min-w-[50px]">{format!("line#{}", line_number)}</div>
                             <div class="flex-grow text-left">
                                 {content.iter().map(|comment| {
+                                    let state = match comment.state {
+                                        ThreadState::Open => "",
+                                        ThreadState::Resolved => "[resolved]",
+                                        ThreadState::WontFix => "[won't fix]",
+                                    };
                                     view!{
                                         cx,
                                         <div class="flex flex-row gap-5">
-                                            <div class="min-w-[100px]">{format!("-{}:", comment.author.clone())}</div>
-                                            <div>{comment.body.clone()}</div>
+                                            <EditableComment
+                                                reviewers={comment_reviewers.clone()}
+                                                file_name={comment_file_name.clone()}
+                                                line_number
+                                                comment_id={comment.id.clone()}
+                                                author={comment.author.clone()}
+                                                body={comment.body.clone()}
+                                                edits={comment.edits.len()}
+                                            />
+                                            <div class="text-gray-400">{state}</div>
                                         </div>
+                                        {comment.replies.iter().map(|reply| {
+                                            view!{
+                                                cx,
+                                                <div class="ml-10">
+                                                    <EditableComment
+                                                        reviewers={comment_reviewers.clone()}
+                                                        file_name={comment_file_name.clone()}
+                                                        line_number
+                                                        comment_id={reply.id.clone()}
+                                                        author={reply.author.clone()}
+                                                        body={reply.body.clone()}
+                                                        edits={reply.edits.len()}
+                                                    />
+                                                </div>
+                                            }
+                                        }).collect_view(cx)}
                                     }
                                 }).collect_view(cx)}
                             </div>
@@ -272,6 +480,9 @@ where
                 <div class="flex flex-col gap-3 text-gray-500 dark:text-gray-400 text-left">
                     {contents}
                     <p>{format!("full path: {}", &file_name_clone_2)}</p>
+                    {previous_paths.clone()}
+                    {coverage.clone()}
+                    {sign_off.clone()}
                 </div>
             }
             .into_view(cx)
@@ -281,7 +492,7 @@ where
     view! {
         cx,
         <div id>
-            <AccordionButton full_file_name={file_name.clone()} line_info={file_info.line_reviews} comments_count metadata=metadata.clone() is_first expanded on_click/>
+            <AccordionButton full_file_name={file_name.clone()} line_info={file_info.coverage()} comments_count open_threads resolved_threads metadata=metadata.clone() is_first expanded on_click/>
         </div>
         <div class=("hidden", move || !expanded()) aria-labelledby={&id}>
             <FileDetails full_file_name={file_name.clone()} metadata={metadata.clone()} reviewers={reviewers.clone()}/>
@@ -345,7 +556,7 @@ fn Comments(cx: Scope, info: LatestFileInfos, reviewers: Vec<String>) -> impl In
 }
 
 #[component]
-fn FiltersView(cx: Scope, filters: RwSignal<Filters>) -> impl IntoView {
+fn FiltersView(cx: Scope, filters: RwSignal<Filters>, languages: Signal<Vec<String>>) -> impl IntoView {
     let sort_by_modified = move || filters().sort_by_modified;
     let sort_by_reviewed = move || filters().sort_by_reviewed;
     let sort_by_name = move || filters().sort_by_name;
@@ -362,14 +573,32 @@ fn FiltersView(cx: Scope, filters: RwSignal<Filters>) -> impl IntoView {
                     <input checked={move || filters().only_with_comments} on:change=move |ev| filters.update(|f| f.only_with_comments = event_target_checked(&ev)) id="comments_only" type="checkbox" value="" class={filter_checkbox_class_str}/>
                     <label for="comments_only" class={filter_label_class_str}>"Comments"</label>
                 </div>
-                <div class="flex items-center">
-                    <input checked={move || filters().only_c_files} on:change=move |ev| filters.update(|f| f.only_c_files = event_target_checked(&ev)) id="cpp" type="checkbox" value="" class={filter_checkbox_class_str}/>
-                    <label for="cpp" class={filter_label_class_str}>"C/C++"</label>
-                </div>
-                <div class="flex items-center">
-                    <input checked={move || filters().only_go_files} on:change=move |ev| filters.update(|f| f.only_go_files = event_target_checked(&ev)) id="go" type="checkbox" value="" class={filter_checkbox_class_str}/>
-                    <label for="go" class={filter_label_class_str}>"Go"</label>
-                </div>
+                {move || languages()
+                    .into_iter()
+                    .map(|language| {
+                        let id = format!("language_{language}");
+                        let checked = {
+                            let language = language.clone();
+                            move || !filters().hidden_languages.contains(&language)
+                        };
+                        let on_change = {
+                            let language = language.clone();
+                            move |ev| filters.update(|f| {
+                                if event_target_checked(&ev) {
+                                    f.hidden_languages.remove(&language);
+                                } else {
+                                    f.hidden_languages.insert(language.clone());
+                                }
+                            })
+                        };
+                        view! { cx,
+                            <div class="flex items-center">
+                                <input checked=checked on:change=on_change id={id.clone()} type="checkbox" value="" class={filter_checkbox_class_str}/>
+                                <label for={id} class={filter_label_class_str}>{language}</label>
+                            </div>
+                        }
+                    })
+                    .collect_view(cx)}
             </div>
             <div class="flex flex-row gap-5">
                 <p><b>"By reviewer"</b></p>
@@ -378,6 +607,36 @@ fn FiltersView(cx: Scope, filters: RwSignal<Filters>) -> impl IntoView {
                     <label for="unassigned" class={filter_label_class_str}>"Unassigned"</label>
                 </div>
             </div>
+            <div class="flex flex-row gap-5">
+                <p><b>"By sign-off"</b></p>
+                {[
+                    (PolicyStatus::Satisfied, "policy_satisfied", "Satisfied"),
+                    (PolicyStatus::PartiallySatisfied, "policy_partial", "Partially satisfied"),
+                    (PolicyStatus::Violated, "policy_violated", "Violated"),
+                    (PolicyStatus::Pending, "policy_pending", "Pending"),
+                ]
+                    .into_iter()
+                    .map(|(status, id, label)| {
+                        let checked = {
+                            let status = status.clone();
+                            move || !filters().hidden_policy_statuses.contains(&status)
+                        };
+                        let on_change = move |ev| filters.update(|f| {
+                            if event_target_checked(&ev) {
+                                f.hidden_policy_statuses.remove(&status);
+                            } else {
+                                f.hidden_policy_statuses.insert(status.clone());
+                            }
+                        });
+                        view! { cx,
+                            <div class="flex items-center">
+                                <input checked=checked on:change=on_change id={id} type="checkbox" value="" class={filter_checkbox_class_str}/>
+                                <label for={id} class={filter_label_class_str}>{label}</label>
+                            </div>
+                        }
+                    })
+                    .collect_view(cx)}
+            </div>
             <div class="flex flex-row gap-5">
                 <p><b>"By priority"</b></p>
                 <div class="flex items-center">
@@ -449,6 +708,154 @@ fn FiltersView(cx: Scope, filters: RwSignal<Filters>) -> impl IntoView {
     }
 }
 
+#[component]
+fn Findings(cx: Scope, findings: Vec<Finding>) -> impl IntoView {
+    if findings.is_empty() {
+        return view! { cx, <p class="text-gray-500 dark:text-gray-400">"No findings!"</p> }
+            .into_view(cx);
+    }
+    let mut findings = findings;
+    findings.sort_by(|a, b| a.severity.cmp(&b.severity));
+    findings
+        .into_iter()
+        .map(|finding| {
+            let severity = match finding.severity {
+                Severity::Critical => view! { cx, <div class="text-purple-600 min-w-[110px]">"Critical"</div> },
+                Severity::High => view! { cx, <div class="text-red-600 min-w-[110px]">"High"</div> },
+                Severity::Medium => view! { cx, <div class="text-yellow-400 min-w-[110px]">"Medium"</div> },
+                Severity::Low => view! { cx, <div class="text-green-500 min-w-[110px]">"Low"</div> },
+                Severity::Informational => view! { cx, <div class="text-blue-600 min-w-[110px]">"Informational"</div> },
+            };
+            let status = match finding.status {
+                FindingStatus::Open => "open",
+                FindingStatus::Confirmed => "confirmed",
+                FindingStatus::Fixed => "fixed",
+                FindingStatus::FalsePositive => "false positive",
+            };
+            let locations: Vec<String> = finding
+                .locations
+                .iter()
+                .map(|location| {
+                    format!(
+                        "{}#{}-{}",
+                        location.file_name,
+                        location.lines.start() + 1,
+                        location.lines.end() + 1
+                    )
+                })
+                .collect();
+            view! { cx,
+                <div class="flex flex-col gap-1 p-3 text-left text-gray-500 dark:text-gray-400 border border-gray-200 dark:border-gray-700">
+                    <div class="flex flex-row gap-5 font-medium">
+                        {severity}
+                        <div class="flex-grow text-black dark:text-gray-100">{finding.title}</div>
+                        <div class="min-w-[40px]">{finding.category.unwrap_or_default()}</div>
+                        <div class="min-w-[100px]">{status}</div>
+                    </div>
+                    <p>{finding.description}</p>
+                    <p>{format!("recommendation: {}", finding.recommendation)}</p>
+                    <p>{format!("locations: {}", locations.join(", "))}</p>
+                    <p>{format!("reported by: {}", finding.author)}</p>
+                </div>
+            }
+        })
+        .collect_view(cx)
+}
+
+/// Adds the reviewers and the languages of the files, which the filters offer.
+fn with_facets(all_info: LatestFileInfos) -> (LatestFileInfos, Vec<String>, Vec<String>) {
+    let reviewers: HashSet<_> = all_info
+        .0
+        .iter()
+        .map(|info: &LatestFileInfo| match &info.metadata {
+            Some(Metadata {
+                priority: _,
+                reviewer,
+                note: _,
+            }) => reviewer.clone(),
+            None => "Unassigned".to_string(),
+        })
+        .collect();
+
+    let mut reviewers: Vec<String> = reviewers.iter().cloned().collect();
+    reviewers.sort();
+
+    // The language facet only offers the languages that are actually reviewed
+    let languages: HashSet<_> = all_info.0.iter().map(|info| info.language()).collect();
+    let mut languages: Vec<String> = languages.into_iter().collect();
+    languages.sort();
+
+    (all_info, reviewers, languages)
+}
+
+/// Keeps the dashboard current without reloading: a changed file is fetched again and replaces
+/// its entry, findings are fetched again when one changed.
+fn subscribe_to_changes(
+    infos: Resource<(), (LatestFileInfos, Vec<String>, Vec<String>)>,
+    findings: Resource<(), Vec<Finding>>,
+) {
+    spawn_local(async move {
+        // An EventSource can't send headers, the backend also reads the token from the query
+        let url = match API_TOKEN {
+            Some(token) => format!("http://localhost:3000/changes?access_token={token}"),
+            None => "http://localhost:3000/changes".to_string(),
+        };
+        let mut source = match EventSource::new(&url) {
+            Ok(source) => source,
+            Err(err) => {
+                log::error!("cannot subscribe to changes: {err:?}");
+                return;
+            }
+        };
+        let mut changes = match source.subscribe("message") {
+            Ok(changes) => changes,
+            Err(err) => {
+                log::error!("cannot subscribe to changes: {err:?}");
+                return;
+            }
+        };
+        while let Some(Ok((_, message))) = changes.next().await {
+            let change = match message
+                .data()
+                .as_string()
+                .and_then(|data| serde_json::from_str::<Change>(&data).ok())
+            {
+                Some(change) => change,
+                None => continue,
+            };
+            match change {
+                Change::Reviews { file_name }
+                | Change::Comments { file_name }
+                | Change::Metadata { file_name } => {
+                    let request = info_request().query(&[("file_name", &file_name)]);
+                    let changed: LatestFileInfos = match request.send().await {
+                        Ok(response) => match response.json().await {
+                            Ok(changed) => changed,
+                            Err(_) => continue,
+                        },
+                        Err(_) => continue,
+                    };
+                    infos.update(|infos| {
+                        if let Some((all_info, _, _)) = infos.take() {
+                            // A file that left the scope is no longer listed
+                            let mut updated = all_info.0;
+                            updated.retain(|info| info.file_name != file_name);
+                            updated.extend(changed.0);
+                            *infos = Some(with_facets(LatestFileInfos(updated)));
+                        }
+                    });
+                }
+                Change::Findings => findings.refetch(),
+                Change::Resync => {
+                    infos.refetch();
+                    findings.refetch();
+                }
+            }
+        }
+        source.close();
+    });
+}
+
 #[component]
 fn Home(cx: Scope) -> impl IntoView {
     let filters = create_rw_signal(cx, Filters::default());
@@ -458,31 +865,36 @@ fn Home(cx: Scope) -> impl IntoView {
         cx,
         || (),
         |_| async move {
-            let request_url = "http://localhost:3000/info";
-            let response = reqwest::get(request_url).await.unwrap();
+            let response = info_request().send().await.unwrap();
 
             let all_info: LatestFileInfos = response.json().await.unwrap();
+            with_facets(all_info)
+        },
+    );
 
-            let reviewers: HashSet<_> = all_info
-                .0
-                .iter()
-                .map(|info: &LatestFileInfo| match &info.metadata {
-                    Some(Metadata {
-                        priority: _,
-                        reviewer,
-                        note: _,
-                    }) => reviewer.clone(),
-                    None => "Unassigned".to_string(),
-                })
-                .collect();
-
-            let mut reviewers: Vec<String> = reviewers.iter().cloned().collect();
-            reviewers.sort();
-
-            (all_info, reviewers)
+    let async_findings = create_resource(
+        cx,
+        || (),
+        |_| async move {
+            let request_url = "http://localhost:3000/findings";
+            let response = authorized(reqwest::Client::new().get(request_url))
+                .send()
+                .await
+                .unwrap();
+            let findings: Vec<Finding> = response.json().await.unwrap();
+            findings
         },
     );
 
+    subscribe_to_changes(asyc_comments, async_findings);
+
+    let languages = Signal::derive(cx, move || {
+        asyc_comments
+            .read(cx)
+            .map(|resource| resource.2)
+            .unwrap_or_default()
+    });
+
     let filter = move |info: LatestFileInfos| {
         let mut filtered: Vec<LatestFileInfo> = info
             .0
@@ -515,22 +927,19 @@ fn Home(cx: Scope) -> impl IntoView {
                         return false;
                     }
                 }
-                if info.line_reviews.percent_ignored() == 100 {
+                if info.coverage().percent_ignored() == 100 {
                     return false;
                 }
-                let file_name = info.file_name.clone();
                 if filters().only_with_comments && info.comments.is_empty() {
                     return false;
                 }
-                if filters().only_go_files & !file_name.ends_with("go") {
+                if filters().hidden_languages.contains(&info.language()) {
                     return false;
                 }
-                if filters().only_c_files
-                    && !(file_name.ends_with(".c")
-                        || file_name.ends_with(".cpp")
-                        || file_name.ends_with(".h"))
-                {
-                    return false;
+                if let Some(policy_status) = &info.policy_status {
+                    if filters().hidden_policy_statuses.contains(policy_status) {
+                        return false;
+                    }
                 }
                 true
             })
@@ -540,16 +949,16 @@ fn Home(cx: Scope) -> impl IntoView {
             filtered.sort_by(|a, b| a.file_name.partial_cmp(&b.file_name).unwrap());
         } else if filters().sort_by_modified {
             filtered.sort_by(|a, b| {
-                b.line_reviews
+                b.coverage()
                     .percent_modified()
-                    .partial_cmp(&a.line_reviews.percent_modified())
+                    .partial_cmp(&a.coverage().percent_modified())
                     .unwrap()
             });
         } else if filters().sort_by_reviewed {
             filtered.sort_by(|a, b| {
-                a.line_reviews
+                a.coverage()
                     .percent_reviewed()
-                    .partial_cmp(&b.line_reviews.percent_reviewed())
+                    .partial_cmp(&b.coverage().percent_reviewed())
                     .unwrap()
             });
         }
@@ -561,7 +970,7 @@ fn Home(cx: Scope) -> impl IntoView {
             <div class="container-xl  mx-auto max-w-3xl ">
                 <h2 class="p-6 text-4xl dark:text-gray-100">"Review Report"</h2>
                 <SearchBar search/>
-                <FiltersView filters />
+                <FiltersView filters languages />
                 <div class="m-5">
                     {move || match asyc_comments.read(cx) {
                         None => view! { cx, <p>"Loading..."</p> }.into_view(cx),
@@ -570,6 +979,13 @@ fn Home(cx: Scope) -> impl IntoView {
                             reviewers=resource.1/> }.into_view(cx)
                     }}
                 </div>
+                <h2 class="p-6 text-3xl dark:text-gray-100">"Findings"</h2>
+                <div class="m-5">
+                    {move || match async_findings.read(cx) {
+                        None => view! { cx, <p>"Loading..."</p> }.into_view(cx),
+                        Some(findings) => view! { cx, <Findings findings/> }.into_view(cx),
+                    }}
+                </div>
             </div>
         </div>
     }
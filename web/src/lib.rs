@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, ops::RangeInclusive, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    str::FromStr,
+};
 use bitflags::bitflags;
 
 pub mod app;
@@ -9,6 +13,36 @@ pub struct Comment {
     pub id: String,
     pub body: String,
     pub author: String,
+    #[serde(default)]
+    pub replies: Vec<Reply>,
+    #[serde(default)]
+    pub state: ThreadState,
+    #[serde(default)]
+    pub edits: Vec<CommentEdit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Reply {
+    pub id: String,
+    pub body: String,
+    pub author: String,
+    #[serde(default)]
+    pub edits: Vec<CommentEdit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CommentEdit {
+    pub body: String,
+    pub editor: String,
+    pub edited_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub enum ThreadState {
+    #[default]
+    Open,
+    Resolved,
+    WontFix,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -53,6 +87,14 @@ impl StoredReviewForFile {
 #[derive(Serialize, Deserialize, Clone)]
 struct LatestFileInfos(Vec<LatestFileInfo>);
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PolicyStatus {
+    Satisfied,
+    PartiallySatisfied,
+    Violated,
+    Pending,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum Priority {
     Unspecified,
@@ -101,19 +143,123 @@ pub struct UpdateMetadataRequest {
     pub metadata: Metadata,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Informational,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FindingStatus {
+    Open,
+    Confirmed,
+    Fixed,
+    FalsePositive,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Location {
+    pub file_name: String,
+    pub lines: RangeInclusive<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub id: String,
+    pub title: String,
+    pub severity: Severity,
+    pub status: FindingStatus,
+    pub category: Option<String>,
+    pub description: String,
+    pub recommendation: String,
+    pub locations: Vec<Location>,
+    pub author: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpdateCommentRequest {
+    pub file_name: String,
+    pub line_number: usize,
+    pub body: String,
+    pub author: String,
+    pub force: bool,
+}
+
+/// A change pushed by the service, the affected file has to be fetched again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    Reviews { file_name: String },
+    Comments { file_name: String },
+    Metadata { file_name: String },
+    Findings,
+    // Changes were missed, everything has to be fetched again
+    Resync,
+}
+
+/// Body of a failed request, `code` tells the cause apart and `message` describes it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub code: String,
+    pub message: String,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct LatestFileInfo {
     file_name: String,
     line_reviews: StoredReviewForFile,
     comments: HashMap<usize, Vec<Comment>>,
     metadata: Option<Metadata>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    reviewer_coverage: HashMap<String, usize>,
+    // Only set for files that the sign-off policy applies to
+    #[serde(default)]
+    policy_status: Option<PolicyStatus>,
+    #[serde(default)]
+    open_threads: usize,
+    // Threads that were resolved or marked as won't fix
+    #[serde(default)]
+    resolved_threads: usize,
+    // Only set in a diff audit, the changed lines that coverage is relative to
+    #[serde(default)]
+    scope: Option<Vec<RangeInclusive<usize>>>,
+}
+
+impl LatestFileInfo {
+    /// The extension of the file, which is used as its language in the filters.
+    pub fn language(&self) -> String {
+        let base_name = self.file_name.rsplit('/').next().unwrap_or_default();
+        match base_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => extension.to_string(),
+            _ => "other".to_string(),
+        }
+    }
+
+    /// The reviews to show, in a diff audit the percentages are relative to the changed lines.
+    pub fn coverage(&self) -> StoredReviewForFile {
+        let mut line_reviews = self.line_reviews.clone();
+        if let Some(scope) = &self.scope {
+            line_reviews.total_lines = scope
+                .iter()
+                .map(|range| range.end() - range.start() + 1)
+                .sum();
+        }
+        line_reviews
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Filters {
     pub only_with_comments: bool,
-    pub only_c_files: bool,
-    pub only_go_files: bool,
+    // Languages, i.e. file extensions, that were unchecked in the language facet
+    pub hidden_languages: HashSet<String>,
+    // Sign-off statuses that were unchecked, files without a policy are always shown
+    pub hidden_policy_statuses: HashSet<PolicyStatus>,
 
     pub sort_by_modified: bool,
     pub sort_by_reviewed: bool,
@@ -128,8 +274,8 @@ impl Default for Filters {
     fn default() -> Self {
         Self {
             only_with_comments: false,
-            only_c_files: true,
-            only_go_files: false,
+            hidden_languages: HashSet::default(),
+            hidden_policy_statuses: HashSet::default(),
 
             sort_by_modified: false,
             sort_by_reviewed: true,